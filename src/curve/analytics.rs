@@ -0,0 +1,301 @@
+//! Impermanent loss and liquidity provider PnL analytics
+//!
+//! A liquidity position is compared to holding the initially deposited tokens, both valued
+//! at the same price. Fees earned stay in the pool, so they are removed from the pool value
+//! before measuring the divergence loss caused by the curve.
+//!
+//! Prices are quoted in whole tokens and values are reported in raw units of token b. The
+//! reserves are valued in the common scale of the token decimals, where a whole token price
+//! applies to both sides, and converted back to raw token b.
+
+use crate::{
+    curve::{
+        decimals::TokenDecimals,
+        pool::{PoolState, SwapDirection},
+        rebalance::value_at_price,
+        simulation::SimulationStep,
+    },
+    math::{Decimal, TryAdd, TryDiv, TryMul, TrySub},
+    state::MockedSwap,
+};
+use solana_program::program_error::ProgramError;
+use std::convert::TryFrom;
+
+/// Liquidity provider value compared to holding the initial deposit
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LpPnl {
+    /// value of the initial reserves at the valuation price
+    pub hold_value: Decimal,
+    /// value of the current reserves at the valuation price, fees included
+    pub pool_value: Decimal,
+    /// value of the fees earned at the valuation price
+    pub fee_value: Decimal,
+    /// relative loss of the pool without fees against holding, zero if the pool did better
+    pub divergence_loss: Decimal,
+}
+
+impl LpPnl {
+    /// Whether the pool including fees is worth at least as much as holding
+    pub fn beats_hold(&self) -> bool {
+        self.pool_value >= self.hold_value
+    }
+}
+
+/// Compare the pool in `current` against holding the reserves of `initial`, valued at
+/// `price` of one whole token a in whole tokens b, usually the oracle price.
+/// `fee_value` is the value of the fees earned by the pool in between in raw token b, as
+/// returned by `fee_value_from_trace`.
+/// The result is pool-wide, a position of a share of the supply scales every value by it.
+pub fn lp_pnl(
+    initial: &MockedSwap,
+    current: &MockedSwap,
+    price: Decimal,
+    fee_value: Decimal,
+) -> Result<LpPnl, ProgramError> {
+    let decimals = TokenDecimals::new(current.token_a_decimals, current.token_b_decimals)?;
+    let initial = PoolState::try_from(initial)?;
+    let current = PoolState::try_from(current)?;
+    let hold_value = decimals.denormalize_b(value_at_price(
+        initial.current_reserve_a,
+        initial.current_reserve_b,
+        price,
+    )?)?;
+    let pool_value = decimals.denormalize_b(value_at_price(
+        current.current_reserve_a,
+        current.current_reserve_b,
+        price,
+    )?)?;
+
+    let value_without_fees = if pool_value > fee_value {
+        pool_value.try_sub(fee_value)?
+    } else {
+        Decimal::zero()
+    };
+    let divergence_loss = if value_without_fees < hold_value {
+        hold_value
+            .try_sub(value_without_fees)?
+            .try_div(hold_value)?
+    } else {
+        Decimal::zero()
+    };
+
+    Ok(LpPnl {
+        hold_value,
+        pool_value,
+        fee_value,
+        divergence_loss,
+    })
+}
+
+/// Value in raw token b of the liquidity provider fees charged over a simulation trace of a
+/// pool with `decimals`, at `price` of one whole token a in whole tokens b
+pub fn fee_value_from_trace(
+    trace: &[SimulationStep],
+    decimals: TokenDecimals,
+    price: Decimal,
+) -> Result<Decimal, ProgramError> {
+    let value = trace.iter().try_fold(Decimal::zero(), |total, step| {
+        let lp_fee = Decimal::from(step.fees.lp_fee);
+        // fees are charged on the output token
+        let value = match step.trade.direction {
+            SwapDirection::AtoB => lp_fee,
+            SwapDirection::BtoA => lp_fee.try_mul(price)?,
+        };
+        total.try_add(value)
+    })?;
+    // the trace is in the common scale
+    decimals.denormalize_b(value)
+}
+
+/// Divergence loss of a pool conserving `reserve_a^exp * reserve_b` when the price of
+/// token a moves by `price_ratio` from a balanced start and arbitrage follows it.
+/// Constant product pools have `exp` one, the oracle curve `market_price * target_a / target_b`.
+/// With `w = exp / (1 + exp)` the loss is `1 - price_ratio^w / (w * price_ratio + 1 - w)`.
+pub fn divergence_loss(exp: Decimal, price_ratio: Decimal) -> Result<Decimal, ProgramError> {
+    let weight_a = exp.try_div(exp.try_add(Decimal::one())?)?;
+    let weight_b = Decimal::one().try_sub(weight_a)?;
+    let pool_growth = price_ratio.try_powf(weight_a)?;
+    let hold_growth = weight_a.try_mul(price_ratio)?.try_add(weight_b)?;
+    if pool_growth >= hold_growth {
+        return Ok(Decimal::zero());
+    }
+    Decimal::one().try_sub(pool_growth.try_div(hold_growth)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::curve::{
+        fees::Fees,
+        pool::CurveType,
+        simulation::{simulate, Trade},
+    };
+
+    fn assert_close(left: Decimal, right: Decimal, tolerance: u128) {
+        let difference = if left > right {
+            left.try_sub(right).unwrap()
+        } else {
+            right.try_sub(left).unwrap()
+        };
+        assert!(
+            difference <= Decimal::from_scaled_val(tolerance),
+            "{} != {}",
+            left,
+            right
+        );
+    }
+
+    #[test]
+    fn test_constant_product_loss() {
+        let initial = MockedSwap {
+            target_reserve_a: Decimal::from(1_000_000u64),
+            target_reserve_b: Decimal::from(3_000_000u64),
+            current_reserve_a: Decimal::from(1_000_000u64),
+            current_reserve_b: Decimal::from(3_000_000u64),
+            market_price: Decimal::from(3u64),
+            ..MockedSwap::default()
+        };
+        // arbitrage after the price quadruples halves reserve a and doubles reserve b
+        let current = MockedSwap {
+            current_reserve_a: Decimal::from(500_000u64),
+            current_reserve_b: Decimal::from(6_000_000u64),
+            market_price: Decimal::from(12u64),
+            ..initial.clone()
+        };
+        let pnl = lp_pnl(&initial, &current, Decimal::from(12u64), Decimal::zero()).unwrap();
+        assert_eq!(pnl.hold_value, Decimal::from(15_000_000u64));
+        assert_eq!(pnl.pool_value, Decimal::from(12_000_000u64));
+        assert_eq!(pnl.divergence_loss, Decimal::from_percent(20));
+        assert!(!pnl.beats_hold());
+        assert_eq!(
+            divergence_loss(Decimal::one(), Decimal::from(4u64)).unwrap(),
+            pnl.divergence_loss
+        );
+
+        // no price change, no loss
+        assert_eq!(
+            divergence_loss(Decimal::one(), Decimal::one()).unwrap(),
+            Decimal::zero()
+        );
+    }
+
+    #[test]
+    fn test_oracle_exponent_loss() {
+        // exponent 3 * 1_000_000 / 1_000_000, three quarters of the value in token a
+        let initial = MockedSwap {
+            target_reserve_a: Decimal::from(1_000_000u64),
+            target_reserve_b: Decimal::from(1_000_000u64),
+            current_reserve_a: Decimal::from(1_000_000u64),
+            current_reserve_b: Decimal::from(1_000_000u64),
+            market_price: Decimal::from(3u64),
+            ..MockedSwap::default()
+        };
+        // reserves after arbitrage to four times the price: a / 4^0.25 and b * 4^0.75
+        let current = MockedSwap {
+            current_reserve_a: Decimal::from(707_107u64),
+            current_reserve_b: Decimal::from(2_828_427u64),
+            ..initial.clone()
+        };
+        let pnl = lp_pnl(&initial, &current, Decimal::from(12u64), Decimal::zero()).unwrap();
+        let expected = divergence_loss(Decimal::from(3u64), Decimal::from(4u64)).unwrap();
+        // 1 - 4^0.75 / 3.25
+        assert_close(expected, Decimal::from_scaled_val(129_714_731_000), 1_000);
+        assert_close(pnl.divergence_loss, expected, 1_000_000);
+
+        // a curve leaning towards token a loses less than constant product on its rise
+        assert!(expected < divergence_loss(Decimal::one(), Decimal::from(4u64)).unwrap());
+    }
+
+    #[test]
+    fn test_fees_offset_loss() {
+        let initial = MockedSwap {
+            target_reserve_a: Decimal::from(1_000_000u64),
+            target_reserve_b: Decimal::from(3_000_000u64),
+            current_reserve_a: Decimal::from(1_000_000u64),
+            current_reserve_b: Decimal::from(3_000_000u64),
+            market_price: Decimal::from(3u64),
+            ..MockedSwap::default()
+        };
+        let trades: Vec<Trade> = (0..10)
+            .map(|i| Trade {
+                direction: if i % 2 == 0 {
+                    SwapDirection::AtoB
+                } else {
+                    SwapDirection::BtoA
+                },
+                amount_in: if i % 2 == 0 { 20_000 } else { 60_000 },
+                has_referrer: false,
+            })
+            .collect();
+        let trace = simulate(CurveType::Oracle, Fees::default(), &initial, &trades).unwrap();
+        let last = trace.last().unwrap().pool;
+        let current = MockedSwap {
+            current_reserve_a: last.current_reserve_a,
+            current_reserve_b: last.current_reserve_b,
+            ..initial.clone()
+        };
+
+        let price = Decimal::from(3u64);
+        let fee_value = fee_value_from_trace(&trace, TokenDecimals::default(), price).unwrap();
+        assert!(fee_value > Decimal::zero());
+        let pnl = lp_pnl(&initial, &current, price, fee_value).unwrap();
+        // round trips at the oracle price earn the fees without divergence
+        assert!(pnl.beats_hold());
+        assert_eq!(pnl.divergence_loss, Decimal::zero());
+    }
+
+    #[test]
+    fn test_values_in_raw_token_b_across_decimals() {
+        // 1_000 sol(9) against 150_000 usdc(6), a sol worth 150 usdc
+        let initial = MockedSwap {
+            target_reserve_a: Decimal::from(1_000_000_000_000u64),
+            target_reserve_b: Decimal::from(150_000_000_000u64),
+            current_reserve_a: Decimal::from(1_000_000_000_000u64),
+            current_reserve_b: Decimal::from(150_000_000_000u64),
+            market_price: Decimal::from(150u64),
+            token_a_decimals: 9,
+            token_b_decimals: 6,
+            ..MockedSwap::default()
+        };
+        let decimals = TokenDecimals::new(9, 6).unwrap();
+        // sell one sol, then buy it back with 150 usdc, in the common scale of 9 decimals
+        let trades = [
+            Trade {
+                direction: SwapDirection::AtoB,
+                amount_in: 1_000_000_000,
+                has_referrer: false,
+            },
+            Trade {
+                direction: SwapDirection::BtoA,
+                amount_in: 150_000_000_000,
+                has_referrer: false,
+            },
+        ];
+        let trace = simulate(CurveType::Oracle, Fees::default(), &initial, &trades).unwrap();
+        let last = trace.last().unwrap().pool;
+        let current = MockedSwap {
+            current_reserve_a: Decimal::from(
+                decimals
+                    .denormalize_a_floor(last.current_reserve_a)
+                    .unwrap(),
+            ),
+            current_reserve_b: Decimal::from(
+                decimals
+                    .denormalize_b_floor(last.current_reserve_b)
+                    .unwrap(),
+            ),
+            ..initial.clone()
+        };
+
+        let price = Decimal::from(150u64);
+        let fee_value = fee_value_from_trace(&trace, decimals, price).unwrap();
+        // fees on 300 usdc traded are a fraction of a usdc
+        assert!(fee_value > Decimal::zero());
+        assert!(fee_value < Decimal::from(1_000_000u64));
+        let pnl = lp_pnl(&initial, &current, price, fee_value).unwrap();
+        // 300_000 usdc held, not the raw lamports times the sol price
+        assert_eq!(pnl.hold_value, Decimal::from(300_000_000_000u64));
+        assert!(pnl.pool_value > pnl.hold_value);
+        assert!(pnl.pool_value < pnl.hold_value.try_add(Decimal::from(1_000_000u64)).unwrap());
+    }
+}
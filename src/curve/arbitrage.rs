@@ -0,0 +1,260 @@
+//! Trade size moving the pool marginal price to a target price
+//!
+//! The oracle curve and weighted pools conserve `reserve_a^exp * reserve_b`, so their
+//! marginal price `exp * reserve_b / reserve_a` falls with `reserve_a^(exp + 1)`. Selling
+//! token a until the price reaches `target` therefore grows reserve a by
+//! `(spot / target)^(1 / (exp + 1))`. Other curves are solved by bisection on the input.
+
+use crate::{
+    curve::pool::{CurveType, PoolState, SwapDirection},
+    error::SwapError,
+    math::{Decimal, TryAdd, TryDiv, TryMul, TrySub},
+};
+use solana_program::program_error::ProgramError;
+
+/// Maximum number of doublings and halvings of the bisection search
+pub const MAX_ARBITRAGE_ITERATIONS: u32 = 64;
+
+/// Trade bringing the pool price to a target
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ArbitrageTrade {
+    /// direction of the trade
+    pub direction: SwapDirection,
+    /// amount sold into the pool
+    pub amount_in: u64,
+    /// curve output of the trade, before fees
+    pub amount_out: u64,
+    /// pool state after the trade
+    pub pool: PoolState,
+}
+
+/// Largest trade, before fees, that moves the marginal price of token a towards
+/// `target_price` without crossing it
+pub fn arbitrage_trade(
+    curve: &CurveType,
+    pool: &PoolState,
+    target_price: Decimal,
+) -> Result<ArbitrageTrade, ProgramError> {
+    if target_price.is_zero() {
+        return Err(SwapError::InvalidInput.into());
+    }
+    let spot_price = curve.marginal_price(pool)?;
+    let (direction, oriented_target) = if target_price < spot_price {
+        (SwapDirection::AtoB, target_price)
+    } else {
+        (SwapDirection::BtoA, target_price.reciprocal()?)
+    };
+    let oriented_pool = pool.oriented(direction)?;
+    let oriented_curve = curve.oriented(direction);
+
+    let amount_in = if target_price == spot_price {
+        0
+    } else {
+        match oriented_curve {
+            CurveType::Oracle => {
+                let exp = oriented_pool
+                    .market_price
+                    .try_mul(oriented_pool.target_reserve_a)?
+                    .try_div(oriented_pool.target_reserve_b)?;
+                closed_form_input(&oriented_curve, &oriented_pool, oriented_target, exp)?
+            }
+            CurveType::Weighted { weight_a, weight_b } => closed_form_input(
+                &oriented_curve,
+                &oriented_pool,
+                oriented_target,
+                weight_a.try_div(weight_b)?,
+            )?,
+            CurveType::Stable { .. } => {
+                bisection_input(&oriented_curve, &oriented_pool, oriented_target)?
+            }
+        }
+    };
+
+    let amount_out_decimal =
+        oriented_curve.swap_exact_in(&oriented_pool, Decimal::from(amount_in))?;
+    let amount_out = amount_out_decimal.try_floor_u64()?;
+    let amount_in_decimal = Decimal::from(amount_in);
+    let pool = match direction {
+        SwapDirection::AtoB => pool.after_swap(amount_in_decimal, amount_out_decimal)?,
+        SwapDirection::BtoA => PoolState {
+            current_reserve_a: pool.current_reserve_a.try_sub(amount_out_decimal)?,
+            current_reserve_b: pool.current_reserve_b.try_add(amount_in_decimal)?,
+            ..*pool
+        },
+    };
+
+    Ok(ArbitrageTrade {
+        direction,
+        amount_in,
+        amount_out,
+        pool,
+    })
+}
+
+/// Closed-form input of token a for curves conserving `reserve_a^exp * reserve_b`
+fn closed_form_input(
+    curve: &CurveType,
+    pool: &PoolState,
+    target_price: Decimal,
+    exp: Decimal,
+) -> Result<u64, ProgramError> {
+    let spot_price = curve.marginal_price(pool)?;
+    let growth = spot_price
+        .try_div(target_price)?
+        .try_powf(Decimal::one().try_div(exp.try_add(Decimal::one())?)?)?;
+    pool.current_reserve_a
+        .try_mul(growth.try_sub(Decimal::one())?)?
+        .try_floor_u64()
+}
+
+/// Largest input of token a whose post-trade marginal price stays at or above `target_price`,
+/// for curves whose price falls monotonically as token a is sold
+pub fn bisection_input(
+    curve: &CurveType,
+    pool: &PoolState,
+    target_price: Decimal,
+) -> Result<u64, ProgramError> {
+    let price_after = |amount_in: u64| -> Result<Decimal, ProgramError> {
+        let input = Decimal::from(amount_in);
+        let output = curve.swap_exact_in(pool, input)?;
+        curve.marginal_price(&pool.after_swap(input, output)?)
+    };
+    if price_after(0)? < target_price {
+        return Ok(0);
+    }
+
+    // grow the upper bound until the price falls below the target
+    let mut low = 0u64;
+    let mut high = pool.current_reserve_a.try_ceil_u64()?.max(1);
+    let mut iterations = 0;
+    while price_after(high)? >= target_price {
+        low = high;
+        high = high.checked_mul(2).ok_or(SwapError::CalculationFailure)?;
+        iterations += 1;
+        if iterations > MAX_ARBITRAGE_ITERATIONS {
+            return Err(SwapError::CalculationFailure.into());
+        }
+    }
+
+    for _ in 0..MAX_ARBITRAGE_ITERATIONS {
+        if high - low <= 1 {
+            return Ok(low);
+        }
+        let middle = low + (high - low) / 2;
+        if price_after(middle)? >= target_price {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    Err(SwapError::CalculationFailure.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn oracle_pool() -> PoolState {
+        PoolState {
+            market_price: Decimal::from(3u64),
+            target_reserve_a: Decimal::from(1_000_000u64),
+            target_reserve_b: Decimal::from(3_000_000u64),
+            current_reserve_a: Decimal::from(1_000_000u64),
+            current_reserve_b: Decimal::from(3_000_000u64),
+        }
+    }
+
+    /// |price - target| / target
+    fn relative_error(price: Decimal, target: Decimal) -> Decimal {
+        let difference = if price > target {
+            price.try_sub(target).unwrap()
+        } else {
+            target.try_sub(price).unwrap()
+        };
+        difference.try_div(target).unwrap()
+    }
+
+    fn assert_reaches_target(curve: &CurveType, pool: &PoolState, target: Decimal) {
+        let trade = arbitrage_trade(curve, pool, target).unwrap();
+        let price = curve.marginal_price(&trade.pool).unwrap();
+        assert!(
+            relative_error(price, target) < Decimal::from_scaled_val(100_000_000),
+            "{} != {}",
+            price,
+            target
+        );
+
+        // the floored input leaves at most one unit of the move undone
+        let oriented_pool = pool.oriented(trade.direction).unwrap();
+        let oriented_curve = curve.oriented(trade.direction);
+        let input = Decimal::from(trade.amount_in + 1);
+        let output = oriented_curve.swap_exact_in(&oriented_pool, input).unwrap();
+        let next_price = oriented_curve
+            .marginal_price(
+                &oriented_pool
+                    .after_swap(input, output)
+                    .unwrap(),
+            )
+            .unwrap();
+        let oriented_target = match trade.direction {
+            SwapDirection::AtoB => target,
+            SwapDirection::BtoA => target.reciprocal().unwrap(),
+        };
+        assert!(
+            relative_error(next_price, oriented_target) < Decimal::from_scaled_val(100_000_000)
+        );
+    }
+
+    #[test]
+    fn test_oracle_arbitrage() {
+        let pool = oracle_pool();
+        let curve = CurveType::Oracle;
+
+        // price falls to 2.5, token a is sold into the pool
+        let trade =
+            arbitrage_trade(&curve, &pool, Decimal::from_scaled_val(2_500_000_000_000)).unwrap();
+        assert_eq!(trade.direction, SwapDirection::AtoB);
+        // exponent one: reserve a grows by sqrt(3 / 2.5)
+        assert_eq!(trade.amount_in, 95_445);
+        assert_reaches_target(&curve, &pool, Decimal::from_scaled_val(2_500_000_000_000));
+
+        // price rises to 4, token b is sold into the pool
+        let trade = arbitrage_trade(&curve, &pool, Decimal::from(4u64)).unwrap();
+        assert_eq!(trade.direction, SwapDirection::BtoA);
+        assert!(trade.pool.current_reserve_a < pool.current_reserve_a);
+        assert_reaches_target(&curve, &pool, Decimal::from(4u64));
+
+        let trade = arbitrage_trade(&curve, &pool, Decimal::from(3u64)).unwrap();
+        assert_eq!(trade.amount_in, 0);
+        assert_eq!(trade.pool, pool);
+
+        assert_eq!(
+            arbitrage_trade(&curve, &pool, Decimal::zero()).unwrap_err(),
+            SwapError::InvalidInput.into()
+        );
+    }
+
+    #[test]
+    fn test_weighted_and_stable_arbitrage() {
+        let weighted = CurveType::Weighted {
+            weight_a: Decimal::from_percent(80),
+            weight_b: Decimal::from_percent(20),
+        };
+        // spot price 4 * 3_000_000 / 1_000_000
+        assert_reaches_target(&weighted, &oracle_pool(), Decimal::from(10u64));
+        assert_reaches_target(&weighted, &oracle_pool(), Decimal::from(15u64));
+
+        let stable_pool = PoolState {
+            market_price: Decimal::one(),
+            current_reserve_b: Decimal::from(1_000_000u64),
+            ..oracle_pool()
+        };
+        let stable = CurveType::Stable { amp: 100 };
+        assert_reaches_target(&stable, &stable_pool, Decimal::from_percent(99));
+        assert_reaches_target(
+            &stable,
+            &stable_pool,
+            Decimal::from_scaled_val(1_020_000_000_000),
+        );
+    }
+}
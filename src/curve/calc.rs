@@ -12,7 +12,7 @@ pub fn simple_powf(
     target_reserve_a: Decimal, target_reserve_b: Decimal, 
     current_reserve_a: Decimal, current_reserve_b: Decimal,
     input_a_amount: Decimal
-) ->Result<u64, ProgramError> {
+) ->Result<Decimal, ProgramError> {
     let core: Decimal = current_reserve_a.try_div(current_reserve_a.try_add(input_a_amount)?)?;
     let exp: Decimal = market_price.try_mul(target_reserve_a)?.try_div(target_reserve_b)?;
     // let mut core: f64 = (current_reserve_a as f64) / (current_reserve_a as f64 + input_a_amount as f64);
@@ -26,9 +26,81 @@ pub fn simple_powf(
 
     let result: Decimal = current_reserve_b.try_mul(Decimal::one().try_sub(core_exp)?)?;
 
-    Ok(result.try_floor_u64()?)
+    // whole units of the common scale, rounded down
+    Ok(Decimal::from(result.try_floor_u128()?))
 }
 
+/// marginal price of token a in token b for simple_powf, i.e. the derivative of
+/// `current_reserve_b * (1 - (current_reserve_a / (current_reserve_a + x))^exp)` at x = 0
+pub fn simple_powf_marginal_price(
+    market_price: Decimal,
+    target_reserve_a: Decimal, target_reserve_b: Decimal,
+    current_reserve_a: Decimal, current_reserve_b: Decimal,
+) -> Result<Decimal, ProgramError> {
+    let exp: Decimal = market_price.try_mul(target_reserve_a)?.try_div(target_reserve_b)?;
+    current_reserve_b.try_mul(exp)?.try_div(current_reserve_a)
+}
+
+/// input of token a required by simple_powf to receive `output_b_amount` of token b,
+/// i.e. `current_reserve_a * ((current_reserve_b / (current_reserve_b - y))^(1 / exp) - 1)`
+pub fn simple_powf_exact_out(
+    market_price: Decimal,
+    target_reserve_a: Decimal, target_reserve_b: Decimal,
+    current_reserve_a: Decimal, current_reserve_b: Decimal,
+    output_b_amount: Decimal
+) -> Result<u64, ProgramError> {
+    let core: Decimal = current_reserve_b.try_div(current_reserve_b.try_sub(output_b_amount)?)?;
+    let exp: Decimal = market_price.try_mul(target_reserve_a)?.try_div(target_reserve_b)?;
+
+    let core_float64: f64 = core.to_float64()?;
+    let exp_float64: f64 = exp.to_float64()?;
+
+    let core_exp_float64: f64 = core_float64.powf(1f64 / exp_float64);
+    let core_exp: Decimal = Decimal::from_float64(core_exp_float64);
+
+    let result: Decimal = current_reserve_a.try_mul(core_exp.try_sub(Decimal::one())?)?;
+
+    result.try_ceil_u64()
+}
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simple_powf_marginal_price() {
+        // balanced pool at the market price quotes the market price
+        let price = simple_powf_marginal_price(
+            Decimal::from(3u64),
+            Decimal::from(1_000u64), Decimal::from(3_000u64),
+            Decimal::from(1_000u64), Decimal::from(3_000u64),
+        ).unwrap();
+        assert_eq!(price, Decimal::from(3u64));
+
+        // a small trade executes close to the marginal price
+        let output = simple_powf(
+            Decimal::from(3u64),
+            Decimal::from(1_000_000u64), Decimal::from(3_000_000u64),
+            Decimal::from(1_000_000u64), Decimal::from(3_000_000u64),
+            Decimal::from(10u64),
+        ).unwrap();
+        assert_eq!(output, Decimal::from(29u64));
+    }
+
+    #[test]
+    fn test_simple_powf_exact_out() {
+        let input = simple_powf_exact_out(
+            Decimal::from(3u64),
+            Decimal::from(1_000_000u64), Decimal::from(3_000_000u64),
+            Decimal::from(1_000_000u64), Decimal::from(2_000_000u64),
+            Decimal::from(100_000u64),
+        ).unwrap();
+        let output = simple_powf(
+            Decimal::from(3u64),
+            Decimal::from(1_000_000u64), Decimal::from(3_000_000u64),
+            Decimal::from(1_000_000u64), Decimal::from(2_000_000u64),
+            Decimal::from(input),
+        ).unwrap();
+        assert!((100_000..=100_001).contains(&output.try_floor_u64().unwrap()));
+    }
+}
@@ -0,0 +1,832 @@
+//! Concentrated liquidity (tick based) curve
+//!
+//! Liquidity is provided over price ranges delimited by ticks, where tick `i` has a price
+//! of `1.0001^i` token b per token a. The pool tracks the square root of the price and the
+//! liquidity active at the current tick, and swaps step from one initialized tick to the next.
+
+use crate::{
+    curve::pool::SwapDirection,
+    error::SwapError,
+    math::{Decimal, Rate, TryAdd, TryDiv, TryMul, TrySub},
+};
+use solana_program::program_error::ProgramError;
+use std::cmp::{max, min};
+
+/// Lowest supported tick, neighbouring ticks below about -184_000 have the same price at
+/// WAD precision
+pub const MIN_TICK: i32 = -150_000;
+/// Highest supported tick
+pub const MAX_TICK: i32 = 150_000;
+/// sqrt(1.0001) scaled by WAD
+pub const SQRT_TICK_BASE: u128 = 1_000_049_998_750;
+/// Maximum number of steps a single swap can take
+pub const MAX_SWAP_STEPS: usize = 64;
+
+/// sqrt(1.0001)^(2^i) scaled by WAD, one power for each bit of a tick up to `MAX_TICK`
+const SQRT_TICK_POWERS: [u128; 18] = [
+    SQRT_TICK_BASE,
+    1_000_100_000_000,
+    1_000_200_010_000,
+    1_000_400_060_004,
+    1_000_800_280_056,
+    1_001_601_200_560,
+    1_003_204_964_963,
+    1_006_420_201_727,
+    1_012_881_622_445,
+    1_025_929_181_087,
+    1_052_530_684_607,
+    1_107_820_842_039,
+    1_227_267_018_058,
+    1_506_184_333_613,
+    2_268_591_246_822,
+    5_146_506_245_160,
+    26_486_526_531_474,
+    701_536_087_702_486,
+];
+
+/// Square root price `|tick|` ticks above one, multiplying the powers of its bits from the
+/// highest down
+fn sqrt_price_at_abs_tick(abs_tick: u32) -> Result<Decimal, ProgramError> {
+    let mut sqrt_price = Decimal::one();
+    for bit in (0..SQRT_TICK_POWERS.len()).rev() {
+        if abs_tick & (1 << bit) != 0 {
+            sqrt_price = sqrt_price.try_mul(Decimal::from_scaled_val(SQRT_TICK_POWERS[bit]))?;
+        }
+    }
+    Ok(sqrt_price)
+}
+
+/// Square root of the price at `tick`
+pub fn sqrt_price_at_tick(tick: i32) -> Result<Decimal, ProgramError> {
+    if !(MIN_TICK..=MAX_TICK).contains(&tick) {
+        return Err(SwapError::InvalidInput.into());
+    }
+    let sqrt_price = sqrt_price_at_abs_tick(tick.unsigned_abs())?;
+    if tick < 0 {
+        sqrt_price.reciprocal()
+    } else {
+        Ok(sqrt_price)
+    }
+}
+
+/// Greatest tick whose square root price is at most `sqrt_price`
+pub fn tick_at_sqrt_price(sqrt_price: Decimal) -> Result<i32, ProgramError> {
+    if sqrt_price < sqrt_price_at_tick(MIN_TICK)? || sqrt_price > sqrt_price_at_tick(MAX_TICK)? {
+        return Err(SwapError::InvalidInput.into());
+    }
+    // search the bits of the tick from the highest down, each candidate extends the partial
+    // product `sqrt_price_at_tick` computes for the bits kept so far
+    let above_one = sqrt_price >= Decimal::one();
+    let mut abs_tick = 0u32;
+    let mut partial = Decimal::one();
+    for bit in (0..SQRT_TICK_POWERS.len()).rev() {
+        let candidate = partial.try_mul(Decimal::from_scaled_val(SQRT_TICK_POWERS[bit]))?;
+        let keep = if above_one {
+            candidate <= sqrt_price
+        } else {
+            candidate.reciprocal()? > sqrt_price
+        };
+        if keep {
+            partial = candidate;
+            abs_tick |= 1 << bit;
+        }
+    }
+    // below one the search finds the last tick above `sqrt_price`, the next one is at most it
+    if above_one {
+        Ok(abs_tick as i32)
+    } else {
+        Ok(-(abs_tick as i32) - 1)
+    }
+}
+
+/// Price of token a in token b at `tick`
+pub fn price_at_tick(tick: i32) -> Result<Decimal, ProgramError> {
+    let sqrt_price = sqrt_price_at_tick(tick)?;
+    sqrt_price.try_mul(sqrt_price)
+}
+
+/// Greatest tick whose price is at most `price`
+pub fn tick_at_price(price: Decimal) -> Result<i32, ProgramError> {
+    tick_at_sqrt_price(price.sqrt()?)
+}
+
+fn to_amount(value: Decimal, round_up: bool) -> Result<u64, ProgramError> {
+    if round_up {
+        value.try_ceil_u64()
+    } else {
+        value.try_floor_u64()
+    }
+}
+
+fn ordered(a: Decimal, b: Decimal) -> (Decimal, Decimal) {
+    if a < b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+/// Amount of token a covered by `liquidity` between two square root prices,
+/// `L * (sqrt_upper - sqrt_lower) / (sqrt_lower * sqrt_upper)`
+pub fn amount_a_delta(
+    sqrt_price_0: Decimal,
+    sqrt_price_1: Decimal,
+    liquidity: Decimal,
+    round_up: bool,
+) -> Result<u64, ProgramError> {
+    let (sqrt_lower, sqrt_upper) = ordered(sqrt_price_0, sqrt_price_1);
+    let amount = liquidity
+        .try_mul(sqrt_upper.try_sub(sqrt_lower)?)?
+        .try_div(sqrt_upper)?
+        .try_div(sqrt_lower)?;
+    to_amount(amount, round_up)
+}
+
+/// Amount of token b covered by `liquidity` between two square root prices,
+/// `L * (sqrt_upper - sqrt_lower)`
+pub fn amount_b_delta(
+    sqrt_price_0: Decimal,
+    sqrt_price_1: Decimal,
+    liquidity: Decimal,
+    round_up: bool,
+) -> Result<u64, ProgramError> {
+    let (sqrt_lower, sqrt_upper) = ordered(sqrt_price_0, sqrt_price_1);
+    let amount = liquidity.try_mul(sqrt_upper.try_sub(sqrt_lower)?)?;
+    to_amount(amount, round_up)
+}
+
+/// Square root price after `amount_in` is added to the pool, rounded in favour of the pool
+pub fn next_sqrt_price_from_input(
+    sqrt_price: Decimal,
+    liquidity: Decimal,
+    amount_in: u64,
+    a_to_b: bool,
+) -> Result<Decimal, ProgramError> {
+    if amount_in == 0 {
+        return Ok(sqrt_price);
+    }
+    if liquidity.is_zero() {
+        return Err(SwapError::InsufficientLiquidity.into());
+    }
+    let amount_in = Decimal::from(amount_in);
+    if a_to_b {
+        // L * sqrt_p / (L + amount * sqrt_p), rounded up
+        liquidity
+            .try_mul(sqrt_price)?
+            .try_div(liquidity.try_add(amount_in.try_mul(sqrt_price)?)?)?
+            .try_add(Decimal::from_scaled_val(1))
+    } else {
+        // sqrt_p + amount / L, rounded down
+        sqrt_price.try_add(amount_in.try_div(liquidity)?)
+    }
+}
+
+/// Result of a swap within a single tick range
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SwapStep {
+    /// square root price after the step
+    pub sqrt_price_next: Decimal,
+    /// input consumed by the curve, excluding fees
+    pub amount_in: u64,
+    /// output paid by the curve
+    pub amount_out: u64,
+    /// fee charged on the input
+    pub fee_amount: u64,
+}
+
+/// Swap as much of `amount_remaining` as possible without moving past `sqrt_price_target`
+pub fn compute_swap_step(
+    sqrt_price_current: Decimal,
+    sqrt_price_target: Decimal,
+    liquidity: u128,
+    amount_remaining: u64,
+    fee_rate: Rate,
+    a_to_b: bool,
+) -> Result<SwapStep, ProgramError> {
+    let liquidity = Decimal::from(liquidity);
+    let fee_complement = Rate::one().try_sub(fee_rate)?;
+    let amount_remaining_less_fee = Decimal::from(amount_remaining)
+        .try_mul(fee_complement)?
+        .try_floor_u64()?;
+
+    let amount_to_target = if a_to_b {
+        amount_a_delta(sqrt_price_target, sqrt_price_current, liquidity, true)?
+    } else {
+        amount_b_delta(sqrt_price_current, sqrt_price_target, liquidity, true)?
+    };
+    let sqrt_price_next = if amount_remaining_less_fee >= amount_to_target {
+        sqrt_price_target
+    } else {
+        let next = next_sqrt_price_from_input(
+            sqrt_price_current,
+            liquidity,
+            amount_remaining_less_fee,
+            a_to_b,
+        )?;
+        // rounding must not carry the price past the target
+        if a_to_b {
+            max(next, sqrt_price_target)
+        } else {
+            min(next, sqrt_price_target)
+        }
+    };
+    let reached_target = sqrt_price_next == sqrt_price_target;
+
+    let amount_in = if reached_target {
+        amount_to_target
+    } else {
+        amount_remaining_less_fee
+    };
+    let amount_out = if a_to_b {
+        amount_b_delta(sqrt_price_next, sqrt_price_current, liquidity, false)?
+    } else {
+        amount_a_delta(sqrt_price_current, sqrt_price_next, liquidity, false)?
+    };
+    let fee_amount = if reached_target {
+        Decimal::from(amount_in)
+            .try_mul(fee_rate)?
+            .try_div(fee_complement)?
+            .try_ceil_u64()?
+    } else {
+        amount_remaining
+            .checked_sub(amount_in)
+            .ok_or(SwapError::CalculationFailure)?
+    };
+
+    Ok(SwapStep {
+        sqrt_price_next,
+        amount_in,
+        amount_out,
+        fee_amount,
+    })
+}
+
+/// Fee growth is tracked modulo 2^192, only differences between snapshots are meaningful
+fn wrapping_sub(a: Decimal, b: Decimal) -> Decimal {
+    Decimal(a.0.overflowing_sub(b.0).0)
+}
+
+fn add_liquidity_delta(liquidity: u128, delta: i128) -> Result<u128, ProgramError> {
+    let result = if delta < 0 {
+        liquidity.checked_sub(delta.unsigned_abs())
+    } else {
+        liquidity.checked_add(delta.unsigned_abs())
+    };
+    result.ok_or_else(|| SwapError::CalculationFailure.into())
+}
+
+/// Initialized tick of a concentrated liquidity pool
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TickInfo {
+    /// tick index
+    pub index: i32,
+    /// total liquidity of positions using this tick as a bound
+    pub liquidity_gross: u128,
+    /// liquidity added when the tick is crossed from left to right
+    pub liquidity_net: i128,
+    /// fee growth of token a on the other side of this tick from the current tick
+    pub fee_growth_outside_a: Decimal,
+    /// fee growth of token b on the other side of this tick from the current tick
+    pub fee_growth_outside_b: Decimal,
+}
+
+/// Liquidity position over a tick range
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Position {
+    /// lower tick of the range
+    pub tick_lower: i32,
+    /// upper tick of the range
+    pub tick_upper: i32,
+    /// liquidity of the position
+    pub liquidity: u128,
+    /// fee growth of token a inside the range at the last update
+    pub fee_growth_inside_last_a: Decimal,
+    /// fee growth of token b inside the range at the last update
+    pub fee_growth_inside_last_b: Decimal,
+    /// uncollected fees of token a
+    pub fees_owed_a: u64,
+    /// uncollected fees of token b
+    pub fees_owed_b: u64,
+}
+
+/// Result of a concentrated liquidity swap
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ConcentratedSwapResult {
+    /// input consumed, including fees
+    pub amount_in: u64,
+    /// output paid to the user
+    pub amount_out: u64,
+    /// fees charged on the input
+    pub fee_amount: u64,
+}
+
+/// Concentrated liquidity pool
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConcentratedPool {
+    /// square root of the current price
+    pub sqrt_price: Decimal,
+    /// current tick
+    pub tick_current: i32,
+    /// liquidity active at the current tick
+    pub liquidity: u128,
+    /// fraction of the input charged as fee
+    pub fee_rate: Rate,
+    /// fee growth of token a per unit of liquidity
+    pub fee_growth_global_a: Decimal,
+    /// fee growth of token b per unit of liquidity
+    pub fee_growth_global_b: Decimal,
+    /// initialized ticks, kept sorted by index for the binary searches
+    ticks: Vec<TickInfo>,
+}
+
+impl ConcentratedPool {
+    /// Create an empty pool at `sqrt_price`
+    pub fn new(sqrt_price: Decimal, fee_rate: Rate) -> Result<Self, ProgramError> {
+        if fee_rate >= Rate::one() {
+            return Err(SwapError::InvalidInput.into());
+        }
+        Ok(Self {
+            sqrt_price,
+            tick_current: tick_at_sqrt_price(sqrt_price)?,
+            liquidity: 0,
+            fee_rate,
+            fee_growth_global_a: Decimal::zero(),
+            fee_growth_global_b: Decimal::zero(),
+            ticks: vec![],
+        })
+    }
+
+    /// Initialized ticks sorted by index
+    pub fn ticks(&self) -> &[TickInfo] {
+        &self.ticks
+    }
+
+    /// Initialized tick at `index`
+    pub fn tick(&self, index: i32) -> Option<&TickInfo> {
+        self.ticks
+            .binary_search_by_key(&index, |tick| tick.index)
+            .ok()
+            .map(|position| &self.ticks[position])
+    }
+
+    fn update_tick(
+        &mut self,
+        index: i32,
+        liquidity_delta: i128,
+        upper: bool,
+    ) -> Result<(), ProgramError> {
+        let position = match self.ticks.binary_search_by_key(&index, |tick| tick.index) {
+            Ok(position) => position,
+            Err(position) => {
+                // by convention all fee growth so far happened below the current tick
+                let (fee_growth_outside_a, fee_growth_outside_b) = if index <= self.tick_current {
+                    (self.fee_growth_global_a, self.fee_growth_global_b)
+                } else {
+                    (Decimal::zero(), Decimal::zero())
+                };
+                self.ticks.insert(
+                    position,
+                    TickInfo {
+                        index,
+                        fee_growth_outside_a,
+                        fee_growth_outside_b,
+                        ..TickInfo::default()
+                    },
+                );
+                position
+            }
+        };
+
+        let tick = &mut self.ticks[position];
+        tick.liquidity_gross = add_liquidity_delta(tick.liquidity_gross, liquidity_delta)?;
+        tick.liquidity_net = if upper {
+            tick.liquidity_net.checked_sub(liquidity_delta)
+        } else {
+            tick.liquidity_net.checked_add(liquidity_delta)
+        }
+        .ok_or(SwapError::CalculationFailure)?;
+        Ok(())
+    }
+
+    /// Fee growth of token a and b per unit of liquidity inside `[tick_lower, tick_upper)`
+    pub fn fee_growth_inside(
+        &self,
+        tick_lower: i32,
+        tick_upper: i32,
+    ) -> Result<(Decimal, Decimal), ProgramError> {
+        let lower = self.tick(tick_lower).ok_or(SwapError::InvalidInput)?;
+        let upper = self.tick(tick_upper).ok_or(SwapError::InvalidInput)?;
+
+        let (below_a, below_b) = if self.tick_current >= tick_lower {
+            (lower.fee_growth_outside_a, lower.fee_growth_outside_b)
+        } else {
+            (
+                wrapping_sub(self.fee_growth_global_a, lower.fee_growth_outside_a),
+                wrapping_sub(self.fee_growth_global_b, lower.fee_growth_outside_b),
+            )
+        };
+        let (above_a, above_b) = if self.tick_current < tick_upper {
+            (upper.fee_growth_outside_a, upper.fee_growth_outside_b)
+        } else {
+            (
+                wrapping_sub(self.fee_growth_global_a, upper.fee_growth_outside_a),
+                wrapping_sub(self.fee_growth_global_b, upper.fee_growth_outside_b),
+            )
+        };
+
+        Ok((
+            wrapping_sub(wrapping_sub(self.fee_growth_global_a, below_a), above_a),
+            wrapping_sub(wrapping_sub(self.fee_growth_global_b, below_b), above_b),
+        ))
+    }
+
+    /// Add (positive delta) or remove (negative delta) liquidity from `position`, accruing
+    /// its fees. Returns the amounts of token a and b deposited or withdrawn, rounded in
+    /// favour of the pool.
+    pub fn modify_position(
+        &mut self,
+        position: &mut Position,
+        liquidity_delta: i128,
+    ) -> Result<(u64, u64), ProgramError> {
+        if position.tick_lower >= position.tick_upper
+            || position.tick_lower < MIN_TICK
+            || position.tick_upper > MAX_TICK
+        {
+            return Err(SwapError::InvalidInput.into());
+        }
+        if liquidity_delta < 0 && liquidity_delta.unsigned_abs() > position.liquidity {
+            return Err(SwapError::InsufficientLiquidity.into());
+        }
+        if liquidity_delta == 0 && position.liquidity == 0 {
+            return Err(SwapError::LiquidityPositionEmpty.into());
+        }
+
+        if liquidity_delta != 0 {
+            self.update_tick(position.tick_lower, liquidity_delta, false)?;
+            self.update_tick(position.tick_upper, liquidity_delta, true)?;
+        }
+
+        let (inside_a, inside_b) =
+            self.fee_growth_inside(position.tick_lower, position.tick_upper)?;
+        let liquidity = Decimal::from(position.liquidity);
+        let owed_a = wrapping_sub(inside_a, position.fee_growth_inside_last_a)
+            .try_mul(liquidity)?
+            .try_floor_u64()?;
+        let owed_b = wrapping_sub(inside_b, position.fee_growth_inside_last_b)
+            .try_mul(liquidity)?
+            .try_floor_u64()?;
+        position.fees_owed_a = position
+            .fees_owed_a
+            .checked_add(owed_a)
+            .ok_or(SwapError::CalculationFailure)?;
+        position.fees_owed_b = position
+            .fees_owed_b
+            .checked_add(owed_b)
+            .ok_or(SwapError::CalculationFailure)?;
+        position.fee_growth_inside_last_a = inside_a;
+        position.fee_growth_inside_last_b = inside_b;
+        position.liquidity = add_liquidity_delta(position.liquidity, liquidity_delta)?;
+
+        if self.tick_current >= position.tick_lower && self.tick_current < position.tick_upper {
+            self.liquidity = add_liquidity_delta(self.liquidity, liquidity_delta)?;
+        }
+        self.ticks.retain(|tick| tick.liquidity_gross > 0);
+
+        let round_up = liquidity_delta > 0;
+        let delta = Decimal::from(liquidity_delta.unsigned_abs());
+        let sqrt_lower = sqrt_price_at_tick(position.tick_lower)?;
+        let sqrt_upper = sqrt_price_at_tick(position.tick_upper)?;
+        if self.tick_current < position.tick_lower {
+            Ok((amount_a_delta(sqrt_lower, sqrt_upper, delta, round_up)?, 0))
+        } else if self.tick_current < position.tick_upper {
+            Ok((
+                amount_a_delta(self.sqrt_price, sqrt_upper, delta, round_up)?,
+                amount_b_delta(sqrt_lower, self.sqrt_price, delta, round_up)?,
+            ))
+        } else {
+            Ok((0, amount_b_delta(sqrt_lower, sqrt_upper, delta, round_up)?))
+        }
+    }
+
+    fn next_initialized_tick(&self, a_to_b: bool) -> Option<i32> {
+        // first tick above the current one
+        let above = self
+            .ticks
+            .partition_point(|tick| tick.index <= self.tick_current);
+        let next = if a_to_b { above.checked_sub(1)? } else { above };
+        self.ticks.get(next).map(|tick| tick.index)
+    }
+
+    fn cross_tick(&mut self, index: i32) -> Result<i128, ProgramError> {
+        let position = self
+            .ticks
+            .binary_search_by_key(&index, |tick| tick.index)
+            .map_err(|_| SwapError::CalculationFailure)?;
+        let tick = &mut self.ticks[position];
+        tick.fee_growth_outside_a =
+            wrapping_sub(self.fee_growth_global_a, tick.fee_growth_outside_a);
+        tick.fee_growth_outside_b =
+            wrapping_sub(self.fee_growth_global_b, tick.fee_growth_outside_b);
+        Ok(tick.liquidity_net)
+    }
+
+    /// Swap an exact input, stepping across initialized ticks until the input is consumed
+    /// or the price reaches `sqrt_price_limit` (defaults to the end of the tick range)
+    pub fn swap(
+        &mut self,
+        direction: SwapDirection,
+        amount_in: u64,
+        sqrt_price_limit: Option<Decimal>,
+    ) -> Result<ConcentratedSwapResult, ProgramError> {
+        let a_to_b = direction == SwapDirection::AtoB;
+        let sqrt_price_limit = match sqrt_price_limit {
+            Some(limit) => limit,
+            None => sqrt_price_at_tick(if a_to_b { MIN_TICK } else { MAX_TICK })?,
+        };
+        let limit_valid = if a_to_b {
+            sqrt_price_limit < self.sqrt_price && sqrt_price_limit >= sqrt_price_at_tick(MIN_TICK)?
+        } else {
+            sqrt_price_limit > self.sqrt_price && sqrt_price_limit <= sqrt_price_at_tick(MAX_TICK)?
+        };
+        if !limit_valid {
+            return Err(SwapError::InvalidInput.into());
+        }
+
+        let mut amount_remaining = amount_in;
+        let mut result = ConcentratedSwapResult::default();
+        let mut steps = 0;
+        while amount_remaining > 0 && self.sqrt_price != sqrt_price_limit {
+            steps += 1;
+            if steps > MAX_SWAP_STEPS {
+                return Err(SwapError::CalculationFailure.into());
+            }
+
+            let next_tick = self.next_initialized_tick(a_to_b);
+            let sqrt_price_next_tick = sqrt_price_at_tick(match next_tick {
+                Some(index) => index,
+                None if a_to_b => MIN_TICK,
+                None => MAX_TICK,
+            })?;
+            let sqrt_price_target = if a_to_b {
+                max(sqrt_price_next_tick, sqrt_price_limit)
+            } else {
+                min(sqrt_price_next_tick, sqrt_price_limit)
+            };
+
+            let step = compute_swap_step(
+                self.sqrt_price,
+                sqrt_price_target,
+                self.liquidity,
+                amount_remaining,
+                self.fee_rate,
+                a_to_b,
+            )?;
+            amount_remaining = amount_remaining
+                .checked_sub(step.amount_in)
+                .and_then(|amount| amount.checked_sub(step.fee_amount))
+                .ok_or(SwapError::CalculationFailure)?;
+            result.amount_out = result
+                .amount_out
+                .checked_add(step.amount_out)
+                .ok_or(SwapError::CalculationFailure)?;
+            result.fee_amount = result
+                .fee_amount
+                .checked_add(step.fee_amount)
+                .ok_or(SwapError::CalculationFailure)?;
+
+            if self.liquidity > 0 {
+                let growth =
+                    Decimal::from(step.fee_amount).try_div(Decimal::from(self.liquidity))?;
+                if a_to_b {
+                    self.fee_growth_global_a = self.fee_growth_global_a.try_add(growth)?;
+                } else {
+                    self.fee_growth_global_b = self.fee_growth_global_b.try_add(growth)?;
+                }
+            }
+
+            self.sqrt_price = step.sqrt_price_next;
+            match next_tick {
+                Some(index) if step.sqrt_price_next == sqrt_price_next_tick => {
+                    let liquidity_net = self.cross_tick(index)?;
+                    if a_to_b {
+                        self.liquidity = add_liquidity_delta(self.liquidity, -liquidity_net)?;
+                        self.tick_current = index - 1;
+                    } else {
+                        self.liquidity = add_liquidity_delta(self.liquidity, liquidity_net)?;
+                        self.tick_current = index;
+                    }
+                }
+                _ => self.tick_current = tick_at_sqrt_price(self.sqrt_price)?,
+            }
+        }
+
+        result.amount_in = amount_in - amount_remaining;
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fee_rate() -> Rate {
+        Rate::from_scaled_val(3_000_000_000)
+    }
+
+    fn position(tick_lower: i32, tick_upper: i32) -> Position {
+        Position {
+            tick_lower,
+            tick_upper,
+            ..Position::default()
+        }
+    }
+
+    #[test]
+    fn test_tick_price_conversion() {
+        assert_eq!(sqrt_price_at_tick(0).unwrap(), Decimal::one());
+        assert_eq!(
+            price_at_tick(1).unwrap(),
+            Decimal::from_scaled_val(1_000_099_999_999)
+        );
+        assert_eq!(
+            price_at_tick(-1).unwrap(),
+            Decimal::from_scaled_val(999_900_009_997)
+        );
+        // ln(2) / ln(1.0001) = 6931.8
+        assert_eq!(tick_at_price(Decimal::from(2u64)).unwrap(), 6_931);
+        assert_eq!(tick_at_price(Decimal::from_percent(50)).unwrap(), -6_932);
+
+        for tick in [-100_000, -6_932, -1, 0, 1, 42, 6_931, 100_000] {
+            assert_eq!(
+                tick_at_sqrt_price(sqrt_price_at_tick(tick).unwrap()).unwrap(),
+                tick
+            );
+        }
+        assert!(sqrt_price_at_tick(MAX_TICK + 1).is_err());
+        assert!(tick_at_sqrt_price(Decimal::zero()).is_err());
+    }
+
+    #[test]
+    fn test_tick_range_bounds() {
+        // neighbouring ticks keep distinct prices up to both ends of the range
+        for range in [MIN_TICK..MIN_TICK + 1_000, MAX_TICK - 1_000..MAX_TICK].iter() {
+            for tick in range.clone() {
+                assert!(price_at_tick(tick).unwrap() < price_at_tick(tick + 1).unwrap());
+            }
+        }
+        for tick in [MIN_TICK, MIN_TICK + 1, MAX_TICK - 1, MAX_TICK] {
+            assert_eq!(
+                tick_at_sqrt_price(sqrt_price_at_tick(tick).unwrap()).unwrap(),
+                tick
+            );
+        }
+        assert!(sqrt_price_at_tick(MIN_TICK - 1).is_err());
+        assert!(tick_at_sqrt_price(
+            sqrt_price_at_tick(MIN_TICK)
+                .unwrap()
+                .try_sub(Decimal::from_scaled_val(1))
+                .unwrap()
+        )
+        .is_err());
+        assert!(tick_at_sqrt_price(
+            sqrt_price_at_tick(MAX_TICK)
+                .unwrap()
+                .try_add(Decimal::from_scaled_val(1))
+                .unwrap()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_modify_position_amounts() {
+        let mut pool = ConcentratedPool::new(Decimal::one(), fee_rate()).unwrap();
+
+        // in range, both tokens
+        let mut in_range = position(-1_000, 1_000);
+        let (amount_a, amount_b) = pool.modify_position(&mut in_range, 1_000_000).unwrap();
+        assert_eq!((amount_a, amount_b), (48_769, 48_769));
+        assert_eq!(pool.liquidity, 1_000_000);
+
+        // above the current price, token a only
+        let mut above = position(1_000, 2_000);
+        let (amount_a, amount_b) = pool.modify_position(&mut above, 1_000_000).unwrap();
+        assert!(amount_a > 0);
+        assert_eq!(amount_b, 0);
+        assert_eq!(pool.liquidity, 1_000_000);
+
+        // below the current price, token b only
+        let mut below = position(-2_000, -1_000);
+        let (amount_a, amount_b) = pool.modify_position(&mut below, 1_000_000).unwrap();
+        assert_eq!(amount_a, 0);
+        assert!(amount_b > 0);
+
+        // ticks stay sorted whatever order they are added in
+        let indexes: Vec<i32> = pool.ticks().iter().map(|tick| tick.index).collect();
+        assert_eq!(indexes, vec![-2_000, -1_000, 1_000, 2_000]);
+        assert_eq!(pool.next_initialized_tick(true), Some(-1_000));
+        assert_eq!(pool.next_initialized_tick(false), Some(1_000));
+
+        // removing rounds down
+        let (amount_a, amount_b) = pool.modify_position(&mut in_range, -1_000_000).unwrap();
+        assert_eq!((amount_a, amount_b), (48_768, 48_768));
+        assert_eq!(pool.liquidity, 0);
+        assert!(pool.tick(-1_000).is_some());
+        assert!(pool.tick(0).is_none());
+
+        assert_eq!(
+            pool.modify_position(&mut in_range, -1).unwrap_err(),
+            SwapError::InsufficientLiquidity.into()
+        );
+        assert_eq!(
+            pool.modify_position(&mut position(10, 10), 1).unwrap_err(),
+            SwapError::InvalidInput.into()
+        );
+    }
+
+    #[test]
+    fn test_swap_within_range() {
+        let mut pool = ConcentratedPool::new(Decimal::one(), fee_rate()).unwrap();
+        pool.modify_position(&mut position(-10_000, 10_000), 1_000_000_000)
+            .unwrap();
+
+        let result = pool.swap(SwapDirection::AtoB, 1_000_000, None).unwrap();
+        assert_eq!(result.amount_in, 1_000_000);
+        assert_eq!(result.fee_amount, 3_000);
+        // constant product on 997_000 net input: 1e9 * 997_000 / (1e9 + 997_000) = 996_007
+        assert_eq!(result.amount_out, 996_006);
+        assert!(pool.sqrt_price < Decimal::one());
+        assert_eq!(pool.tick_current, -20);
+
+        // and back, the price returns close to one
+        let result = pool.swap(SwapDirection::BtoA, 996_006, None).unwrap();
+        assert!(result.amount_out < 1_000_000);
+        assert!(pool.tick_current == -1 || pool.tick_current == -2);
+    }
+
+    #[test]
+    fn test_swap_across_ticks() {
+        let mut pool = ConcentratedPool::new(Decimal::one(), fee_rate()).unwrap();
+        let mut narrow = position(-100, 100);
+        let mut wide = position(-1_000, 1_000);
+        pool.modify_position(&mut narrow, 1_000_000_000).unwrap();
+        pool.modify_position(&mut wide, 1_000_000_000).unwrap();
+        assert_eq!(pool.liquidity, 2_000_000_000);
+
+        let result = pool.swap(SwapDirection::AtoB, 20_000_000, None).unwrap();
+        assert_eq!(result.amount_in, 20_000_000);
+        assert!(pool.tick_current < -100);
+        assert_eq!(pool.liquidity, 1_000_000_000);
+
+        // a price limit stops the swap early
+        let limit = sqrt_price_at_tick(-500).unwrap();
+        let result = pool
+            .swap(SwapDirection::AtoB, 1_000_000_000, Some(limit))
+            .unwrap();
+        assert!(result.amount_in < 1_000_000_000);
+        assert_eq!(pool.sqrt_price, limit);
+        assert_eq!(pool.tick_current, -500);
+
+        // swapping back into the narrow range restores its liquidity
+        pool.swap(SwapDirection::BtoA, 30_000_000, None).unwrap();
+        assert!(pool.tick_current >= -100 && pool.tick_current < 100);
+        assert_eq!(pool.liquidity, 2_000_000_000);
+
+        assert_eq!(
+            pool.swap(SwapDirection::BtoA, 1, Some(Decimal::zero()))
+                .unwrap_err(),
+            SwapError::InvalidInput.into()
+        );
+    }
+
+    #[test]
+    fn test_fee_growth() {
+        let mut pool = ConcentratedPool::new(Decimal::one(), fee_rate()).unwrap();
+        let mut first = position(-1_000, 1_000);
+        let mut second = position(-1_000, 1_000);
+        let mut out_of_range = position(1_000, 2_000);
+        pool.modify_position(&mut first, 3_000_000_000).unwrap();
+        pool.modify_position(&mut second, 1_000_000_000).unwrap();
+        pool.modify_position(&mut out_of_range, 1_000_000_000)
+            .unwrap();
+
+        let result = pool.swap(SwapDirection::AtoB, 10_000_000, None).unwrap();
+        assert_eq!(result.fee_amount, 30_000);
+
+        pool.modify_position(&mut first, 0).unwrap();
+        pool.modify_position(&mut second, 0).unwrap();
+        pool.modify_position(&mut out_of_range, 0).unwrap();
+        assert_eq!(first.fees_owed_a, 22_500);
+        assert_eq!(second.fees_owed_a, 7_500);
+        assert_eq!(out_of_range.fees_owed_a, 0);
+        assert_eq!(first.fees_owed_b, 0);
+
+        // fees are not accrued twice
+        pool.modify_position(&mut first, 0).unwrap();
+        assert_eq!(first.fees_owed_a, 22_500);
+        assert_eq!(
+            pool.modify_position(&mut position(-1_000, 1_000), 0)
+                .unwrap_err(),
+            SwapError::LiquidityPositionEmpty.into()
+        );
+    }
+}
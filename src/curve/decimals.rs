@@ -0,0 +1,186 @@
+//! Token decimal normalization
+//!
+//! Raw amounts of mints with different decimals are not comparable: one USDC is `10^6` raw
+//! units while one SOL is `10^9`. Before curve math both sides are converted to the scale of
+//! the mint with the most decimals, where a whole token is `10^decimals` units on both sides
+//! and prices are quoted in whole tokens. Results are converted back rounding in favor of
+//! the pool: amounts paid out down, amounts owed up.
+
+use crate::{
+    curve::pool::{PoolState, SwapDirection, MAX_TOKEN_DECIMALS},
+    error::SwapError,
+    math::{Decimal, TryDiv, TryMul},
+};
+use solana_program::program_error::ProgramError;
+
+/// Decimals of the two mints of a pool
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TokenDecimals {
+    /// decimals of the token a mint
+    pub token_a: u8,
+    /// decimals of the token b mint
+    pub token_b: u8,
+}
+
+impl TokenDecimals {
+    /// Decimals of a pool, both at most `MAX_TOKEN_DECIMALS`
+    pub fn new(token_a: u8, token_b: u8) -> Result<Self, ProgramError> {
+        if token_a > MAX_TOKEN_DECIMALS || token_b > MAX_TOKEN_DECIMALS {
+            return Err(SwapError::InvalidTokenDecimals.into());
+        }
+        Ok(Self { token_a, token_b })
+    }
+
+    /// Swap the sides to match a pool oriented in `direction`
+    pub fn oriented(&self, direction: SwapDirection) -> Self {
+        match direction {
+            SwapDirection::AtoB => *self,
+            SwapDirection::BtoA => Self {
+                token_a: self.token_b,
+                token_b: self.token_a,
+            },
+        }
+    }
+
+    /// Units of the common scale in one raw unit of a mint with `decimals`
+    fn factor(&self, decimals: u8) -> u64 {
+        10u64.pow((self.token_a.max(self.token_b) - decimals) as u32)
+    }
+
+    /// Raw token a amount in the common scale
+    pub fn normalize_a(&self, amount: u64) -> Result<Decimal, ProgramError> {
+        Decimal::from(amount).try_mul(self.factor(self.token_a))
+    }
+
+    /// Raw token b amount in the common scale
+    pub fn normalize_b(&self, amount: u64) -> Result<Decimal, ProgramError> {
+        Decimal::from(amount).try_mul(self.factor(self.token_b))
+    }
+
+    /// Common scale amount of token b in raw units without rounding, for valuations
+    pub fn denormalize_b(&self, amount: Decimal) -> Result<Decimal, ProgramError> {
+        amount.try_div(self.factor(self.token_b))
+    }
+
+    /// Common scale amount of token a in raw units, rounded down for amounts paid out
+    pub fn denormalize_a_floor(&self, amount: Decimal) -> Result<u64, ProgramError> {
+        amount.try_div(self.factor(self.token_a))?.try_floor_u64()
+    }
+
+    /// Common scale amount of token b in raw units, rounded down for amounts paid out
+    pub fn denormalize_b_floor(&self, amount: Decimal) -> Result<u64, ProgramError> {
+        amount.try_div(self.factor(self.token_b))?.try_floor_u64()
+    }
+
+    /// Common scale amount of token a in raw units, rounded up for amounts owed
+    pub fn denormalize_a_ceil(&self, amount: Decimal) -> Result<u64, ProgramError> {
+        amount.try_div(self.factor(self.token_a))?.try_ceil_u64()
+    }
+
+    /// Common scale amount of token b in raw units, rounded up for amounts owed
+    pub fn denormalize_b_ceil(&self, amount: Decimal) -> Result<u64, ProgramError> {
+        amount.try_div(self.factor(self.token_b))?.try_ceil_u64()
+    }
+
+    /// Pool with raw reserves and a price of raw units, in the common scale
+    pub fn normalize_pool(&self, pool: &PoolState) -> Result<PoolState, ProgramError> {
+        Ok(PoolState {
+            // b per a scales by factor_b / factor_a, divide last to keep precision
+            market_price: pool
+                .market_price
+                .try_mul(self.factor(self.token_b))?
+                .try_div(self.factor(self.token_a))?,
+            ..self.normalize_reserves(pool)?
+        })
+    }
+
+    /// Pool with raw reserves and a price of whole tokens, in the common scale. The price is
+    /// already in the common scale and kept as is, without the rounding of a raw price.
+    pub fn normalize_reserves(&self, pool: &PoolState) -> Result<PoolState, ProgramError> {
+        let factor_a = self.factor(self.token_a);
+        let factor_b = self.factor(self.token_b);
+        Ok(PoolState {
+            market_price: pool.market_price,
+            target_reserve_a: pool.target_reserve_a.try_mul(factor_a)?,
+            target_reserve_b: pool.target_reserve_b.try_mul(factor_b)?,
+            current_reserve_a: pool.current_reserve_a.try_mul(factor_a)?,
+            current_reserve_b: pool.current_reserve_b.try_mul(factor_b)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::curve::{pool::raw_price, CurveType};
+
+    // usdc as token a, sol as token b
+    fn usdc_sol() -> TokenDecimals {
+        TokenDecimals::new(6, 9).unwrap()
+    }
+
+    #[test]
+    fn test_normalize_amounts() {
+        let decimals = usdc_sol();
+        // one usdc and one sol are both 10^9 units
+        assert_eq!(
+            decimals.normalize_a(1_000_000).unwrap(),
+            Decimal::from(1_000_000_000u64)
+        );
+        assert_eq!(
+            decimals.normalize_b(1_000_000_000).unwrap(),
+            Decimal::from(1_000_000_000u64)
+        );
+
+        let amount = Decimal::from(1_234_567u64);
+        assert_eq!(decimals.denormalize_a_floor(amount).unwrap(), 1_234);
+        assert_eq!(decimals.denormalize_a_ceil(amount).unwrap(), 1_235);
+        assert_eq!(decimals.denormalize_b_floor(amount).unwrap(), 1_234_567);
+        assert_eq!(decimals.denormalize_b_ceil(amount).unwrap(), 1_234_567);
+
+        let oriented = decimals.oriented(SwapDirection::BtoA);
+        assert_eq!(oriented, TokenDecimals::new(9, 6).unwrap());
+        assert_eq!(oriented.denormalize_b_floor(amount).unwrap(), 1_234);
+
+        assert_eq!(
+            TokenDecimals::new(6, MAX_TOKEN_DECIMALS + 1).unwrap_err(),
+            SwapError::InvalidTokenDecimals.into()
+        );
+    }
+
+    #[test]
+    fn test_normalized_pool_prices_whole_tokens() {
+        let decimals = usdc_sol();
+        // 1_000 sol against 150_000 usdc, a sol worth 150 usdc
+        let price = Decimal::one().try_div(150u64).unwrap();
+        let pool = PoolState {
+            market_price: raw_price(price, 6, 9).unwrap(),
+            target_reserve_a: Decimal::from(150_000_000_000u64),
+            target_reserve_b: Decimal::from(1_000_000_000_000u64),
+            current_reserve_a: Decimal::from(150_000_000_000u64),
+            current_reserve_b: Decimal::from(1_000_000_000_000u64),
+        };
+        let normalized = decimals.normalize_pool(&pool).unwrap();
+        assert_eq!(normalized.market_price, price);
+        assert_eq!(
+            normalized.current_reserve_a,
+            Decimal::from(150_000_000_000_000u64)
+        );
+        assert_eq!(normalized.current_reserve_b, pool.current_reserve_b);
+
+        // at the target reserves the marginal price is the whole token price
+        let curve = CurveType::Oracle;
+        let marginal = curve.marginal_price(&normalized).unwrap();
+        assert_eq!(
+            marginal.try_mul(150u64).unwrap().try_round_u64().unwrap(),
+            1
+        );
+
+        // selling 150 usdc buys a bit less than one sol
+        let amount_in = decimals.normalize_a(150_000_000).unwrap();
+        let amount_out = curve.swap_exact_in(&normalized, amount_in).unwrap();
+        let sol_out = decimals.denormalize_b_floor(amount_out).unwrap();
+        assert!(sol_out < 1_000_000_000);
+        assert!(sol_out > 990_000_000);
+    }
+}
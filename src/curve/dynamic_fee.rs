@@ -0,0 +1,194 @@
+//! Trade fee scaling with the deviation of current reserves from target reserves
+
+use crate::{
+    curve::{
+        fees::{FeeBreakdown, Fees},
+        pool::{CurveType, PoolState},
+    },
+    error::SwapError,
+    math::{Decimal, Rate, TryAdd, TryDiv, TryMul, TrySub},
+};
+use solana_program::program_error::ProgramError;
+use std::{cmp::min, convert::TryFrom};
+
+/// Dynamic trade fee configuration.
+/// Trades that leave the pool closer to its target reserves pay `base_fee`, trades that move
+/// it further away pay `base_fee + slope * deviation` after the trade, capped at `max_fee`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DynamicFee {
+    /// fee charged on rebalancing trades
+    pub base_fee: Rate,
+    /// additional fee per unit of post-trade deviation
+    pub slope: Rate,
+    /// maximum fee
+    pub max_fee: Rate,
+}
+
+impl DynamicFee {
+    /// Check the fee bounds are consistent
+    pub fn validate(&self) -> Result<(), ProgramError> {
+        if self.base_fee > self.max_fee || self.max_fee > Rate::one() {
+            return Err(SwapError::InvalidInput.into());
+        }
+        Ok(())
+    }
+
+    /// Trade fee for a swap moving the pool from `pre_trade` to `post_trade`
+    pub fn trade_fee(
+        &self,
+        pre_trade: &PoolState,
+        post_trade: &PoolState,
+    ) -> Result<Rate, ProgramError> {
+        self.validate()?;
+        let pre_deviation = reserve_deviation(pre_trade)?;
+        let post_deviation = reserve_deviation(post_trade)?;
+        if post_deviation <= pre_deviation {
+            return Ok(self.base_fee);
+        }
+
+        let fee = Decimal::from(self.base_fee).try_add(post_deviation.try_mul(self.slope)?)?;
+        Ok(min(Rate::try_from(fee)?, self.max_fee))
+    }
+}
+
+/// Average relative deviation of the current reserves from the target reserves
+pub fn reserve_deviation(pool: &PoolState) -> Result<Decimal, ProgramError> {
+    let deviation_a = relative_deviation(pool.current_reserve_a, pool.target_reserve_a)?;
+    let deviation_b = relative_deviation(pool.current_reserve_b, pool.target_reserve_b)?;
+    deviation_a.try_add(deviation_b)?.try_div(2u64)
+}
+
+/// |current - target| / target
+fn relative_deviation(current: Decimal, target: Decimal) -> Result<Decimal, ProgramError> {
+    let difference = if current > target {
+        current.try_sub(target)?
+    } else {
+        target.try_sub(current)?
+    };
+    difference.try_div(target)
+}
+
+/// Swap an exact input of token a and charge a trade fee derived from `dynamic_fee`.
+/// The admin and referrer shares of the fee are taken from `fees`.
+pub fn swap_with_dynamic_fee(
+    curve: &CurveType,
+    pool: &PoolState,
+    dynamic_fee: &DynamicFee,
+    fees: &Fees,
+    input_a_amount: Decimal,
+    has_referrer: bool,
+) -> Result<FeeBreakdown, ProgramError> {
+    let output = curve.swap_exact_in(pool, input_a_amount)?;
+    let post_trade = pool.after_swap(input_a_amount, output)?;
+    let trade_fee = dynamic_fee.trade_fee(pool, &post_trade)?;
+
+    Fees { trade_fee, ..*fees }.apply(output.try_floor_u64()?, has_referrer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::MockedSwap;
+
+    fn dynamic_fee() -> DynamicFee {
+        DynamicFee {
+            base_fee: Rate::from_scaled_val(1_000_000_000),
+            slope: Rate::from_percent(10),
+            max_fee: Rate::from_percent(1),
+        }
+    }
+
+    fn mocked_swap(current_reserve_a: u64, current_reserve_b: u64) -> MockedSwap {
+        MockedSwap {
+            target_reserve_a: Decimal::from(1_000_000u64),
+            target_reserve_b: Decimal::from(3_000_000u64),
+            current_reserve_a: Decimal::from(current_reserve_a),
+            current_reserve_b: Decimal::from(current_reserve_b),
+            market_price: Decimal::from(3u64),
+            ..MockedSwap::default()
+        }
+    }
+
+    #[test]
+    fn test_reserve_deviation() {
+        let pool = PoolState::try_from(&mocked_swap(1_000_000, 3_000_000)).unwrap();
+        assert_eq!(reserve_deviation(&pool).unwrap(), Decimal::zero());
+
+        // 10% over on a and 10% under on b
+        let pool = PoolState::try_from(&mocked_swap(1_100_000, 2_700_000)).unwrap();
+        assert_eq!(reserve_deviation(&pool).unwrap(), Decimal::from_percent(10));
+    }
+
+    #[test]
+    fn test_rebalancing_trades_are_cheaper() {
+        let fee = dynamic_fee();
+        let input = Decimal::from(10_000u64);
+
+        // pool short of token a, selling a restores balance
+        let short_a = PoolState::try_from(&mocked_swap(900_000, 3_300_000)).unwrap();
+        let rebalancing = swap_with_dynamic_fee(
+            &CurveType::Oracle,
+            &short_a,
+            &fee,
+            &Fees::default(),
+            input,
+            false,
+        )
+        .unwrap();
+
+        // pool long token a, selling a pushes it further from target
+        let long_a = PoolState::try_from(&mocked_swap(1_100_000, 2_700_000)).unwrap();
+        let imbalancing = swap_with_dynamic_fee(
+            &CurveType::Oracle,
+            &long_a,
+            &fee,
+            &Fees::default(),
+            input,
+            false,
+        )
+        .unwrap();
+
+        let rebalancing_rate = Decimal::from(rebalancing.total_fee)
+            .try_div(Decimal::from(
+                rebalancing.total_fee + rebalancing.amount_out,
+            ))
+            .unwrap();
+        let imbalancing_rate = Decimal::from(imbalancing.total_fee)
+            .try_div(Decimal::from(
+                imbalancing.total_fee + imbalancing.amount_out,
+            ))
+            .unwrap();
+        assert!(rebalancing_rate < imbalancing_rate);
+
+        let post_trade = short_a.after_swap(input, Decimal::from(30_000u64)).unwrap();
+        assert_eq!(fee.trade_fee(&short_a, &post_trade).unwrap(), fee.base_fee);
+    }
+
+    #[test]
+    fn test_fee_is_capped() {
+        let fee = dynamic_fee();
+        let pre_trade = PoolState::try_from(&mocked_swap(1_000_000, 3_000_000)).unwrap();
+        let small = pre_trade
+            .after_swap(Decimal::from(10_000u64), Decimal::from(30_000u64))
+            .unwrap();
+        // 1% deviation: 0.1% + 10% * 1%
+        assert_eq!(
+            fee.trade_fee(&pre_trade, &small).unwrap(),
+            Rate::from_scaled_val(2_000_000_000)
+        );
+
+        let large = pre_trade
+            .after_swap(Decimal::from(500_000u64), Decimal::from(1_000_000u64))
+            .unwrap();
+        assert_eq!(fee.trade_fee(&pre_trade, &large).unwrap(), fee.max_fee);
+
+        let invalid = DynamicFee {
+            base_fee: Rate::from_percent(2),
+            ..fee
+        };
+        assert_eq!(
+            invalid.trade_fee(&pre_trade, &small).unwrap_err(),
+            SwapError::InvalidInput.into()
+        );
+    }
+}
@@ -0,0 +1,214 @@
+//! Trading fees charged on swaps
+
+use crate::{
+    curve::pool::{round_up_quote, CurveType, PoolState},
+    error::SwapError,
+    math::{Decimal, Rate, TryAdd, TryDiv, TryMul, TrySub, WAD},
+};
+use solana_program::program_error::ProgramError;
+
+/// Default trade fee, 0.3% of the swap output
+pub const DEFAULT_TRADE_FEE: u128 = 3 * WAD as u128 / 1_000;
+/// Default share of the trade fee paid to the admin fee account
+pub const DEFAULT_ADMIN_FEE_PERCENT: u8 = 20;
+/// Default share of the trade fee paid to the referrer
+pub const DEFAULT_REFERRER_FEE_PERCENT: u8 = 10;
+
+/// Fee configuration of a pool
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fees {
+    /// fraction of the swap output charged as fee
+    pub trade_fee: Rate,
+    /// fraction of the trade fee paid to the admin fee account
+    pub admin_fee: Rate,
+    /// fraction of the trade fee paid to the referrer
+    pub referrer_fee: Rate,
+}
+
+impl Default for Fees {
+    fn default() -> Self {
+        Self {
+            trade_fee: Rate::from_scaled_val(DEFAULT_TRADE_FEE),
+            admin_fee: Rate::from_percent(DEFAULT_ADMIN_FEE_PERCENT),
+            referrer_fee: Rate::from_percent(DEFAULT_REFERRER_FEE_PERCENT),
+        }
+    }
+}
+
+/// Split of a swap output between the user and the fee destinations
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FeeBreakdown {
+    /// amount sent to the user
+    pub amount_out: u64,
+    /// total fee charged
+    pub total_fee: u64,
+    /// portion of the fee left in the pool for liquidity providers
+    pub lp_fee: u64,
+    /// portion of the fee sent to the admin fee account
+    pub admin_fee: u64,
+    /// portion of the fee sent to the referrer
+    pub referrer_fee: u64,
+}
+
+impl Fees {
+    /// Check every fee is at most one and the admin and referrer shares fit in the trade fee
+    pub fn validate(&self) -> Result<(), ProgramError> {
+        if self.trade_fee > Rate::one() || self.admin_fee.try_add(self.referrer_fee)? > Rate::one()
+        {
+            return Err(SwapError::InvalidInput.into());
+        }
+        Ok(())
+    }
+
+    /// Split `output` into the user amount and fee portions.
+    /// The total fee is rounded up and the admin and referrer portions are rounded down,
+    /// so every rounding remainder stays in the pool.
+    pub fn apply(&self, output: u64, has_referrer: bool) -> Result<FeeBreakdown, ProgramError> {
+        self.validate()?;
+        let total_fee = Decimal::from(output)
+            .try_mul(self.trade_fee)?
+            .try_ceil_u64()?;
+        let admin_fee = Decimal::from(total_fee)
+            .try_mul(self.admin_fee)?
+            .try_floor_u64()?;
+        let referrer_fee = if has_referrer {
+            Decimal::from(total_fee)
+                .try_mul(self.referrer_fee)?
+                .try_floor_u64()?
+        } else {
+            0
+        };
+        let lp_fee = total_fee
+            .checked_sub(admin_fee)
+            .and_then(|fee| fee.checked_sub(referrer_fee))
+            .ok_or(SwapError::CalculationFailure)?;
+        let amount_out = output
+            .checked_sub(total_fee)
+            .ok_or(SwapError::CalculationFailure)?;
+
+        Ok(FeeBreakdown {
+            amount_out,
+            total_fee,
+            lp_fee,
+            admin_fee,
+            referrer_fee,
+        })
+    }
+
+    /// Smallest curve output for which `apply` leaves at least `amount_out` to the user
+    pub fn output_before_fees(&self, amount_out: u64) -> Result<u64, ProgramError> {
+        self.validate()?;
+        let output = Decimal::from(amount_out)
+            .try_div(Rate::one().try_sub(self.trade_fee)?)?
+            .try_ceil_u64()?;
+        round_up_quote(output, |output| {
+            Ok(self.apply(output, false)?.amount_out >= amount_out)
+        })
+    }
+}
+
+/// Swap an exact input of token a and charge fees on the token b output
+pub fn swap_with_fees(
+    curve: &CurveType,
+    pool: &PoolState,
+    fees: &Fees,
+    input_a_amount: Decimal,
+    has_referrer: bool,
+) -> Result<FeeBreakdown, ProgramError> {
+    let output = curve.swap_exact_in(pool, input_a_amount)?.try_floor_u64()?;
+    fees.apply(output, has_referrer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_fees() {
+        let fees = Fees::default();
+        let breakdown = fees.apply(1_000_000, true).unwrap();
+        assert_eq!(
+            breakdown,
+            FeeBreakdown {
+                amount_out: 997_000,
+                total_fee: 3_000,
+                lp_fee: 2_100,
+                admin_fee: 600,
+                referrer_fee: 300,
+            }
+        );
+
+        // without referrer the referrer share stays with the liquidity providers
+        let breakdown = fees.apply(1_000_000, false).unwrap();
+        assert_eq!(breakdown.referrer_fee, 0);
+        assert_eq!(breakdown.lp_fee, 2_400);
+    }
+
+    #[test]
+    fn test_fee_rounding_favours_pool() {
+        let fees = Fees::default();
+        // 0.3% of 1_001 is 3.003, rounded up to 4
+        let breakdown = fees.apply(1_001, true).unwrap();
+        assert_eq!(breakdown.total_fee, 4);
+        assert_eq!(breakdown.amount_out, 997);
+        assert_eq!(breakdown.admin_fee, 0);
+        assert_eq!(breakdown.referrer_fee, 0);
+        assert_eq!(breakdown.lp_fee, 4);
+
+        let breakdown = fees.apply(0, true).unwrap();
+        assert_eq!(breakdown, FeeBreakdown::default());
+    }
+
+    #[test]
+    fn test_output_before_fees() {
+        let fees = Fees::default();
+        for amount_out in [0u64, 1, 997, 1_000, 123_457, 1_000_000] {
+            let output = fees.output_before_fees(amount_out).unwrap();
+            assert!(fees.apply(output, true).unwrap().amount_out >= amount_out);
+            if output > 0 {
+                assert!(fees.apply(output - 1, true).unwrap().amount_out < amount_out);
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_fees() {
+        let fees = Fees {
+            trade_fee: Rate::from_percent(1),
+            admin_fee: Rate::from_percent(60),
+            referrer_fee: Rate::from_percent(50),
+        };
+        assert_eq!(fees.validate().unwrap_err(), SwapError::InvalidInput.into());
+
+        let fees = Fees {
+            trade_fee: Rate::from_percent(101),
+            ..Fees::default()
+        };
+        assert_eq!(
+            fees.apply(1_000, false).unwrap_err(),
+            SwapError::InvalidInput.into()
+        );
+    }
+
+    #[test]
+    fn test_swap_with_fees() {
+        let pool = PoolState {
+            market_price: Decimal::from(3u64),
+            target_reserve_a: Decimal::from(1_000_000u64),
+            target_reserve_b: Decimal::from(3_000_000u64),
+            current_reserve_a: Decimal::from(1_000_000u64),
+            current_reserve_b: Decimal::from(3_000_000u64),
+        };
+        let breakdown = swap_with_fees(
+            &CurveType::Oracle,
+            &pool,
+            &Fees::default(),
+            Decimal::from(1_000u64),
+            false,
+        )
+        .unwrap();
+        // 2_997 before fees
+        assert_eq!(breakdown.total_fee, 9);
+        assert_eq!(breakdown.amount_out, 2_988);
+    }
+}
@@ -0,0 +1,503 @@
+//! Liquidity deposit and withdraw calculations with pool token accounting
+//!
+//! Proportional deposits and withdrawals move both reserves by the share of pool tokens
+//! minted or burned. Single-sided deposits and withdrawals mint or burn pool tokens for
+//! the change of the curve's value function. The oracle curve keeps
+//! `current_reserve_a^exp * current_reserve_b` constant and is valued like a weighted pool
+//! with weights `exp` and one, so a single-sided round trip pays the same slippage as a swap.
+//! The part of a single-sided deposit or withdrawal beyond the pool composition is a swap in
+//! disguise and pays the trade fee, which stays in the pool for the liquidity providers.
+//! Targets of the resulting pool state are scaled by the value ratio, which keeps the oracle
+//! curve exponent unchanged.
+
+use crate::{
+    curve::{
+        fees::Fees,
+        pool::{CurveType, PoolState, SwapDirection},
+        rebalance::{current_value, deposit_to_targets, value_at_price, withdraw_from_targets},
+        stable::{compute_d, compute_y, validate_stable_price},
+    },
+    error::SwapError,
+    math::{Decimal, TryAdd, TryDiv, TryMul, TrySub},
+};
+use solana_program::program_error::ProgramError;
+
+/// Pool tokens locked on the first deposit so the supply never returns to zero
+pub const MINIMUM_LIQUIDITY: u64 = 1_000;
+
+/// Token and pool token amounts of a deposit or withdrawal
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LiquidityChange {
+    /// token a deposited or withdrawn
+    pub token_a_amount: u64,
+    /// token b deposited or withdrawn
+    pub token_b_amount: u64,
+    /// pool tokens minted to or burned from the user
+    pub pool_token_amount: u64,
+}
+
+/// First deposit into an empty pool.
+/// Pool tokens are the value of the deposit in token b at the market price, of which
+/// `MINIMUM_LIQUIDITY` is locked. The deposit becomes both the current and target reserves.
+pub fn initial_deposit(
+    pool: &PoolState,
+    pool_token_supply: u64,
+    token_a_amount: u64,
+    token_b_amount: u64,
+) -> Result<(LiquidityChange, PoolState), ProgramError> {
+    if pool_token_supply != 0 {
+        return Err(SwapError::InvalidSupply.into());
+    }
+    if token_a_amount == 0 || token_b_amount == 0 {
+        return Err(SwapError::InconsistentInitialPoolTokenBalance.into());
+    }
+
+    let reserve_a = Decimal::from(token_a_amount);
+    let reserve_b = Decimal::from(token_b_amount);
+    let pool_tokens = value_at_price(reserve_a, reserve_b, pool.market_price)?.try_floor_u64()?;
+    if pool_tokens <= MINIMUM_LIQUIDITY {
+        return Err(SwapError::InconsistentInitialPoolTokenBalance.into());
+    }
+
+    Ok((
+        LiquidityChange {
+            token_a_amount,
+            token_b_amount,
+            pool_token_amount: pool_tokens - MINIMUM_LIQUIDITY,
+        },
+        PoolState {
+            market_price: pool.market_price,
+            target_reserve_a: reserve_a,
+            target_reserve_b: reserve_b,
+            current_reserve_a: reserve_a,
+            current_reserve_b: reserve_b,
+        },
+    ))
+}
+
+/// Tokens required to mint `pool_token_amount`, rounded up in favor of the pool
+pub fn deposit_proportional(
+    pool: &PoolState,
+    pool_token_supply: u64,
+    pool_token_amount: u64,
+) -> Result<(LiquidityChange, PoolState), ProgramError> {
+    let share = pool_share(pool_token_supply, pool_token_amount)?;
+    let token_a_amount = pool.current_reserve_a.try_mul(share)?.try_ceil_u64()?;
+    let token_b_amount = pool.current_reserve_b.try_mul(share)?.try_ceil_u64()?;
+
+    Ok((
+        LiquidityChange {
+            token_a_amount,
+            token_b_amount,
+            pool_token_amount,
+        },
+        deposit_to_targets(
+            pool,
+            Decimal::from(token_a_amount),
+            Decimal::from(token_b_amount),
+        )?,
+    ))
+}
+
+/// Tokens received for burning `pool_token_amount`, rounded down in favor of the pool
+pub fn withdraw_proportional(
+    pool: &PoolState,
+    pool_token_supply: u64,
+    pool_token_amount: u64,
+) -> Result<(LiquidityChange, PoolState), ProgramError> {
+    if pool_token_amount > pool_token_supply {
+        return Err(SwapError::WithdrawNotEnough.into());
+    }
+    let share = pool_share(pool_token_supply, pool_token_amount)?;
+    let token_a_amount = pool.current_reserve_a.try_mul(share)?.try_floor_u64()?;
+    let token_b_amount = pool.current_reserve_b.try_mul(share)?.try_floor_u64()?;
+
+    Ok((
+        LiquidityChange {
+            token_a_amount,
+            token_b_amount,
+            pool_token_amount,
+        },
+        withdraw_from_targets(
+            pool,
+            Decimal::from(token_a_amount),
+            Decimal::from(token_b_amount),
+        )?,
+    ))
+}
+
+/// Pool tokens minted for depositing a single token, rounded down.
+/// `direction` selects the deposited token, `AtoB` deposits token a and `BtoA` token b.
+/// Pool tokens are minted for the deposit net of the trade fee on its imbalance.
+pub fn deposit_single(
+    curve: &CurveType,
+    pool: &PoolState,
+    fees: &Fees,
+    pool_token_supply: u64,
+    direction: SwapDirection,
+    amount: u64,
+) -> Result<(LiquidityChange, PoolState), ProgramError> {
+    let oriented = pool.oriented(direction)?;
+    let amount_in = Decimal::from(amount);
+    let amount_in = amount_in.try_sub(imbalance_fee(&oriented, fees, amount_in)?)?;
+    let growth = match curve.oriented(direction) {
+        CurveType::Oracle => weighted_deposit_growth(
+            &oriented,
+            amount_in,
+            oracle_exponent(&oriented)?,
+            Decimal::one(),
+        )?,
+        CurveType::Stable { amp } => {
+            validate_stable_price(oriented.market_price)?;
+            let d_before = compute_d(amp, oriented.current_reserve_a, oriented.current_reserve_b)?;
+            let d_after = compute_d(
+                amp,
+                oriented.current_reserve_a.try_add(amount_in)?,
+                oriented.current_reserve_b,
+            )?;
+            d_after.try_sub(d_before)?.try_div(d_before)?
+        }
+        CurveType::Weighted { weight_a, weight_b } => {
+            weighted_deposit_growth(&oriented, amount_in, weight_a, weight_b)?
+        }
+    };
+    let pool_token_amount = supply_decimal(pool_token_supply)?
+        .try_mul(growth)?
+        .try_floor_u64()?;
+
+    let (token_a_amount, token_b_amount) = single_sided(direction, amount);
+    Ok((
+        LiquidityChange {
+            token_a_amount,
+            token_b_amount,
+            pool_token_amount,
+        },
+        deposit_to_targets(
+            pool,
+            Decimal::from(token_a_amount),
+            Decimal::from(token_b_amount),
+        )?,
+    ))
+}
+
+/// Single token received for burning `pool_token_amount`, rounded down.
+/// `direction` selects the withdrawn token, `AtoB` withdraws token a and `BtoA` token b.
+/// The trade fee on the imbalance of the withdrawal is kept by the pool.
+pub fn withdraw_single(
+    curve: &CurveType,
+    pool: &PoolState,
+    fees: &Fees,
+    pool_token_supply: u64,
+    direction: SwapDirection,
+    pool_token_amount: u64,
+) -> Result<(LiquidityChange, PoolState), ProgramError> {
+    // the other reserve can not back the remaining supply once this side is drained
+    if pool_token_amount >= pool_token_supply {
+        return Err(SwapError::WithdrawNotEnough.into());
+    }
+    let share = pool_share(pool_token_supply, pool_token_amount)?;
+    let oriented = pool.oriented(direction)?;
+    let amount_out = match curve.oriented(direction) {
+        CurveType::Oracle => weighted_withdraw_amount(
+            &oriented,
+            share,
+            oracle_exponent(&oriented)?,
+            Decimal::one(),
+        )?,
+        CurveType::Stable { amp } => {
+            validate_stable_price(oriented.market_price)?;
+            let d_before = compute_d(amp, oriented.current_reserve_a, oriented.current_reserve_b)?;
+            let d_after = d_before.try_mul(Decimal::one().try_sub(share)?)?;
+            let reserve_after = compute_y(amp, oriented.current_reserve_b, d_after)?;
+            oriented.current_reserve_a.try_sub(reserve_after)?
+        }
+        CurveType::Weighted { weight_a, weight_b } => {
+            weighted_withdraw_amount(&oriented, share, weight_a, weight_b)?
+        }
+    };
+    if amount_out >= oriented.current_reserve_a {
+        return Err(SwapError::WithdrawNotEnough.into());
+    }
+    let amount_out = amount_out.try_sub(imbalance_fee(&oriented, fees, amount_out)?)?;
+
+    let (token_a_amount, token_b_amount) = single_sided(direction, amount_out.try_floor_u64()?);
+    Ok((
+        LiquidityChange {
+            token_a_amount,
+            token_b_amount,
+            pool_token_amount,
+        },
+        withdraw_from_targets(
+            pool,
+            Decimal::from(token_a_amount),
+            Decimal::from(token_b_amount),
+        )?,
+    ))
+}
+
+/// Trade fee on the part of a single-sided `amount` of token a beyond the pool composition,
+/// `amount * value_share_b * trade_fee` with `value_share_b` the share of reserve b in the
+/// pool value at the market price, rounded up in favor of the pool like the fee of a swap
+fn imbalance_fee(pool: &PoolState, fees: &Fees, amount: Decimal) -> Result<Decimal, ProgramError> {
+    fees.validate()?;
+    let fee = amount
+        .try_mul(pool.current_reserve_b)?
+        .try_div(current_value(pool)?)?
+        .try_mul(fees.trade_fee)?
+        .try_ceil_u64()?;
+    Ok(Decimal::from(fee))
+}
+
+/// Exponent of the oracle curve, `market_price * target_reserve_a / target_reserve_b`
+fn oracle_exponent(pool: &PoolState) -> Result<Decimal, ProgramError> {
+    pool.market_price
+        .try_mul(pool.target_reserve_a)?
+        .try_div(pool.target_reserve_b)
+}
+
+/// Value growth of a weighted pool from adding `amount_in` to reserve a,
+/// `((a + amount_in) / a)^(weight_a / (weight_a + weight_b)) - 1`
+fn weighted_deposit_growth(
+    pool: &PoolState,
+    amount_in: Decimal,
+    weight_a: Decimal,
+    weight_b: Decimal,
+) -> Result<Decimal, ProgramError> {
+    pool.current_reserve_a
+        .try_add(amount_in)?
+        .try_div(pool.current_reserve_a)?
+        .try_powf(weight_a.try_div(weight_a.try_add(weight_b)?)?)?
+        .try_sub(Decimal::one())
+}
+
+/// Reserve a removed from a weighted pool to give up `share` of its value,
+/// `a * (1 - (1 - share)^((weight_a + weight_b) / weight_a))`
+fn weighted_withdraw_amount(
+    pool: &PoolState,
+    share: Decimal,
+    weight_a: Decimal,
+    weight_b: Decimal,
+) -> Result<Decimal, ProgramError> {
+    let remaining = Decimal::one()
+        .try_sub(share)?
+        .try_powf(weight_a.try_add(weight_b)?.try_div(weight_a)?)?;
+    pool.current_reserve_a
+        .try_mul(Decimal::one().try_sub(remaining)?)
+}
+
+/// `pool_token_amount / pool_token_supply`
+fn pool_share(pool_token_supply: u64, pool_token_amount: u64) -> Result<Decimal, ProgramError> {
+    Decimal::from(pool_token_amount).try_div(supply_decimal(pool_token_supply)?)
+}
+
+fn supply_decimal(pool_token_supply: u64) -> Result<Decimal, ProgramError> {
+    if pool_token_supply == 0 {
+        return Err(SwapError::EmptyPool.into());
+    }
+    Ok(Decimal::from(pool_token_supply))
+}
+
+/// Token a and token b amounts of a single-sided change
+fn single_sided(direction: SwapDirection, amount: u64) -> (u64, u64) {
+    match direction {
+        SwapDirection::AtoB => (amount, 0),
+        SwapDirection::BtoA => (0, amount),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        curve::{fees::swap_with_fees, rebalance::check_value_conserved},
+        math::Rate,
+    };
+
+    fn oracle_pool() -> (PoolState, u64) {
+        let (change, pool) = initial_deposit(
+            &PoolState {
+                market_price: Decimal::from(3u64),
+                ..PoolState::default()
+            },
+            0,
+            1_000_000,
+            3_000_000,
+        )
+        .unwrap();
+        (pool, change.pool_token_amount + MINIMUM_LIQUIDITY)
+    }
+
+    #[test]
+    fn test_initial_deposit() {
+        let (pool, supply) = oracle_pool();
+        assert_eq!(supply, 6_000_000);
+        assert_eq!(pool.target_reserve_a, pool.current_reserve_a);
+
+        assert_eq!(
+            initial_deposit(&pool, supply, 1_000, 1_000).unwrap_err(),
+            SwapError::InvalidSupply.into()
+        );
+        assert_eq!(
+            initial_deposit(&pool, 0, 0, 1_000_000).unwrap_err(),
+            SwapError::InconsistentInitialPoolTokenBalance.into()
+        );
+        // 100 * 3 + 500 is below the locked liquidity
+        assert_eq!(
+            initial_deposit(&pool, 0, 100, 500).unwrap_err(),
+            SwapError::InconsistentInitialPoolTokenBalance.into()
+        );
+    }
+
+    #[test]
+    fn test_proportional_round_trip() {
+        let (pool, supply) = oracle_pool();
+        let (deposit, deposited) = deposit_proportional(&pool, supply, 600_001).unwrap();
+        // 10% plus rounding up in favor of the pool
+        assert_eq!(deposit.token_a_amount, 100_001);
+        assert_eq!(deposit.token_b_amount, 300_001);
+        check_value_conserved(&deposited, Decimal::from_scaled_val(1_000)).unwrap();
+
+        let (withdrawal, withdrawn) =
+            withdraw_proportional(&deposited, supply + 600_001, 600_001).unwrap();
+        assert!(withdrawal.token_a_amount <= deposit.token_a_amount);
+        assert!(withdrawal.token_b_amount <= deposit.token_b_amount);
+        assert!(withdrawn.current_reserve_a >= pool.current_reserve_a);
+        assert!(withdrawn.current_reserve_b >= pool.current_reserve_b);
+
+        assert_eq!(
+            deposit_proportional(&pool, 0, 1).unwrap_err(),
+            SwapError::EmptyPool.into()
+        );
+        assert_eq!(
+            withdraw_proportional(&pool, supply, supply + 1).unwrap_err(),
+            SwapError::WithdrawNotEnough.into()
+        );
+    }
+
+    #[test]
+    fn test_single_sided_oracle_round_trip_loses_value() {
+        let (pool, supply) = oracle_pool();
+        let fees = Fees::default();
+        // 60_000 token b is worth 1% of the pool at the market price
+        let (deposit, deposited) = deposit_single(
+            &CurveType::Oracle,
+            &pool,
+            &fees,
+            supply,
+            SwapDirection::BtoA,
+            60_000,
+        )
+        .unwrap();
+        assert_eq!(deposit.token_a_amount, 0);
+        // valued along the curve, less than the 60_000 pool tokens of a balanced deposit
+        assert!(deposit.pool_token_amount < 60_000);
+        assert!(deposit.pool_token_amount > 59_000);
+        check_value_conserved(&deposited, Decimal::from_scaled_val(1_000_000)).unwrap();
+
+        // half of the deposit is beyond the pool composition and pays the trade fee
+        let (fee_free, _) = deposit_single(
+            &CurveType::Oracle,
+            &pool,
+            &Fees {
+                trade_fee: Rate::zero(),
+                ..fees
+            },
+            supply,
+            SwapDirection::BtoA,
+            60_000,
+        )
+        .unwrap();
+        assert!(deposit.pool_token_amount < fee_free.pool_token_amount);
+        assert!(deposit.pool_token_amount >= fee_free.pool_token_amount - 90);
+
+        // targets grow by the value ratio, the exponent is unchanged
+        assert!(deposited.target_reserve_a > pool.target_reserve_a);
+
+        // withdrawing the other side is no cheaper than swapping 60_000 token b
+        let (withdrawal, _) = withdraw_single(
+            &CurveType::Oracle,
+            &deposited,
+            &fees,
+            supply + deposit.pool_token_amount,
+            SwapDirection::AtoB,
+            deposit.pool_token_amount,
+        )
+        .unwrap();
+        assert_eq!(withdrawal.token_b_amount, 0);
+        let swapped = swap_with_fees(
+            &CurveType::Oracle.oriented(SwapDirection::BtoA),
+            &pool.oriented(SwapDirection::BtoA).unwrap(),
+            &fees,
+            Decimal::from(60_000u64),
+            false,
+        )
+        .unwrap();
+        assert!(withdrawal.token_a_amount <= swapped.amount_out);
+        assert!(withdrawal.token_a_amount < 20_000);
+
+        // withdrawing the same side returns at most the deposit
+        let (same_side, _) = withdraw_single(
+            &CurveType::Oracle,
+            &deposited,
+            &fees,
+            supply + deposit.pool_token_amount,
+            SwapDirection::BtoA,
+            deposit.pool_token_amount,
+        )
+        .unwrap();
+        assert!(same_side.token_b_amount <= 60_000);
+
+        assert_eq!(
+            withdraw_single(
+                &CurveType::Oracle,
+                &pool,
+                &fees,
+                supply,
+                SwapDirection::AtoB,
+                supply
+            )
+            .unwrap_err(),
+            SwapError::WithdrawNotEnough.into()
+        );
+    }
+
+    #[test]
+    fn test_single_sided_stable_and_weighted() {
+        let pool = PoolState {
+            market_price: Decimal::one(),
+            target_reserve_a: Decimal::from(1_000_000u64),
+            target_reserve_b: Decimal::from(1_000_000u64),
+            current_reserve_a: Decimal::from(1_000_000u64),
+            current_reserve_b: Decimal::from(1_000_000u64),
+        };
+        let supply = 2_000_000;
+        let fees = Fees::default();
+        let curves = [
+            CurveType::Stable { amp: 100 },
+            CurveType::Weighted {
+                weight_a: Decimal::from_percent(50),
+                weight_b: Decimal::from_percent(50),
+            },
+        ];
+        for curve in curves.iter() {
+            let (deposit, deposited) =
+                deposit_single(curve, &pool, &fees, supply, SwapDirection::AtoB, 100_000).unwrap();
+            // single-sided deposits are worth less than a balanced deposit of equal size
+            assert!(deposit.pool_token_amount < 100_000);
+            assert!(deposit.pool_token_amount > 90_000);
+
+            let (withdrawal, _) = withdraw_single(
+                curve,
+                &deposited,
+                &fees,
+                supply + deposit.pool_token_amount,
+                SwapDirection::AtoB,
+                deposit.pool_token_amount,
+            )
+            .unwrap();
+            // no value is created by a deposit and withdrawal round trip
+            assert!(withdrawal.token_a_amount <= 100_000);
+            assert!(withdrawal.token_a_amount > 99_000);
+        }
+    }
+}
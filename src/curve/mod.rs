@@ -1,8 +1,38 @@
 //! Curve modules
 
+pub mod analytics;
+pub mod arbitrage;
 pub mod calc;
+pub mod concentrated;
+pub mod decimals;
+pub mod dynamic_fee;
+pub mod fees;
+pub mod liquidity;
+pub mod oracle;
+pub mod pool;
+pub mod rebalance;
+pub mod route;
+pub mod simulation;
+pub mod stable;
+pub mod verify;
+pub mod weighted;
 
+pub use analytics::*;
+pub use arbitrage::*;
 pub use calc::*;
+pub use concentrated::*;
+pub use decimals::*;
+pub use dynamic_fee::*;
+pub use fees::*;
+pub use liquidity::*;
+pub use oracle::*;
+pub use pool::*;
+pub use rebalance::*;
+pub use route::*;
+pub use simulation::*;
+pub use stable::*;
+pub use verify::*;
+pub use weighted::*;
 
 #[cfg(test)]
 use crate::math::{Decimal, HALF_WAD};
@@ -0,0 +1,327 @@
+//! Oracle price input for the curves
+//!
+//! An oracle price carries its confidence interval and the slot it was published in.
+//! Quotes use the price widened by a spread made of the relative confidence and a decay
+//! term growing with the age of the price. Prices that are too old, too uncertain or
+//! need too wide a spread are rejected.
+
+use crate::{
+    curve::pool::{PoolState, SwapDirection},
+    error::SwapError,
+    math::{Decimal, Rate, TryAdd, TryDiv, TryMul, TrySub, WAD},
+};
+use solana_program::{clock::Slot, program_error::ProgramError};
+use std::cmp::min;
+
+/// Default number of slots a price is used without staleness spread
+pub const DEFAULT_FRESH_SLOTS: u64 = 2;
+/// Default number of slots after which a price is rejected
+pub const DEFAULT_MAX_AGE_SLOTS: u64 = 25;
+/// Default maximum confidence relative to price, 2%
+pub const DEFAULT_MAX_CONFIDENCE_RATIO: u128 = 2 * WAD as u128 / 100;
+/// Default spread added per slot past the fresh slots, 0.05%
+pub const DEFAULT_STALENESS_SPREAD_PER_SLOT: u128 = 5 * WAD as u128 / 10_000;
+/// Default maximum total spread, 3%
+pub const DEFAULT_MAX_SPREAD: u128 = 3 * WAD as u128 / 100;
+
+/// Price published by an oracle
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct OraclePrice {
+    /// price of token a in token b
+    pub price: Decimal,
+    /// confidence interval around the price, in token b
+    pub confidence: Decimal,
+    /// slot the price was published in
+    pub publish_slot: Slot,
+}
+
+/// Limits applied to oracle prices before quoting
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OracleConfig {
+    /// slots a price is used without staleness spread
+    pub fresh_slots: u64,
+    /// slots after which a price is rejected
+    pub max_age_slots: u64,
+    /// maximum confidence relative to price
+    pub max_confidence_ratio: Rate,
+    /// spread added per slot past `fresh_slots`
+    pub staleness_spread_per_slot: Rate,
+    /// maximum total spread
+    pub max_spread: Rate,
+}
+
+impl Default for OracleConfig {
+    fn default() -> Self {
+        Self {
+            fresh_slots: DEFAULT_FRESH_SLOTS,
+            max_age_slots: DEFAULT_MAX_AGE_SLOTS,
+            max_confidence_ratio: Rate::from_scaled_val(DEFAULT_MAX_CONFIDENCE_RATIO),
+            staleness_spread_per_slot: Rate::from_scaled_val(DEFAULT_STALENESS_SPREAD_PER_SLOT),
+            max_spread: Rate::from_scaled_val(DEFAULT_MAX_SPREAD),
+        }
+    }
+}
+
+/// Bid and ask derived from an oracle price
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PriceQuote {
+    /// price paid for selling token a, `price * (1 - spread)`
+    pub bid: Decimal,
+    /// price charged for buying token a, `price * (1 + spread)`
+    pub ask: Decimal,
+    /// relative spread applied on each side
+    pub spread: Decimal,
+}
+
+impl OraclePrice {
+    /// Blend two prices weighted by the inverse of their confidence.
+    /// The blended confidence is the harmonic mean of both and the publish slot is the
+    /// older one, so the blend is never fresher or more certain than its inputs. Prices
+    /// further apart than their confidence intervals reach disagree on the market and are
+    /// rejected.
+    pub fn blend(&self, other: &OraclePrice) -> Result<OraclePrice, ProgramError> {
+        let publish_slot = min(self.publish_slot, other.publish_slot);
+        let divergence = if self.price > other.price {
+            self.price.try_sub(other.price)?
+        } else {
+            other.price.try_sub(self.price)?
+        };
+        if divergence > self.confidence.try_add(other.confidence)? {
+            return Err(SwapError::UnstableMarketPrice.into());
+        }
+        if self.confidence.is_zero() || other.confidence.is_zero() {
+            let exact = if self.confidence.is_zero() {
+                self
+            } else {
+                other
+            };
+            return Ok(OraclePrice {
+                publish_slot,
+                ..*exact
+            });
+        }
+
+        let weight_self = Decimal::one().try_div(self.confidence)?;
+        let weight_other = Decimal::one().try_div(other.confidence)?;
+        let total_weight = weight_self.try_add(weight_other)?;
+        Ok(OraclePrice {
+            price: self
+                .price
+                .try_mul(weight_self)?
+                .try_add(other.price.try_mul(weight_other)?)?
+                .try_div(total_weight)?,
+            confidence: Decimal::from(2u64).try_div(total_weight)?,
+            publish_slot,
+        })
+    }
+
+    /// Relative spread for quoting at `current_slot`, rejecting prices out of `config` limits
+    /// and prices published after `current_slot`
+    pub fn spread(
+        &self,
+        config: &OracleConfig,
+        current_slot: Slot,
+    ) -> Result<Decimal, ProgramError> {
+        if self.price.is_zero() || self.publish_slot > current_slot {
+            return Err(SwapError::InvalidInput.into());
+        }
+        let age = current_slot - self.publish_slot;
+        if age > config.max_age_slots {
+            return Err(SwapError::StalePythPrice.into());
+        }
+        let confidence_ratio = self.confidence.try_div(self.price)?;
+        if confidence_ratio > Decimal::from(config.max_confidence_ratio) {
+            return Err(SwapError::InconfidentPythPrice.into());
+        }
+
+        let stale_slots = age.saturating_sub(config.fresh_slots);
+        let spread = confidence_ratio
+            .try_add(Decimal::from(config.staleness_spread_per_slot).try_mul(stale_slots)?)?;
+        if spread > Decimal::from(config.max_spread) {
+            return Err(SwapError::UnstableMarketPrice.into());
+        }
+        Ok(spread)
+    }
+
+    /// Bid and ask at `current_slot`
+    pub fn quote(
+        &self,
+        config: &OracleConfig,
+        current_slot: Slot,
+    ) -> Result<PriceQuote, ProgramError> {
+        let spread = self.spread(config, current_slot)?;
+        Ok(PriceQuote {
+            bid: self.price.try_mul(Decimal::one().try_sub(spread)?)?,
+            ask: self.price.try_mul(Decimal::one().try_add(spread)?)?,
+            spread,
+        })
+    }
+
+    /// Pool priced for a swap in `direction` and oriented so the input token is token a.
+    /// Sellers of token a get the bid and sellers of token b pay the ask, so a wider
+    /// spread always quotes less output.
+    pub fn priced_pool(
+        &self,
+        pool: &PoolState,
+        config: &OracleConfig,
+        current_slot: Slot,
+        direction: SwapDirection,
+    ) -> Result<PoolState, ProgramError> {
+        let quote = self.quote(config, current_slot)?;
+        let market_price = match direction {
+            SwapDirection::AtoB => quote.bid,
+            SwapDirection::BtoA => quote.ask,
+        };
+        PoolState {
+            market_price,
+            ..*pool
+        }
+        .oriented(direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::curve::pool::CurveType;
+
+    fn oracle_price(confidence: u128) -> OraclePrice {
+        OraclePrice {
+            price: Decimal::from(3u64),
+            confidence: Decimal::from_scaled_val(confidence),
+            publish_slot: 100,
+        }
+    }
+
+    #[test]
+    fn test_spread_decays_with_age() {
+        let config = OracleConfig::default();
+        // 0.3% relative confidence
+        let price = oracle_price(9_000_000_000);
+        assert_eq!(
+            price.spread(&config, 102).unwrap(),
+            Decimal::from_scaled_val(3_000_000_000)
+        );
+        // 8 slots past fresh add 0.4%
+        assert_eq!(
+            price.spread(&config, 110).unwrap(),
+            Decimal::from_scaled_val(7_000_000_000)
+        );
+        assert_eq!(
+            price.spread(&config, 100).unwrap(),
+            Decimal::from_scaled_val(3_000_000_000)
+        );
+
+        let quote = price.quote(&config, 110).unwrap();
+        assert!(quote.bid < price.price && price.price < quote.ask);
+    }
+
+    #[test]
+    fn test_rejected_prices() {
+        let config = OracleConfig {
+            max_age_slots: 100,
+            ..OracleConfig::default()
+        };
+        let price = oracle_price(9_000_000_000);
+        assert_eq!(
+            price
+                .spread(&OracleConfig::default(), 100 + DEFAULT_MAX_AGE_SLOTS + 1)
+                .unwrap_err(),
+            SwapError::StalePythPrice.into()
+        );
+        // 0.3% confidence and 58 stale slots at 0.05% exceed 3%
+        assert_eq!(
+            price.spread(&config, 160).unwrap_err(),
+            SwapError::UnstableMarketPrice.into()
+        );
+        // 3% relative confidence
+        assert_eq!(
+            oracle_price(90_000_000_000)
+                .spread(&config, 100)
+                .unwrap_err(),
+            SwapError::InconfidentPythPrice.into()
+        );
+        assert_eq!(
+            OraclePrice::default().spread(&config, 0).unwrap_err(),
+            SwapError::InvalidInput.into()
+        );
+        // a publish slot ahead of the clock
+        assert_eq!(
+            price.spread(&config, 99).unwrap_err(),
+            SwapError::InvalidInput.into()
+        );
+    }
+
+    #[test]
+    fn test_blend() {
+        let precise = oracle_price(1_000_000_000);
+        let vague = OraclePrice {
+            price: Decimal::from_scaled_val(3_002_000_000_000),
+            confidence: Decimal::from_scaled_val(3_000_000_000),
+            publish_slot: 90,
+        };
+        let blended = precise.blend(&vague).unwrap();
+        // weights 3:1 towards the precise price
+        assert!((Decimal::from_scaled_val(3_000_499_999_999)
+            ..=Decimal::from_scaled_val(3_000_500_000_000))
+            .contains(&blended.price));
+        assert_eq!(blended.confidence, Decimal::from_scaled_val(1_500_000_000));
+        assert_eq!(blended.publish_slot, 90);
+
+        let exact = oracle_price(0);
+        assert_eq!(vague.blend(&exact).unwrap().price, exact.price);
+    }
+
+    #[test]
+    fn test_blend_rejects_diverging_prices() {
+        let precise = oracle_price(1_000_000_000);
+        let diverging = OraclePrice {
+            price: Decimal::from(4u64),
+            confidence: Decimal::from_scaled_val(3_000_000_000),
+            publish_slot: 100,
+        };
+        assert_eq!(
+            precise.blend(&diverging).unwrap_err(),
+            SwapError::UnstableMarketPrice.into()
+        );
+        assert_eq!(
+            diverging.blend(&precise).unwrap_err(),
+            SwapError::UnstableMarketPrice.into()
+        );
+
+        // touching intervals still agree, an exact price has none of its own
+        let touching = OraclePrice {
+            price: Decimal::from_scaled_val(3_004_000_000_000),
+            ..diverging
+        };
+        assert!(precise.blend(&touching).is_ok());
+        assert_eq!(
+            oracle_price(0).blend(&touching).unwrap_err(),
+            SwapError::UnstableMarketPrice.into()
+        );
+    }
+
+    #[test]
+    fn test_spread_reduces_output() {
+        let pool = PoolState {
+            market_price: Decimal::from(3u64),
+            target_reserve_a: Decimal::from(1_000_000u64),
+            target_reserve_b: Decimal::from(3_000_000u64),
+            current_reserve_a: Decimal::from(1_000_000u64),
+            current_reserve_b: Decimal::from(3_000_000u64),
+        };
+        let config = OracleConfig::default();
+        let input = Decimal::from(1_000u64);
+        for direction in [SwapDirection::AtoB, SwapDirection::BtoA].iter() {
+            let fresh = oracle_price(0)
+                .priced_pool(&pool, &config, 100, *direction)
+                .unwrap();
+            let uncertain = oracle_price(30_000_000_000)
+                .priced_pool(&pool, &config, 110, *direction)
+                .unwrap();
+            let fresh_out = CurveType::Oracle.swap_exact_in(&fresh, input).unwrap();
+            let uncertain_out = CurveType::Oracle.swap_exact_in(&uncertain, input).unwrap();
+            assert!(uncertain_out < fresh_out);
+        }
+    }
+}
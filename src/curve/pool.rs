@@ -0,0 +1,544 @@
+//! Pool state consumed by the curves
+
+use crate::{
+    curve::{
+        calc::{simple_powf, simple_powf_exact_out, simple_powf_marginal_price},
+        decimals::TokenDecimals,
+        stable::{
+            stable_marginal_price, stable_swap, stable_swap_exact_out, validate_stable_price,
+        },
+        weighted::{weighted_spot_price, weighted_swap_exact_in, weighted_swap_exact_out},
+    },
+    error::SwapError,
+    math::{Decimal, Rate, TryAdd, TryDiv, TryMul, TrySub, SCALE},
+    state::MockedSwap,
+};
+use solana_program::program_error::ProgramError;
+use std::convert::TryFrom;
+
+/// Basis points in one
+pub const BPS_SCALER: u64 = 10_000;
+/// Maximum decimals of a token mint, finer amounts than the `Decimal` scale can not be priced
+pub const MAX_TOKEN_DECIMALS: u8 = SCALE as u8;
+
+/// Direction of a swap
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SwapDirection {
+    /// sell token a for token b
+    AtoB,
+    /// sell token b for token a
+    BtoA,
+}
+
+/// Reserves and oracle price of a two-token pool
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PoolState {
+    /// market price of token a in token b
+    pub market_price: Decimal,
+    /// target reserve a
+    pub target_reserve_a: Decimal,
+    /// target reserve b
+    pub target_reserve_b: Decimal,
+    /// current reserve a
+    pub current_reserve_a: Decimal,
+    /// current reserve b
+    pub current_reserve_b: Decimal,
+}
+
+impl TryFrom<&MockedSwap> for PoolState {
+    type Error = ProgramError;
+
+    /// Pool in the common scale of the token decimals, priced in whole tokens
+    fn try_from(mocked_swap: &MockedSwap) -> Result<Self, Self::Error> {
+        TokenDecimals::new(mocked_swap.token_a_decimals, mocked_swap.token_b_decimals)?
+            .normalize_reserves(&Self {
+                market_price: mocked_swap.market_price,
+                target_reserve_a: mocked_swap.target_reserve_a,
+                target_reserve_b: mocked_swap.target_reserve_b,
+                current_reserve_a: mocked_swap.current_reserve_a,
+                current_reserve_b: mocked_swap.current_reserve_b,
+            })
+    }
+}
+
+/// Price of one raw unit of token a in raw units of token b, from `price` quoted in whole
+/// tokens. A whole token a is `10^token_a_decimals` raw units.
+pub fn raw_price(
+    price: Decimal,
+    token_a_decimals: u8,
+    token_b_decimals: u8,
+) -> Result<Decimal, ProgramError> {
+    if token_a_decimals > MAX_TOKEN_DECIMALS || token_b_decimals > MAX_TOKEN_DECIMALS {
+        return Err(SwapError::InvalidTokenDecimals.into());
+    }
+    if token_b_decimals >= token_a_decimals {
+        price.try_mul(10u64.pow((token_b_decimals - token_a_decimals) as u32))
+    } else {
+        price.try_div(10u64.pow((token_a_decimals - token_b_decimals) as u32))
+    }
+}
+
+/// Smallest amount at or above the `estimate` of a quote that `covers`, which must hold for
+/// every amount above the smallest one. The estimate can fall short by any amount after
+/// float rounding, so the step doubles until the quote covers and is then bisected back.
+pub fn round_up_quote<F>(estimate: u64, covers: F) -> Result<u64, ProgramError>
+where
+    F: Fn(u64) -> Result<bool, ProgramError>,
+{
+    if covers(estimate)? {
+        return Ok(estimate);
+    }
+    let mut short = estimate;
+    let mut step = 1u64;
+    let mut enough = loop {
+        let candidate = short
+            .checked_add(step)
+            .ok_or(SwapError::CalculationFailure)?;
+        if covers(candidate)? {
+            break candidate;
+        }
+        short = candidate;
+        step = step.checked_mul(2).ok_or(SwapError::CalculationFailure)?;
+    };
+    while enough - short > 1 {
+        let middle = short + (enough - short) / 2;
+        if covers(middle)? {
+            enough = middle;
+        } else {
+            short = middle;
+        }
+    }
+    Ok(enough)
+}
+
+/// Curve used to price swaps in a pool
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CurveType {
+    /// Oracle curve with exponent `market_price * target_reserve_a / target_reserve_b`
+    Oracle,
+    /// StableSwap invariant with amplification coefficient
+    Stable {
+        /// amplification coefficient
+        amp: u64,
+    },
+    /// Two-token weighted pool with exponent `weight_a / weight_b`
+    Weighted {
+        /// weight of token a
+        weight_a: Decimal,
+        /// weight of token b
+        weight_b: Decimal,
+    },
+}
+
+/// Price impact of a swap
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PriceImpact {
+    /// output amount divided by input amount
+    pub effective_price: Decimal,
+    /// marginal price before the swap
+    pub spot_price: Decimal,
+    /// relative difference between spot and effective price in basis points
+    pub impact_bps: Rate,
+}
+
+impl PoolState {
+    /// Pool state seen from the other side, with token a and token b swapped
+    pub fn flipped(&self) -> Result<Self, ProgramError> {
+        Ok(Self {
+            market_price: self.market_price.reciprocal()?,
+            target_reserve_a: self.target_reserve_b,
+            target_reserve_b: self.target_reserve_a,
+            current_reserve_a: self.current_reserve_b,
+            current_reserve_b: self.current_reserve_a,
+        })
+    }
+
+    /// Pool state oriented so that the input token is token a
+    pub fn oriented(&self, direction: SwapDirection) -> Result<Self, ProgramError> {
+        match direction {
+            SwapDirection::AtoB => Ok(*self),
+            SwapDirection::BtoA => self.flipped(),
+        }
+    }
+
+    /// Pool state after token a is deposited and token b is paid out
+    pub fn after_swap(
+        &self,
+        input_a_amount: Decimal,
+        output_b_amount: Decimal,
+    ) -> Result<Self, ProgramError> {
+        Ok(Self {
+            current_reserve_a: self.current_reserve_a.try_add(input_a_amount)?,
+            current_reserve_b: self.current_reserve_b.try_sub(output_b_amount)?,
+            ..*self
+        })
+    }
+}
+
+impl CurveType {
+    /// Curve oriented so that the input token is token a
+    pub fn oriented(&self, direction: SwapDirection) -> Self {
+        match (*self, direction) {
+            (CurveType::Weighted { weight_a, weight_b }, SwapDirection::BtoA) => {
+                CurveType::Weighted {
+                    weight_a: weight_b,
+                    weight_b: weight_a,
+                }
+            }
+            (curve, _) => curve,
+        }
+    }
+
+    /// Amount of token b received for an exact input of token a, in whole units of the scale
+    /// of the pool rounded down. Normalized amounts do not fit a `u64` for every pair of
+    /// decimals, so callers denormalize before converting.
+    pub fn swap_exact_in(
+        &self,
+        pool: &PoolState,
+        input_a_amount: Decimal,
+    ) -> Result<Decimal, ProgramError> {
+        match *self {
+            CurveType::Oracle => simple_powf(
+                pool.market_price,
+                pool.target_reserve_a,
+                pool.target_reserve_b,
+                pool.current_reserve_a,
+                pool.current_reserve_b,
+                input_a_amount,
+            ),
+            CurveType::Stable { amp } => {
+                validate_stable_price(pool.market_price)?;
+                stable_swap(
+                    amp,
+                    pool.current_reserve_a,
+                    pool.current_reserve_b,
+                    input_a_amount,
+                )
+            }
+            CurveType::Weighted { weight_a, weight_b } => weighted_swap_exact_in(
+                pool.current_reserve_a,
+                weight_a,
+                pool.current_reserve_b,
+                weight_b,
+                input_a_amount,
+            ),
+        }
+    }
+
+    /// Amount of token a required to receive an exact output of token b.
+    /// The quote is rounded up until `swap_exact_in` delivers at least `output_b_amount`.
+    pub fn swap_exact_out(
+        &self,
+        pool: &PoolState,
+        output_b_amount: Decimal,
+    ) -> Result<u64, ProgramError> {
+        if output_b_amount >= pool.current_reserve_b {
+            return Err(SwapError::InsufficientLiquidity.into());
+        }
+        let input = match *self {
+            CurveType::Oracle => simple_powf_exact_out(
+                pool.market_price,
+                pool.target_reserve_a,
+                pool.target_reserve_b,
+                pool.current_reserve_a,
+                pool.current_reserve_b,
+                output_b_amount,
+            )?,
+            CurveType::Stable { amp } => {
+                validate_stable_price(pool.market_price)?;
+                stable_swap_exact_out(
+                    amp,
+                    pool.current_reserve_a,
+                    pool.current_reserve_b,
+                    output_b_amount,
+                )?
+            }
+            CurveType::Weighted { weight_a, weight_b } => weighted_swap_exact_out(
+                pool.current_reserve_a,
+                weight_a,
+                pool.current_reserve_b,
+                weight_b,
+                output_b_amount,
+            )?,
+        };
+
+        round_up_quote(input, |input| {
+            Ok(self.swap_exact_in(pool, Decimal::from(input))? >= output_b_amount)
+        })
+    }
+
+    /// Instantaneous price of token a in token b at the current reserves
+    pub fn marginal_price(&self, pool: &PoolState) -> Result<Decimal, ProgramError> {
+        match *self {
+            CurveType::Oracle => simple_powf_marginal_price(
+                pool.market_price,
+                pool.target_reserve_a,
+                pool.target_reserve_b,
+                pool.current_reserve_a,
+                pool.current_reserve_b,
+            ),
+            CurveType::Stable { amp } => {
+                validate_stable_price(pool.market_price)?;
+                stable_marginal_price(amp, pool.current_reserve_a, pool.current_reserve_b)
+            }
+            CurveType::Weighted { weight_a, weight_b } => weighted_spot_price(
+                pool.current_reserve_a,
+                weight_a,
+                pool.current_reserve_b,
+                weight_b,
+            ),
+        }
+    }
+
+    /// Effective price, spot price and price impact of swapping `input_a_amount`
+    pub fn price_impact(
+        &self,
+        pool: &PoolState,
+        input_a_amount: Decimal,
+    ) -> Result<PriceImpact, ProgramError> {
+        if input_a_amount.is_zero() {
+            return Err(SwapError::InvalidInput.into());
+        }
+        let spot_price = self.marginal_price(pool)?;
+        let output = self.swap_exact_in(pool, input_a_amount)?;
+        let effective_price = output.try_div(input_a_amount)?;
+        let impact_bps = calculate_impact_bps(spot_price, effective_price)?;
+
+        Ok(PriceImpact {
+            effective_price,
+            spot_price,
+            impact_bps,
+        })
+    }
+}
+
+/// Relative shortfall of `effective_price` against `spot_price` in basis points
+pub fn calculate_impact_bps(
+    spot_price: Decimal,
+    effective_price: Decimal,
+) -> Result<Rate, ProgramError> {
+    if spot_price.is_zero() {
+        return Err(SwapError::CalculationFailure.into());
+    }
+    // rounding can make the effective price marginally better than spot
+    if effective_price >= spot_price {
+        return Ok(Rate::zero());
+    }
+    Rate::try_from(
+        spot_price
+            .try_sub(effective_price)?
+            .try_mul(BPS_SCALER)?
+            .try_div(spot_price)?,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::WAD;
+
+    fn oracle_pool() -> PoolState {
+        PoolState {
+            market_price: Decimal::from(3u64),
+            target_reserve_a: Decimal::from(1_000_000u64),
+            target_reserve_b: Decimal::from(3_000_000u64),
+            current_reserve_a: Decimal::from(1_000_000u64),
+            current_reserve_b: Decimal::from(3_000_000u64),
+        }
+    }
+
+    #[test]
+    fn test_raw_price() {
+        let price = Decimal::from_scaled_val(100_250_000_000_000);
+        assert_eq!(raw_price(price, 6, 6).unwrap(), price);
+        // 100.25 usdc(6) per sol(9) is 0.10025 raw usdc per lamport
+        assert_eq!(
+            raw_price(price, 9, 6).unwrap(),
+            Decimal::from_scaled_val(100_250_000_000)
+        );
+        assert_eq!(raw_price(price, 6, 9).unwrap(), Decimal::from(100_250u64));
+        assert_eq!(
+            raw_price(price, 13, 6).unwrap_err(),
+            SwapError::InvalidTokenDecimals.into()
+        );
+    }
+
+    #[test]
+    fn test_mocked_swap_pool_is_normalized() {
+        // 1_000 sol(9) against 100_250 usdc(6), a price finer than a raw usdc per lamport
+        let price = Decimal::from_scaled_val(100_250_001_234);
+        let pool = PoolState::try_from(&MockedSwap {
+            market_price: price,
+            target_reserve_a: Decimal::from(1_000_000_000_000u64),
+            target_reserve_b: Decimal::from(100_250_000_000u64),
+            current_reserve_a: Decimal::from(1_000_000_000_000u64),
+            current_reserve_b: Decimal::from(100_250_000_000u64),
+            token_a_decimals: 9,
+            token_b_decimals: 6,
+            ..MockedSwap::default()
+        })
+        .unwrap();
+        // the whole token price is kept, the raw price would lose its last digits
+        assert_eq!(pool.market_price, price);
+        assert_ne!(
+            TokenDecimals::new(9, 6)
+                .unwrap()
+                .normalize_pool(&PoolState {
+                    market_price: raw_price(price, 9, 6).unwrap(),
+                    ..pool
+                })
+                .unwrap()
+                .market_price,
+            price
+        );
+        assert_eq!(pool.current_reserve_a, Decimal::from(1_000_000_000_000u64));
+        assert_eq!(
+            pool.current_reserve_b,
+            Decimal::from(100_250_000_000_000u64)
+        );
+    }
+
+    #[test]
+    fn test_oracle_price_impact() {
+        let pool = oracle_pool();
+        let curve = CurveType::Oracle;
+        assert_eq!(curve.marginal_price(&pool).unwrap(), Decimal::from(3u64));
+
+        let small = curve.price_impact(&pool, Decimal::from(1_000u64)).unwrap();
+        assert_eq!(small.spot_price, Decimal::from(3u64));
+        // 1000 in -> 2997 out, i.e. 10 bps
+        assert_eq!(
+            small.effective_price,
+            Decimal::from_scaled_val(2_997_000_000_000)
+        );
+        assert_eq!(small.impact_bps, Rate::from_scaled_val(10_000_000_000_000));
+
+        let large = curve
+            .price_impact(&pool, Decimal::from(100_000u64))
+            .unwrap();
+        assert!(large.impact_bps > small.impact_bps);
+    }
+
+    #[test]
+    fn test_stable_price_impact() {
+        let mut pool = oracle_pool();
+        pool.market_price = Decimal::one();
+        pool.current_reserve_b = pool.current_reserve_a;
+        let curve = CurveType::Stable { amp: 100 };
+
+        let impact = curve.price_impact(&pool, Decimal::from(10_000u64)).unwrap();
+        assert_eq!(impact.spot_price.try_round_u64().unwrap(), 1);
+        // a balanced stable pool trades within a basis point
+        assert!(impact.impact_bps <= Rate::one());
+
+        pool.market_price = Decimal::from(3u64);
+        assert_eq!(
+            curve.marginal_price(&pool).unwrap_err(),
+            SwapError::IncorrectStablePrice.into()
+        );
+    }
+
+    #[test]
+    fn test_swap_exact_out() {
+        let pool = oracle_pool();
+        let weighted = CurveType::Weighted {
+            weight_a: Decimal::from_percent(80),
+            weight_b: Decimal::from_percent(20),
+        };
+        for curve in [CurveType::Oracle, CurveType::Stable { amp: 100 }, weighted] {
+            let pool = if curve != (CurveType::Stable { amp: 100 }) {
+                pool
+            } else {
+                PoolState {
+                    market_price: Decimal::one(),
+                    current_reserve_b: pool.current_reserve_a,
+                    ..pool
+                }
+            };
+            let input = curve
+                .swap_exact_out(&pool, Decimal::from(50_000u64))
+                .unwrap();
+            let output = curve.swap_exact_in(&pool, Decimal::from(input)).unwrap();
+            assert!(output >= Decimal::from(50_000u64));
+            let less = curve
+                .swap_exact_in(&pool, Decimal::from(input - 1))
+                .unwrap();
+            assert!(less < Decimal::from(50_000u64));
+        }
+        assert_eq!(
+            CurveType::Oracle
+                .swap_exact_out(&pool, pool.current_reserve_b)
+                .unwrap_err(),
+            SwapError::InsufficientLiquidity.into()
+        );
+    }
+
+    #[test]
+    fn test_round_up_quote() {
+        let covers = |amount: u64| Ok(amount >= 1_000_003);
+        assert_eq!(round_up_quote(1_000_003, covers).unwrap(), 1_000_003);
+        assert_eq!(round_up_quote(2_000_000, covers).unwrap(), 2_000_000);
+        assert_eq!(round_up_quote(999_999, covers).unwrap(), 1_000_003);
+        assert_eq!(round_up_quote(0, covers).unwrap(), 1_000_003);
+        assert_eq!(
+            round_up_quote(u64::MAX - 1, |_| Ok(false)).unwrap_err(),
+            SwapError::CalculationFailure.into()
+        );
+    }
+
+    #[test]
+    fn test_swap_exact_out_large_reserves() {
+        // the float estimate of reserves this large is off by far more than a few units
+        let pool = PoolState {
+            market_price: Decimal::from(3u64),
+            target_reserve_a: Decimal::from(1_000_000_000_000_000u64),
+            target_reserve_b: Decimal::from(3_000_000_000_000_000u64),
+            current_reserve_a: Decimal::from(1_000_000_000_000_000u64),
+            current_reserve_b: Decimal::from(3_000_000_000_000_000u64),
+        };
+        let output = Decimal::from(123_456_789_012u64);
+        let input = CurveType::Oracle.swap_exact_out(&pool, output).unwrap();
+        assert!(
+            CurveType::Oracle
+                .swap_exact_in(&pool, Decimal::from(input))
+                .unwrap()
+                >= output
+        );
+        assert!(
+            CurveType::Oracle
+                .swap_exact_in(&pool, Decimal::from(input - 1))
+                .unwrap()
+                < output
+        );
+    }
+
+    #[test]
+    fn test_flipped() {
+        let pool = oracle_pool();
+        let flipped = pool.flipped().unwrap();
+        assert_eq!(flipped.current_reserve_a, pool.current_reserve_b);
+        assert_eq!(flipped.target_reserve_b, pool.target_reserve_a);
+        // 3000 b buys roughly 1000 a
+        let output = CurveType::Oracle
+            .swap_exact_in(&flipped, Decimal::from(3_000u64))
+            .unwrap();
+        assert_eq!(output, Decimal::from(999u64));
+        assert_eq!(
+            flipped.flipped().unwrap().current_reserve_a,
+            pool.current_reserve_a
+        );
+    }
+
+    #[test]
+    fn test_calculate_impact_bps() {
+        assert_eq!(
+            calculate_impact_bps(Decimal::from(2u64), Decimal::one()).unwrap(),
+            Rate::from_scaled_val(5_000 * WAD as u128)
+        );
+        assert_eq!(
+            calculate_impact_bps(Decimal::one(), Decimal::from(2u64)).unwrap(),
+            Rate::zero()
+        );
+        assert!(calculate_impact_bps(Decimal::zero(), Decimal::one()).is_err());
+    }
+}
@@ -0,0 +1,256 @@
+//! Target reserve rebalancing for the oracle curve
+//!
+//! The oracle curve exponent `market_price * target_reserve_a / target_reserve_b` is the
+//! ratio of the value held in token a to the value held in token b at the targets.
+//! Rebalancing keeps that split, and moves the targets so their value at the oracle price
+//! equals the value of the current reserves.
+
+use crate::{
+    curve::pool::PoolState,
+    error::SwapError,
+    math::{Decimal, TryAdd, TryDiv, TryMul, TrySub},
+};
+use solana_program::program_error::ProgramError;
+
+/// Value of `reserve_a` and `reserve_b` in token b at `market_price`
+pub fn value_at_price(
+    reserve_a: Decimal,
+    reserve_b: Decimal,
+    market_price: Decimal,
+) -> Result<Decimal, ProgramError> {
+    reserve_a.try_mul(market_price)?.try_add(reserve_b)
+}
+
+/// Value of the current reserves in token b at the pool market price
+pub fn current_value(pool: &PoolState) -> Result<Decimal, ProgramError> {
+    value_at_price(
+        pool.current_reserve_a,
+        pool.current_reserve_b,
+        pool.market_price,
+    )
+}
+
+/// Value of the target reserves in token b at the pool market price
+pub fn target_value(pool: &PoolState) -> Result<Decimal, ProgramError> {
+    value_at_price(
+        pool.target_reserve_a,
+        pool.target_reserve_b,
+        pool.market_price,
+    )
+}
+
+/// Recompute target reserves for `new_market_price`.
+/// The share of value held in token a at the targets is preserved and the new targets
+/// are worth as much as the current reserves at the new price.
+pub fn rebalance_targets(
+    pool: &PoolState,
+    new_market_price: Decimal,
+) -> Result<PoolState, ProgramError> {
+    if new_market_price.is_zero() {
+        return Err(SwapError::InvalidInput.into());
+    }
+    let value_share_a = pool
+        .target_reserve_a
+        .try_mul(pool.market_price)?
+        .try_div(target_value(pool)?)?;
+    let value = value_at_price(
+        pool.current_reserve_a,
+        pool.current_reserve_b,
+        new_market_price,
+    )?;
+
+    let target_reserve_a = value.try_mul(value_share_a)?.try_div(new_market_price)?;
+    let target_reserve_b = value.try_sub(target_reserve_a.try_mul(new_market_price)?)?;
+
+    Ok(PoolState {
+        market_price: new_market_price,
+        target_reserve_a,
+        target_reserve_b,
+        ..*pool
+    })
+}
+
+/// Add a deposit to the current reserves and scale the targets by the same value ratio,
+/// so existing liquidity providers keep their share of the targets
+pub fn deposit_to_targets(
+    pool: &PoolState,
+    amount_a: Decimal,
+    amount_b: Decimal,
+) -> Result<PoolState, ProgramError> {
+    let value_before = current_value(pool)?;
+    let deposit_value = value_at_price(amount_a, amount_b, pool.market_price)?;
+    let value_after = value_before.try_add(deposit_value)?;
+    scale_targets(
+        &PoolState {
+            current_reserve_a: pool.current_reserve_a.try_add(amount_a)?,
+            current_reserve_b: pool.current_reserve_b.try_add(amount_b)?,
+            ..*pool
+        },
+        value_before,
+        value_after,
+    )
+}
+
+/// Remove a withdrawal from the current reserves and scale the targets down by the same
+/// value ratio
+pub fn withdraw_from_targets(
+    pool: &PoolState,
+    amount_a: Decimal,
+    amount_b: Decimal,
+) -> Result<PoolState, ProgramError> {
+    if amount_a > pool.current_reserve_a || amount_b > pool.current_reserve_b {
+        return Err(SwapError::WithdrawNotEnough.into());
+    }
+    let value_before = current_value(pool)?;
+    let withdraw_value = value_at_price(amount_a, amount_b, pool.market_price)?;
+    let value_after = value_before.try_sub(withdraw_value)?;
+    scale_targets(
+        &PoolState {
+            current_reserve_a: pool.current_reserve_a.try_sub(amount_a)?,
+            current_reserve_b: pool.current_reserve_b.try_sub(amount_b)?,
+            ..*pool
+        },
+        value_before,
+        value_after,
+    )
+}
+
+fn scale_targets(
+    pool: &PoolState,
+    value_before: Decimal,
+    value_after: Decimal,
+) -> Result<PoolState, ProgramError> {
+    if value_before.is_zero() {
+        return Err(SwapError::EmptyPool.into());
+    }
+    Ok(PoolState {
+        target_reserve_a: pool
+            .target_reserve_a
+            .try_mul(value_after)?
+            .try_div(value_before)?,
+        target_reserve_b: pool
+            .target_reserve_b
+            .try_mul(value_after)?
+            .try_div(value_before)?,
+        ..*pool
+    })
+}
+
+/// Check the targets are worth the current reserves at the market price, within
+/// `tolerance` token b units of rounding
+pub fn check_value_conserved(pool: &PoolState, tolerance: Decimal) -> Result<(), ProgramError> {
+    let current = current_value(pool)?;
+    let target = target_value(pool)?;
+    let difference = if current > target {
+        current.try_sub(target)?
+    } else {
+        target.try_sub(current)?
+    };
+    if difference > tolerance {
+        return Err(SwapError::InconsistentPoolState.into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::MockedSwap;
+    use std::convert::TryFrom;
+
+    fn tolerance() -> Decimal {
+        Decimal::from_scaled_val(1_000)
+    }
+
+    fn pool() -> PoolState {
+        PoolState::try_from(&MockedSwap {
+            target_reserve_a: Decimal::from(1_000_000u64),
+            target_reserve_b: Decimal::from(3_000_000u64),
+            current_reserve_a: Decimal::from(1_100_000u64),
+            current_reserve_b: Decimal::from(2_710_000u64),
+            market_price: Decimal::from(3u64),
+            ..MockedSwap::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_rebalance_targets() {
+        let pool = pool();
+        let rebalanced = rebalance_targets(&pool, Decimal::from(4u64)).unwrap();
+
+        // value of current reserves at the new price: 1_100_000 * 4 + 2_710_000
+        assert_eq!(
+            target_value(&rebalanced).unwrap(),
+            Decimal::from(7_110_000u64)
+        );
+        check_value_conserved(&rebalanced, tolerance()).unwrap();
+
+        // the exponent, i.e. the value split of the targets, is unchanged
+        let exp_before = pool
+            .market_price
+            .try_mul(pool.target_reserve_a)
+            .unwrap()
+            .try_div(pool.target_reserve_b)
+            .unwrap();
+        let exp_after = rebalanced
+            .market_price
+            .try_mul(rebalanced.target_reserve_a)
+            .unwrap()
+            .try_div(rebalanced.target_reserve_b)
+            .unwrap();
+        assert_eq!(exp_before, exp_after);
+        assert_eq!(rebalanced.current_reserve_a, pool.current_reserve_a);
+
+        assert_eq!(
+            rebalance_targets(&pool, Decimal::zero()).unwrap_err(),
+            SwapError::InvalidInput.into()
+        );
+    }
+
+    #[test]
+    fn test_liquidity_changes_scale_targets() {
+        let pool = rebalance_targets(&pool(), Decimal::from(3u64)).unwrap();
+        let deposited =
+            deposit_to_targets(&pool, Decimal::from(110_000u64), Decimal::from(271_000u64))
+                .unwrap();
+        // a 10% proportional deposit grows the targets by 10%
+        assert_eq!(
+            deposited.target_reserve_a,
+            pool.target_reserve_a
+                .try_mul(Decimal::from_scaled_val(1_100_000_000_000))
+                .unwrap()
+        );
+        check_value_conserved(&deposited, tolerance()).unwrap();
+
+        let withdrawn = withdraw_from_targets(
+            &deposited,
+            Decimal::from(110_000u64),
+            Decimal::from(271_000u64),
+        )
+        .unwrap();
+        check_value_conserved(&withdrawn, tolerance()).unwrap();
+        assert_eq!(withdrawn.current_reserve_a, pool.current_reserve_a);
+        let (low, high) = if withdrawn.target_reserve_b < pool.target_reserve_b {
+            (withdrawn.target_reserve_b, pool.target_reserve_b)
+        } else {
+            (pool.target_reserve_b, withdrawn.target_reserve_b)
+        };
+        assert!(high.try_sub(low).unwrap() < tolerance());
+
+        assert_eq!(
+            withdraw_from_targets(&pool, Decimal::from(2_000_000u64), Decimal::zero()).unwrap_err(),
+            SwapError::WithdrawNotEnough.into()
+        );
+    }
+
+    #[test]
+    fn test_check_value_conserved() {
+        // the unbalanced pool is worth 6_010_000 against 6_000_000 at the targets
+        assert_eq!(
+            check_value_conserved(&pool(), Decimal::from(1_000u64)).unwrap_err(),
+            SwapError::InconsistentPoolState.into()
+        );
+        check_value_conserved(&pool(), Decimal::from(10_000u64)).unwrap();
+    }
+}
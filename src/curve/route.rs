@@ -0,0 +1,260 @@
+//! Multi-hop route quoting across several pools
+//!
+//! Amounts passed between hops are raw amounts of the shared mint. Each hop normalizes its
+//! input to the common scale of its own pool and denormalizes its output before fees.
+
+use crate::{
+    curve::{
+        decimals::TokenDecimals,
+        fees::{FeeBreakdown, Fees},
+        pool::{CurveType, PoolState, SwapDirection},
+    },
+    error::SwapError,
+    math::Decimal,
+};
+use solana_program::program_error::ProgramError;
+
+/// A single pool in a route
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hop {
+    /// pool state before the route is executed, in the common scale of `decimals`
+    pub pool: PoolState,
+    /// decimals of the pool mints
+    pub decimals: TokenDecimals,
+    /// curve pricing the pool
+    pub curve: CurveType,
+    /// fees charged by the pool
+    pub fees: Fees,
+    /// direction the pool is traded in
+    pub direction: SwapDirection,
+}
+
+/// Amounts of a single hop
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HopQuote {
+    /// raw amount sold into the pool
+    pub amount_in: u64,
+    /// raw amount received from the pool after fees
+    pub amount_out: u64,
+    /// fees charged by the pool, in raw units of the output
+    pub fees: FeeBreakdown,
+}
+
+/// Amounts of a whole route
+#[derive(Clone, Debug, PartialEq)]
+pub struct RouteQuote {
+    /// amount sold into the first pool
+    pub amount_in: u64,
+    /// amount received from the last pool
+    pub amount_out: u64,
+    /// per-hop breakdown, in route order
+    pub hops: Vec<HopQuote>,
+}
+
+impl Hop {
+    /// Quote an exact input into this hop
+    pub fn quote_exact_in(&self, amount_in: u64) -> Result<HopQuote, ProgramError> {
+        let pool = self.pool.oriented(self.direction)?;
+        let curve = self.curve.oriented(self.direction);
+        let decimals = self.decimals.oriented(self.direction);
+        let output = curve.swap_exact_in(&pool, decimals.normalize_a(amount_in)?)?;
+        let fees = self
+            .fees
+            .apply(decimals.denormalize_b_floor(output)?, false)?;
+        Ok(HopQuote {
+            amount_in,
+            amount_out: fees.amount_out,
+            fees,
+        })
+    }
+
+    /// Input required for this hop to pay out at least `amount_out` after fees
+    pub fn required_input(&self, amount_out: u64) -> Result<u64, ProgramError> {
+        let pool = self.pool.oriented(self.direction)?;
+        let curve = self.curve.oriented(self.direction);
+        let decimals = self.decimals.oriented(self.direction);
+        let output = self.fees.output_before_fees(amount_out)?;
+        let input = curve.swap_exact_out(&pool, decimals.normalize_b(output)?)?;
+        decimals.denormalize_a_ceil(Decimal::from(input))
+    }
+}
+
+/// Chain exact-in quotes through `hops`, each hop selling the previous hop's output
+pub fn quote_exact_in(hops: &[Hop], amount_in: u64) -> Result<RouteQuote, ProgramError> {
+    if hops.is_empty() {
+        return Err(SwapError::InvalidInput.into());
+    }
+    let mut quotes = Vec::with_capacity(hops.len());
+    let mut amount = amount_in;
+    for hop in hops {
+        let quote = hop.quote_exact_in(amount)?;
+        amount = quote.amount_out;
+        quotes.push(quote);
+    }
+
+    Ok(RouteQuote {
+        amount_in,
+        amount_out: amount,
+        hops: quotes,
+    })
+}
+
+/// Quote the input needed to receive at least `amount_out` from the last hop.
+/// Required amounts are propagated backwards from the last hop, then the route is
+/// re-quoted forwards so the breakdown reflects the amounts actually received.
+pub fn quote_exact_out(hops: &[Hop], amount_out: u64) -> Result<RouteQuote, ProgramError> {
+    if hops.is_empty() {
+        return Err(SwapError::InvalidInput.into());
+    }
+    let mut amount = amount_out;
+    for hop in hops.iter().rev() {
+        amount = hop.required_input(amount)?;
+    }
+
+    let quote = quote_exact_in(hops, amount)?;
+    if quote.amount_out < amount_out {
+        return Err(SwapError::CalculationFailure.into());
+    }
+    Ok(quote)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::MockedSwap;
+    use std::convert::TryFrom;
+
+    fn hops() -> Vec<Hop> {
+        // a -> b through an oracle pool priced at 3, b -> c through a stable pool
+        let oracle_pool = PoolState::try_from(&MockedSwap {
+            target_reserve_a: Decimal::from(1_000_000u64),
+            target_reserve_b: Decimal::from(3_000_000u64),
+            current_reserve_a: Decimal::from(1_000_000u64),
+            current_reserve_b: Decimal::from(3_000_000u64),
+            market_price: Decimal::from(3u64),
+            ..MockedSwap::default()
+        })
+        .unwrap();
+        let stable_pool = PoolState::try_from(&MockedSwap {
+            target_reserve_a: Decimal::from(5_000_000u64),
+            target_reserve_b: Decimal::from(5_000_000u64),
+            current_reserve_a: Decimal::from(5_000_000u64),
+            current_reserve_b: Decimal::from(5_000_000u64),
+            market_price: Decimal::from(1u64),
+            ..MockedSwap::default()
+        })
+        .unwrap();
+        vec![
+            Hop {
+                pool: oracle_pool,
+                curve: CurveType::Oracle,
+                decimals: TokenDecimals::default(),
+                fees: Fees::default(),
+                direction: SwapDirection::AtoB,
+            },
+            Hop {
+                pool: stable_pool,
+                curve: CurveType::Stable { amp: 100 },
+                decimals: TokenDecimals::default(),
+                fees: Fees::default(),
+                direction: SwapDirection::AtoB,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_quote_exact_in() {
+        let hops = hops();
+        let quote = quote_exact_in(&hops, 1_000).unwrap();
+        assert_eq!(quote.hops.len(), 2);
+        assert_eq!(quote.amount_in, 1_000);
+        // 2_997 out of the first pool less a 9 fee
+        assert_eq!(quote.hops[0].amount_out, 2_988);
+        assert_eq!(quote.hops[1].amount_in, 2_988);
+        assert_eq!(quote.amount_out, quote.hops[1].amount_out);
+        assert!(quote.amount_out < 2_988);
+
+        assert_eq!(
+            quote_exact_in(&[], 1_000).unwrap_err(),
+            SwapError::InvalidInput.into()
+        );
+    }
+
+    #[test]
+    fn test_quote_exact_out() {
+        let hops = hops();
+        let quote = quote_exact_out(&hops, 100_000).unwrap();
+        assert!(quote.amount_out >= 100_000);
+        assert!(
+            quote_exact_in(&hops, quote.amount_in - 1)
+                .unwrap()
+                .amount_out
+                < 100_000
+        );
+    }
+
+    #[test]
+    fn test_reverse_route() {
+        let mut hops = hops();
+        hops.reverse();
+        for hop in hops.iter_mut() {
+            hop.direction = SwapDirection::BtoA;
+        }
+        // c -> b -> a, 3_000 c buys a little less than 1_000 a
+        let quote = quote_exact_in(&hops, 3_000).unwrap();
+        assert!(quote.amount_out < 1_000 && quote.amount_out > 980);
+    }
+
+    #[test]
+    fn test_route_across_decimals() {
+        // a of 6 decimals -> b of 12 decimals at par, b -> c of 0 decimals at 1_000 c per b
+        let pool = |token_a_decimals: u8, token_b_decimals: u8, price: u64| {
+            let reserve_a = 10u64.pow(6 + token_a_decimals as u32);
+            let reserve_b = price * 10u64.pow(6 + token_b_decimals as u32);
+            PoolState::try_from(&MockedSwap {
+                target_reserve_a: Decimal::from(reserve_a),
+                target_reserve_b: Decimal::from(reserve_b),
+                current_reserve_a: Decimal::from(reserve_a),
+                current_reserve_b: Decimal::from(reserve_b),
+                market_price: Decimal::from(price),
+                token_a_decimals,
+                token_b_decimals,
+                ..MockedSwap::default()
+            })
+            .unwrap()
+        };
+        let hops = [
+            Hop {
+                pool: pool(6, 12, 1),
+                curve: CurveType::Oracle,
+                decimals: TokenDecimals::new(6, 12).unwrap(),
+                fees: Fees::default(),
+                direction: SwapDirection::AtoB,
+            },
+            Hop {
+                pool: pool(12, 0, 1_000),
+                curve: CurveType::Oracle,
+                decimals: TokenDecimals::new(12, 0).unwrap(),
+                fees: Fees::default(),
+                direction: SwapDirection::AtoB,
+            },
+        ];
+
+        // 30_000 a buys a bit less than 30_000 b, the c out is more than a u64 in the
+        // common scale of the second pool
+        let quote = quote_exact_in(&hops, 30_000_000_000).unwrap();
+        let b_amount = quote.hops[0].amount_out;
+        assert!(b_amount < 30_000_000_000_000_000 && b_amount > 29_000_000_000_000_000);
+        assert_eq!(quote.hops[1].amount_in, b_amount);
+        assert!(quote.amount_out < 30_000_000 && quote.amount_out > 28_000_000);
+
+        let quote = quote_exact_out(&hops, 20_000_000).unwrap();
+        assert!(quote.amount_out >= 20_000_000);
+        assert!(
+            quote_exact_in(&hops, quote.amount_in - 1)
+                .unwrap()
+                .amount_out
+                < 20_000_000
+        );
+    }
+}
@@ -0,0 +1,238 @@
+//! Off-chain simulation of a trade sequence against a pool
+//!
+//! Each trade is priced by the curve, charged fees and checked by the invariant verifier
+//! the same way the processor does, then applied to the pool state. The admin and
+//! referrer fees leave the pool while the liquidity provider fee stays in it.
+
+use crate::{
+    curve::{
+        fees::{swap_with_fees, FeeBreakdown, Fees},
+        pool::{CurveType, PoolState, SwapDirection},
+        rebalance::current_value,
+        verify::verify_swap,
+    },
+    math::{Decimal, TryAdd, TrySub},
+    state::MockedSwap,
+};
+use solana_program::program_error::ProgramError;
+use std::convert::TryFrom;
+
+/// A trade to replay
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Trade {
+    /// direction of the trade
+    pub direction: SwapDirection,
+    /// amount sold into the pool
+    pub amount_in: u64,
+    /// whether a referrer takes a share of the fee
+    pub has_referrer: bool,
+}
+
+/// Pool state after a replayed trade
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SimulationStep {
+    /// the trade replayed in this step
+    pub trade: Trade,
+    /// amount received by the trader after fees
+    pub amount_out: u64,
+    /// fees charged on the trade
+    pub fees: FeeBreakdown,
+    /// pool state after the trade
+    pub pool: PoolState,
+    /// marginal price of token a in token b after the trade
+    pub marginal_price: Decimal,
+    /// value of the reserves in token b at the market price after the trade
+    pub lp_value: Decimal,
+}
+
+/// Replays trades against a pool
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Simulation {
+    /// curve pricing the pool
+    pub curve: CurveType,
+    /// fees charged by the pool
+    pub fees: Fees,
+    /// current pool state
+    pub pool: PoolState,
+}
+
+impl Simulation {
+    /// Start a simulation from a mocked swap account, trade amounts and the trace are in the
+    /// common scale of its token decimals
+    pub fn new(
+        curve: CurveType,
+        fees: Fees,
+        mocked_swap: &MockedSwap,
+    ) -> Result<Self, ProgramError> {
+        Ok(Self {
+            curve,
+            fees,
+            pool: PoolState::try_from(mocked_swap)?,
+        })
+    }
+
+    /// Apply a single trade to the pool state
+    pub fn step(&mut self, trade: &Trade) -> Result<SimulationStep, ProgramError> {
+        let amount_in = Decimal::from(trade.amount_in);
+        let oriented_pool = self.pool.oriented(trade.direction)?;
+        let oriented_curve = self.curve.oriented(trade.direction);
+        let fees = swap_with_fees(
+            &oriented_curve,
+            &oriented_pool,
+            &self.fees,
+            amount_in,
+            trade.has_referrer,
+        )?;
+
+        let amount_leaving = Decimal::from(fees.amount_out)
+            .try_add(Decimal::from(fees.admin_fee))?
+            .try_add(Decimal::from(fees.referrer_fee))?;
+        verify_swap(&oriented_curve, &oriented_pool, amount_in, amount_leaving)?;
+
+        // apply to the unoriented state to avoid the precision loss of flipping back
+        self.pool = match trade.direction {
+            SwapDirection::AtoB => self.pool.after_swap(amount_in, amount_leaving)?,
+            SwapDirection::BtoA => PoolState {
+                current_reserve_a: self.pool.current_reserve_a.try_sub(amount_leaving)?,
+                current_reserve_b: self.pool.current_reserve_b.try_add(amount_in)?,
+                ..self.pool
+            },
+        };
+
+        Ok(SimulationStep {
+            trade: *trade,
+            amount_out: fees.amount_out,
+            fees,
+            pool: self.pool,
+            marginal_price: self.curve.marginal_price(&self.pool)?,
+            lp_value: current_value(&self.pool)?,
+        })
+    }
+
+    /// Apply `trades` in order and return the trace of every step
+    pub fn run(&mut self, trades: &[Trade]) -> Result<Vec<SimulationStep>, ProgramError> {
+        trades.iter().map(|trade| self.step(trade)).collect()
+    }
+}
+
+/// Replay `trades` against `mocked_swap` and return the trace of every step
+pub fn simulate(
+    curve: CurveType,
+    fees: Fees,
+    mocked_swap: &MockedSwap,
+    trades: &[Trade],
+) -> Result<Vec<SimulationStep>, ProgramError> {
+    Simulation::new(curve, fees, mocked_swap)?.run(trades)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{error::SwapError, math::Rate};
+
+    fn mocked_swap() -> MockedSwap {
+        MockedSwap {
+            target_reserve_a: Decimal::from(1_000_000u64),
+            target_reserve_b: Decimal::from(3_000_000u64),
+            current_reserve_a: Decimal::from(1_000_000u64),
+            current_reserve_b: Decimal::from(3_000_000u64),
+            market_price: Decimal::from(3u64),
+            ..MockedSwap::default()
+        }
+    }
+
+    fn trade(direction: SwapDirection, amount_in: u64) -> Trade {
+        Trade {
+            direction,
+            amount_in,
+            has_referrer: false,
+        }
+    }
+
+    #[test]
+    fn test_trace_tracks_reserves_and_prices() {
+        let trades = [
+            trade(SwapDirection::AtoB, 10_000),
+            trade(SwapDirection::AtoB, 10_000),
+            trade(SwapDirection::BtoA, 60_000),
+        ];
+        let trace = simulate(CurveType::Oracle, Fees::default(), &mocked_swap(), &trades).unwrap();
+        assert_eq!(trace.len(), 3);
+
+        // selling token a pushes its price down, buying it back pushes it up
+        assert!(trace[0].marginal_price < Decimal::from(3u64));
+        assert!(trace[1].marginal_price < trace[0].marginal_price);
+        assert!(trace[2].marginal_price > trace[1].marginal_price);
+
+        let first = &trace[0];
+        assert_eq!(first.pool.current_reserve_a, Decimal::from(1_010_000u64));
+        assert_eq!(
+            first.pool.current_reserve_b,
+            Decimal::from(3_000_000 - first.amount_out - first.fees.admin_fee)
+        );
+        assert_eq!(trace[2].trade, trades[2]);
+    }
+
+    #[test]
+    fn test_fees_grow_lp_value() {
+        let fees = Fees {
+            trade_fee: Rate::from_percent(1),
+            ..Fees::default()
+        };
+        let trades = [
+            trade(SwapDirection::AtoB, 50_000),
+            trade(SwapDirection::BtoA, 150_000),
+        ];
+        let with_fees = simulate(CurveType::Oracle, fees, &mocked_swap(), &trades).unwrap();
+        let without_fees = simulate(
+            CurveType::Oracle,
+            Fees {
+                trade_fee: Rate::zero(),
+                ..fees
+            },
+            &mocked_swap(),
+            &trades,
+        )
+        .unwrap();
+        for (charged, free) in with_fees.iter().zip(without_fees.iter()) {
+            assert!(charged.fees.lp_fee > 0);
+            assert!(charged.lp_value > free.lp_value);
+        }
+    }
+
+    #[test]
+    fn test_stable_and_failed_trades() {
+        let stable = MockedSwap {
+            target_reserve_a: Decimal::from(1_000_000u64),
+            target_reserve_b: Decimal::from(1_000_000u64),
+            current_reserve_a: Decimal::from(1_000_000u64),
+            current_reserve_b: Decimal::from(1_000_000u64),
+            market_price: Decimal::from(1u64),
+            ..MockedSwap::default()
+        };
+        let mut simulation =
+            Simulation::new(CurveType::Stable { amp: 100 }, Fees::default(), &stable).unwrap();
+        let step = simulation
+            .step(&trade(SwapDirection::BtoA, 100_000))
+            .unwrap();
+        assert!(step.amount_out > 99_000);
+        assert!(step.marginal_price > Decimal::one());
+
+        // a trade dwarfing the pool still leaves token b in it
+        let step = simulation
+            .step(&trade(SwapDirection::AtoB, u64::MAX))
+            .unwrap();
+        assert!(step.pool.current_reserve_b > Decimal::zero());
+
+        // a failing trade leaves the state untouched
+        simulation.pool.market_price = Decimal::from(2u64);
+        let pool = simulation.pool;
+        assert_eq!(
+            simulation
+                .step(&trade(SwapDirection::AtoB, 1_000))
+                .unwrap_err(),
+            SwapError::IncorrectStablePrice.into()
+        );
+        assert_eq!(simulation.pool, pool);
+    }
+}
@@ -0,0 +1,294 @@
+//! StableSwap invariant calculations
+//!
+//! Implements the two-token Curve-finance invariant
+//! `A * n^n * (x + y) + D = A * D * n^n + D^(n+1) / (n^n * x * y)`
+//! where `A` is the amplification coefficient and `D` the total amount of
+//! tokens when the pool is balanced.
+
+use crate::{
+    error::SwapError,
+    math::{Decimal, TryAdd, TryDiv, TryMul, TrySub},
+};
+use solana_program::program_error::ProgramError;
+
+/// Number of tokens in a stable pool
+pub const N_COINS: u64 = 2;
+/// Minimum amplification coefficient
+pub const MIN_AMP: u64 = 1;
+/// Maximum amplification coefficient
+pub const MAX_AMP: u64 = 1_000_000;
+/// Maximum number of newton iterations before giving up
+pub const MAX_ITERATIONS: u8 = 64;
+
+/// Check the amplification coefficient is within [MIN_AMP, MAX_AMP]
+pub fn validate_amp(amp: u64) -> Result<(), ProgramError> {
+    if !(MIN_AMP..=MAX_AMP).contains(&amp) {
+        return Err(SwapError::InvalidInput.into());
+    }
+    Ok(())
+}
+
+/// Check the market price of a stable pool is one
+pub fn validate_stable_price(market_price: Decimal) -> Result<(), ProgramError> {
+    if market_price != Decimal::one() {
+        return Err(SwapError::IncorrectStablePrice.into());
+    }
+    Ok(())
+}
+
+/// Absolute difference of two decimals
+fn abs_diff(a: Decimal, b: Decimal) -> Result<Decimal, ProgramError> {
+    if a > b {
+        a.try_sub(b)
+    } else {
+        b.try_sub(a)
+    }
+}
+
+/// Compute the invariant D of the pool with newton iteration
+pub fn compute_d(
+    amp: u64,
+    reserve_a: Decimal,
+    reserve_b: Decimal,
+) -> Result<Decimal, ProgramError> {
+    validate_amp(amp)?;
+    let sum = reserve_a.try_add(reserve_b)?;
+    if sum.is_zero() {
+        return Ok(Decimal::zero());
+    }
+    if reserve_a.is_zero() || reserve_b.is_zero() {
+        return Err(SwapError::CalculationFailure.into());
+    }
+
+    let amp_n = Decimal::from(
+        amp.checked_mul(N_COINS * N_COINS)
+            .ok_or(SwapError::CalculationFailure)?,
+    );
+    let tolerance = Decimal::from_scaled_val(1);
+    let mut d = sum;
+    for _ in 0..MAX_ITERATIONS {
+        // d_p = D^3 / (4 * x * y), products of D with itself overflow a decimal above ~7.9e16
+        // and are divided in the wider intermediate of `try_mul_div`
+        let d_p = d
+            .try_mul_div(d, reserve_a.try_mul(N_COINS)?)?
+            .try_mul_div(d, reserve_b.try_mul(N_COINS)?)?;
+        let d_prev = d;
+        // D = (Ann * S + n * D_P) * D / ((Ann - 1) * D + (n + 1) * D_P)
+        let numerator = amp_n.try_mul(sum)?.try_add(d_p.try_mul(N_COINS)?)?;
+        let denominator = amp_n
+            .try_sub(Decimal::one())?
+            .try_mul(d)?
+            .try_add(d_p.try_mul(N_COINS + 1)?)?;
+        d = numerator.try_mul_div(d, denominator)?;
+
+        if abs_diff(d, d_prev)? <= tolerance {
+            return Ok(d);
+        }
+    }
+
+    Err(SwapError::CalculationFailure.into())
+}
+
+/// Compute the reserve of the other token given the new reserve `x` and the invariant D
+pub fn compute_y(amp: u64, x: Decimal, d: Decimal) -> Result<Decimal, ProgramError> {
+    validate_amp(amp)?;
+    if x.is_zero() {
+        return Err(SwapError::CalculationFailure.into());
+    }
+
+    let amp_n = Decimal::from(
+        amp.checked_mul(N_COINS * N_COINS)
+            .ok_or(SwapError::CalculationFailure)?,
+    );
+    // c = D^3 / (4 * x * Ann), b = x + D / Ann
+    let c = d
+        .try_mul_div(d, x.try_mul(N_COINS)?)?
+        .try_mul_div(d, amp_n.try_mul(N_COINS)?)?;
+    let b = x.try_add(d.try_div(amp_n)?)?;
+
+    let tolerance = Decimal::from_scaled_val(1);
+    let mut y = d;
+    for _ in 0..MAX_ITERATIONS {
+        let y_prev = y;
+        // y = (y^2 + c) / (2y + b - D)
+        let denominator = y.try_mul(2u64)?.try_add(b)?.try_sub(d)?;
+        y = y
+            .try_mul_div(y, denominator)?
+            .try_add(c.try_div(denominator)?)?;
+
+        if abs_diff(y, y_prev)? <= tolerance {
+            return Ok(y);
+        }
+    }
+
+    Err(SwapError::CalculationFailure.into())
+}
+
+/// Amount of token b received for an exact input of token a, in whole units rounded down
+pub fn stable_swap(
+    amp: u64,
+    current_reserve_a: Decimal,
+    current_reserve_b: Decimal,
+    input_a_amount: Decimal,
+) -> Result<Decimal, ProgramError> {
+    let d = compute_d(amp, current_reserve_a, current_reserve_b)?;
+    let new_reserve_b = compute_y(amp, current_reserve_a.try_add(input_a_amount)?, d)?;
+
+    // round the new reserve up so that the pool never pays out more than the invariant allows
+    let new_reserve_b = Decimal::from(new_reserve_b.try_ceil_u128()?);
+    if new_reserve_b >= current_reserve_b {
+        return Ok(Decimal::zero());
+    }
+    Ok(Decimal::from(
+        current_reserve_b.try_sub(new_reserve_b)?.try_floor_u128()?,
+    ))
+}
+
+/// Amount of token a required to receive an exact output of token b
+pub fn stable_swap_exact_out(
+    amp: u64,
+    current_reserve_a: Decimal,
+    current_reserve_b: Decimal,
+    output_b_amount: Decimal,
+) -> Result<u64, ProgramError> {
+    if output_b_amount >= current_reserve_b {
+        return Err(SwapError::InsufficientLiquidity.into());
+    }
+    let d = compute_d(amp, current_reserve_a, current_reserve_b)?;
+    let new_reserve_a = compute_y(amp, current_reserve_b.try_sub(output_b_amount)?, d)?;
+    if new_reserve_a <= current_reserve_a {
+        return Ok(0);
+    }
+    new_reserve_a.try_sub(current_reserve_a)?.try_ceil_u64()
+}
+
+/// Marginal price of token a in token b, i.e. `-dy/dx` along the invariant
+pub fn stable_marginal_price(
+    amp: u64,
+    current_reserve_a: Decimal,
+    current_reserve_b: Decimal,
+) -> Result<Decimal, ProgramError> {
+    let d = compute_d(amp, current_reserve_a, current_reserve_b)?;
+    let amp_n = Decimal::from(
+        amp.checked_mul(N_COINS * N_COINS)
+            .ok_or(SwapError::CalculationFailure)?,
+    );
+    // d_p = D^3 / (4 * x * y), dI/dx = Ann + d_p / x, dI/dy = Ann + d_p / y
+    let d_p = d
+        .try_mul_div(d, current_reserve_a.try_mul(N_COINS)?)?
+        .try_mul_div(d, current_reserve_b.try_mul(N_COINS)?)?;
+    let partial_a = amp_n.try_add(d_p.try_div(current_reserve_a)?)?;
+    let partial_b = amp_n.try_add(d_p.try_div(current_reserve_b)?)?;
+    partial_a.try_div(partial_b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compute_d_balanced() {
+        let reserve = Decimal::from(1_000_000u64);
+        for amp in [1u64, 10, 100, 1_000, 100_000] {
+            let d = compute_d(amp, reserve, reserve).unwrap();
+            assert_eq!(d.try_round_u64().unwrap(), 2_000_000u64);
+        }
+    }
+
+    #[test]
+    fn test_compute_y_recovers_reserve() {
+        let amp = 100u64;
+        let reserve_a = Decimal::from(1_200_000u64);
+        let reserve_b = Decimal::from(800_000u64);
+        let d = compute_d(amp, reserve_a, reserve_b).unwrap();
+        let y = compute_y(amp, reserve_a, d).unwrap();
+        assert_eq!(y.try_round_u64().unwrap(), 800_000u64);
+    }
+
+    #[test]
+    fn test_stable_swap() {
+        let reserve = Decimal::from(1_000_000u64);
+        let input = Decimal::from(1_000u64);
+
+        // high amplification prices close to one
+        let out = stable_swap(1_000, reserve, reserve, input).unwrap();
+        assert!((999..1_000).contains(&out.try_floor_u64().unwrap()));
+
+        // a larger trade gets a worse price
+        let large_input = Decimal::from(500_000u64);
+        let out_large = stable_swap(1_000, reserve, reserve, large_input).unwrap();
+        assert!(out_large < Decimal::from(500_000u64));
+
+        // low amplification behaves closer to constant product
+        let out_low_amp = stable_swap(1, reserve, reserve, large_input).unwrap();
+        assert!(out_low_amp < out_large);
+    }
+
+    #[test]
+    fn test_stable_marginal_price() {
+        let reserve = Decimal::from(1_000_000u64);
+        let price = stable_marginal_price(100, reserve, reserve).unwrap();
+        assert_eq!(price.try_round_u64().unwrap(), 1);
+
+        // token a is abundant so it is worth less than token b
+        let price =
+            stable_marginal_price(100, Decimal::from(1_500_000u64), Decimal::from(500_000u64))
+                .unwrap();
+        assert!(price < Decimal::one());
+        let flatter = stable_marginal_price(
+            1_000,
+            Decimal::from(1_500_000u64),
+            Decimal::from(500_000u64),
+        )
+        .unwrap();
+        assert!(price < flatter);
+    }
+
+    #[test]
+    fn test_large_reserves() {
+        // a million tokens of 12 decimals, D^2 does not fit a decimal
+        let reserve = Decimal::from(1_000_000_000_000_000_000u128);
+        for amp in [1u64, 100, MAX_AMP] {
+            let d = compute_d(amp, reserve, reserve).unwrap();
+            assert_eq!(d.try_round_u128().unwrap(), 2_000_000_000_000_000_000);
+        }
+
+        let reserve_a = Decimal::from(1_200_000_000_000_000_000u128);
+        let reserve_b = Decimal::from(800_000_000_000_000_000u128);
+        let d = compute_d(100, reserve_a, reserve_b).unwrap();
+        let y = compute_y(100, reserve_a, d).unwrap();
+        assert_eq!(y.try_round_u128().unwrap(), 800_000_000_000_000_000);
+
+        let input = Decimal::from(1_000_000_000_000_000u64);
+        let out = stable_swap(1_000, reserve, reserve, input).unwrap();
+        assert!(out < input && out > Decimal::from(999_000_000_000_000u64));
+        let price = stable_marginal_price(100, reserve, reserve).unwrap();
+        assert_eq!(price.try_round_u64().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_invalid_inputs() {
+        let reserve = Decimal::from(1_000u64);
+        assert_eq!(
+            compute_d(0, reserve, reserve).unwrap_err(),
+            SwapError::InvalidInput.into()
+        );
+        assert_eq!(
+            compute_d(MAX_AMP + 1, reserve, reserve).unwrap_err(),
+            SwapError::InvalidInput.into()
+        );
+        assert_eq!(
+            compute_d(100, reserve, Decimal::zero()).unwrap_err(),
+            SwapError::CalculationFailure.into()
+        );
+        assert_eq!(
+            compute_d(100, Decimal::zero(), Decimal::zero()).unwrap(),
+            Decimal::zero()
+        );
+        assert!(validate_stable_price(Decimal::one()).is_ok());
+        assert_eq!(
+            validate_stable_price(Decimal::from(2u64)).unwrap_err(),
+            SwapError::IncorrectStablePrice.into()
+        );
+    }
+}
@@ -0,0 +1,167 @@
+//! Post-trade invariant verification
+//!
+//! Every curve conserves a quantity along a swap: the oracle curve and weighted pools
+//! conserve `reserve_a^exp * reserve_b`, stable pools conserve the invariant D. A swap
+//! leaving the pool with a lower value of that quantity gives away value for free.
+
+use crate::{
+    curve::{
+        pool::{CurveType, PoolState},
+        stable::compute_d,
+    },
+    error::SwapError,
+    math::{Decimal, TryAdd, TryDiv, TryMul, TrySub},
+};
+use solana_program::program_error::ProgramError;
+
+/// Relative decrease of the invariant tolerated for fixed-point rounding, 1e-10
+pub const INVARIANT_TOLERANCE: u128 = 100;
+/// Absolute decrease of the stable invariant D tolerated for newton convergence
+pub const STABLE_INVARIANT_TOLERANCE: u128 = 2;
+
+/// Ratio of the invariant after the swap to the invariant before,
+/// `(a_after / a_before)^exp * (b_after / b_before)`
+fn weighted_invariant_ratio(
+    before: &PoolState,
+    after: &PoolState,
+    exp: Decimal,
+) -> Result<Decimal, ProgramError> {
+    after
+        .current_reserve_a
+        .try_div(before.current_reserve_a)?
+        .try_powf(exp)?
+        .try_mul(after.current_reserve_b.try_div(before.current_reserve_b)?)
+}
+
+/// Check the curve invariant did not decrease from `before` to `after`
+pub fn check_invariant(
+    curve: &CurveType,
+    before: &PoolState,
+    after: &PoolState,
+) -> Result<(), ProgramError> {
+    let decreased = match *curve {
+        CurveType::Oracle => {
+            let exp = before
+                .market_price
+                .try_mul(before.target_reserve_a)?
+                .try_div(before.target_reserve_b)?;
+            weighted_invariant_ratio(before, after, exp)?
+                < Decimal::one().try_sub(Decimal::from_scaled_val(INVARIANT_TOLERANCE))?
+        }
+        CurveType::Weighted { weight_a, weight_b } => {
+            weighted_invariant_ratio(before, after, weight_a.try_div(weight_b)?)?
+                < Decimal::one().try_sub(Decimal::from_scaled_val(INVARIANT_TOLERANCE))?
+        }
+        CurveType::Stable { amp } => {
+            let d_before = compute_d(amp, before.current_reserve_a, before.current_reserve_b)?;
+            let d_after = compute_d(amp, after.current_reserve_a, after.current_reserve_b)?;
+            d_after.try_add(Decimal::from_scaled_val(STABLE_INVARIANT_TOLERANCE))? < d_before
+        }
+    };
+
+    if decreased {
+        return Err(SwapError::InconsistentPoolState.into());
+    }
+    Ok(())
+}
+
+/// Verify a swap of `input_a_amount` for `output_b_amount` leaves the pool at least as
+/// valuable as before and never drains `current_reserve_b`.
+/// Returns the pool state after the swap.
+pub fn verify_swap(
+    curve: &CurveType,
+    before: &PoolState,
+    input_a_amount: Decimal,
+    output_b_amount: Decimal,
+) -> Result<PoolState, ProgramError> {
+    if output_b_amount >= before.current_reserve_b {
+        return Err(SwapError::InconsistentPoolState.into());
+    }
+    let after = before.after_swap(input_a_amount, output_b_amount)?;
+    check_invariant(curve, before, &after)?;
+    Ok(after)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::MockedSwap;
+    use std::convert::TryFrom;
+
+    fn curves() -> Vec<(CurveType, PoolState)> {
+        let oracle_pool = PoolState::try_from(&MockedSwap {
+            target_reserve_a: Decimal::from(1_000_000u64),
+            target_reserve_b: Decimal::from(3_000_000u64),
+            current_reserve_a: Decimal::from(1_200_000u64),
+            current_reserve_b: Decimal::from(2_500_000u64),
+            market_price: Decimal::from(3u64),
+            ..MockedSwap::default()
+        })
+        .unwrap();
+        let stable_pool = PoolState::try_from(&MockedSwap {
+            target_reserve_a: Decimal::from(1_000_000u64),
+            target_reserve_b: Decimal::from(1_000_000u64),
+            current_reserve_a: Decimal::from(800_000u64),
+            current_reserve_b: Decimal::from(1_300_000u64),
+            market_price: Decimal::from(1u64),
+            ..MockedSwap::default()
+        })
+        .unwrap();
+        vec![
+            (CurveType::Oracle, oracle_pool),
+            (CurveType::Stable { amp: 100 }, stable_pool),
+            (
+                CurveType::Weighted {
+                    weight_a: Decimal::from_percent(80),
+                    weight_b: Decimal::from_percent(20),
+                },
+                oracle_pool,
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_curve_swaps_keep_invariant() {
+        for (curve, pool) in curves() {
+            let mut input = 1u64;
+            while input < 10_000_000 {
+                let output = curve
+                    .swap_exact_in(&pool, Decimal::from(input))
+                    .unwrap()
+                    .try_floor_u64()
+                    .unwrap();
+                verify_swap(&curve, &pool, Decimal::from(input), Decimal::from(output)).unwrap();
+
+                // any larger payout on a meaningful trade is a free lunch
+                if output > 1_000 {
+                    assert_eq!(
+                        verify_swap(
+                            &curve,
+                            &pool,
+                            Decimal::from(input),
+                            Decimal::from(output + output / 1_000)
+                        )
+                        .unwrap_err(),
+                        SwapError::InconsistentPoolState.into()
+                    );
+                }
+                input = input * 7 + 3;
+            }
+        }
+    }
+
+    #[test]
+    fn test_output_exceeding_reserve() {
+        for (curve, pool) in curves() {
+            let reserve = pool.current_reserve_b;
+            assert_eq!(
+                verify_swap(&curve, &pool, reserve, reserve.try_mul(2u64).unwrap()).unwrap_err(),
+                SwapError::InconsistentPoolState.into()
+            );
+            assert_eq!(
+                verify_swap(&curve, &pool, Decimal::from(1_000u64), reserve).unwrap_err(),
+                SwapError::InconsistentPoolState.into()
+            );
+        }
+    }
+}
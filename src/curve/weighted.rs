@@ -0,0 +1,235 @@
+//! Weighted pool (Balancer-style) calculations
+//!
+//! The value function is `prod(balance_i ^ weight_i)`. Swapping `amount_in` of token i
+//! for token o pays `balance_o * (1 - (balance_i / (balance_i + amount_in))^(w_i / w_o))`,
+//! the same shape as `simple_powf` whose exponent is derived from the oracle price instead.
+
+use crate::{
+    error::SwapError,
+    math::{Decimal, TryAdd, TryDiv, TryMul, TrySub},
+};
+use solana_program::program_error::ProgramError;
+
+/// Minimum number of tokens in a weighted pool
+pub const MIN_WEIGHTED_TOKENS: usize = 2;
+/// Maximum number of tokens in a weighted pool
+pub const MAX_WEIGHTED_TOKENS: usize = 8;
+
+/// Amount of token out received for an exact input of token in, in whole units rounded down
+pub fn weighted_swap_exact_in(
+    balance_in: Decimal,
+    weight_in: Decimal,
+    balance_out: Decimal,
+    weight_out: Decimal,
+    amount_in: Decimal,
+) -> Result<Decimal, ProgramError> {
+    let core = balance_in.try_div(balance_in.try_add(amount_in)?)?;
+    let exp = weight_in.try_div(weight_out)?;
+    // round the remaining balance up so the pool keeps any rounding dust
+    let core_exp = core.try_powf(exp)?.try_add(Decimal::from_scaled_val(1))?;
+    if core_exp >= Decimal::one() {
+        return Ok(Decimal::zero());
+    }
+
+    Ok(Decimal::from(
+        balance_out
+            .try_mul(Decimal::one().try_sub(core_exp)?)?
+            .try_floor_u128()?,
+    ))
+}
+
+/// Amount of token in required to receive an exact output of token out
+pub fn weighted_swap_exact_out(
+    balance_in: Decimal,
+    weight_in: Decimal,
+    balance_out: Decimal,
+    weight_out: Decimal,
+    amount_out: Decimal,
+) -> Result<u64, ProgramError> {
+    if amount_out >= balance_out {
+        return Err(SwapError::InsufficientLiquidity.into());
+    }
+    let core = balance_out.try_div(balance_out.try_sub(amount_out)?)?;
+    let exp = weight_out.try_div(weight_in)?;
+    let core_exp = core.try_powf(exp)?;
+
+    balance_in
+        .try_mul(core_exp.try_sub(Decimal::one())?)?
+        .try_ceil_u64()
+}
+
+/// Marginal price of token in, in units of token out, `(balance_out / w_o) / (balance_in / w_i)`
+pub fn weighted_spot_price(
+    balance_in: Decimal,
+    weight_in: Decimal,
+    balance_out: Decimal,
+    weight_out: Decimal,
+) -> Result<Decimal, ProgramError> {
+    balance_out
+        .try_mul(weight_in)?
+        .try_div(balance_in.try_mul(weight_out)?)
+}
+
+/// Weighted pool with two to eight tokens
+#[derive(Clone, Debug, PartialEq)]
+pub struct WeightedPool {
+    /// token balances
+    pub balances: Vec<Decimal>,
+    /// token weights, only their ratios matter
+    pub weights: Vec<Decimal>,
+}
+
+impl WeightedPool {
+    /// Create a weighted pool, checking token count and weights
+    pub fn new(balances: Vec<Decimal>, weights: Vec<Decimal>) -> Result<Self, ProgramError> {
+        if balances.len() != weights.len()
+            || !(MIN_WEIGHTED_TOKENS..=MAX_WEIGHTED_TOKENS).contains(&balances.len())
+            || weights.iter().any(Decimal::is_zero)
+        {
+            return Err(SwapError::InvalidInput.into());
+        }
+        Ok(Self { balances, weights })
+    }
+
+    fn pair(
+        &self,
+        token_in: usize,
+        token_out: usize,
+    ) -> Result<(Decimal, Decimal, Decimal, Decimal), ProgramError> {
+        if token_in == token_out {
+            return Err(SwapError::InvalidInput.into());
+        }
+        let balance_in = self
+            .balances
+            .get(token_in)
+            .ok_or(SwapError::IndexOutOfRange)?;
+        let balance_out = self
+            .balances
+            .get(token_out)
+            .ok_or(SwapError::IndexOutOfRange)?;
+        Ok((
+            *balance_in,
+            self.weights[token_in],
+            *balance_out,
+            self.weights[token_out],
+        ))
+    }
+
+    /// Amount of `token_out` received for an exact input of `token_in`
+    pub fn swap_exact_in(
+        &self,
+        token_in: usize,
+        token_out: usize,
+        amount_in: Decimal,
+    ) -> Result<u64, ProgramError> {
+        let (balance_in, weight_in, balance_out, weight_out) = self.pair(token_in, token_out)?;
+        weighted_swap_exact_in(balance_in, weight_in, balance_out, weight_out, amount_in)?
+            .try_floor_u64()
+    }
+
+    /// Amount of `token_in` required to receive an exact output of `token_out`
+    pub fn swap_exact_out(
+        &self,
+        token_in: usize,
+        token_out: usize,
+        amount_out: Decimal,
+    ) -> Result<u64, ProgramError> {
+        let (balance_in, weight_in, balance_out, weight_out) = self.pair(token_in, token_out)?;
+        weighted_swap_exact_out(balance_in, weight_in, balance_out, weight_out, amount_out)
+    }
+
+    /// Marginal price of `token_in` in units of `token_out`
+    pub fn spot_price(&self, token_in: usize, token_out: usize) -> Result<Decimal, ProgramError> {
+        let (balance_in, weight_in, balance_out, weight_out) = self.pair(token_in, token_out)?;
+        weighted_spot_price(balance_in, weight_in, balance_out, weight_out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn three_token_pool() -> WeightedPool {
+        WeightedPool::new(
+            vec![
+                Decimal::from(1_000_000u64),
+                Decimal::from(4_000_000u64),
+                Decimal::from(500_000u64),
+            ],
+            vec![
+                Decimal::from_percent(50),
+                Decimal::from_percent(30),
+                Decimal::from_percent(20),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_equal_weights_is_constant_product() {
+        let pool = WeightedPool::new(
+            vec![Decimal::from(1_000_000u64), Decimal::from(2_000_000u64)],
+            vec![Decimal::one(), Decimal::one()],
+        )
+        .unwrap();
+        // 2_000_000 * 10_000 / 1_010_000 = 19_801.98
+        assert_eq!(
+            pool.swap_exact_in(0, 1, Decimal::from(10_000u64)).unwrap(),
+            19_801
+        );
+        assert_eq!(pool.spot_price(0, 1).unwrap(), Decimal::from(2u64));
+    }
+
+    #[test]
+    fn test_weighted_swap() {
+        let pool = three_token_pool();
+        // spot price of token 0 in token 1: (4_000_000 / 0.3) / (1_000_000 / 0.5)
+        let spot = pool.spot_price(0, 1).unwrap();
+        assert_eq!(spot.try_round_u64().unwrap(), 7);
+
+        let out = pool.swap_exact_in(0, 1, Decimal::from(1_000u64)).unwrap();
+        assert!(out < spot.try_mul(1_000u64).unwrap().try_floor_u64().unwrap());
+        assert!(out > 6_600);
+
+        let input = pool.swap_exact_out(0, 1, Decimal::from(out)).unwrap();
+        assert!((999..=1_001).contains(&input));
+
+        let reverse = pool.swap_exact_in(2, 0, Decimal::from(10_000u64)).unwrap();
+        assert!(reverse > 0);
+    }
+
+    #[test]
+    fn test_invalid_pools() {
+        assert_eq!(
+            WeightedPool::new(vec![Decimal::one()], vec![Decimal::one()]).unwrap_err(),
+            SwapError::InvalidInput.into()
+        );
+        assert_eq!(
+            WeightedPool::new(vec![Decimal::one(); 9], vec![Decimal::one(); 9]).unwrap_err(),
+            SwapError::InvalidInput.into()
+        );
+        assert_eq!(
+            WeightedPool::new(
+                vec![Decimal::one(), Decimal::one()],
+                vec![Decimal::one(), Decimal::zero()]
+            )
+            .unwrap_err(),
+            SwapError::InvalidInput.into()
+        );
+
+        let pool = three_token_pool();
+        assert_eq!(
+            pool.swap_exact_in(0, 3, Decimal::one()).unwrap_err(),
+            SwapError::IndexOutOfRange.into()
+        );
+        assert_eq!(
+            pool.swap_exact_in(1, 1, Decimal::one()).unwrap_err(),
+            SwapError::InvalidInput.into()
+        );
+        assert_eq!(
+            pool.swap_exact_out(0, 2, Decimal::from(500_000u64))
+                .unwrap_err(),
+            SwapError::InsufficientLiquidity.into()
+        );
+    }
+}
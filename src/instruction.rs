@@ -1,23 +1,251 @@
 //! Instructions
 
+#![allow(clippy::too_many_arguments)]
+
+
+use arrayref::{array_ref, array_refs};
 
 use solana_program::{
     instruction::{AccountMeta, Instruction},
     program_error::ProgramError,
     pubkey::Pubkey,
+    system_program,
+};
+
+use std::str::FromStr;
+
+use crate::{
+    curve::Fees,
+    error::SwapError,
+    math::Rate,
+    state::{ConfigInfo, MockedSwap, UserReferrerData},
+    utils::SPL_TOKEN_PROGRAM_ID,
+    DUMMY_REFERRER_ADDRESS,
 };
 
-/// Creates a 'mock_swap' instruction
+/// Instruction tag of `mock_swap`
+pub const MOCK_SWAP_TAG: u8 = 1;
+/// Instruction tag of `migrate_account`
+pub const MIGRATE_ACCOUNT_TAG: u8 = 2;
+/// Instruction tag of `initialize_config`
+pub const INITIALIZE_CONFIG_TAG: u8 = 3;
+/// Instruction tag of `set_paused`
+pub const SET_PAUSED_TAG: u8 = 4;
+/// Instruction tag of `commit_new_admin`
+pub const COMMIT_NEW_ADMIN_TAG: u8 = 5;
+/// Instruction tag of `apply_new_admin`
+pub const APPLY_NEW_ADMIN_TAG: u8 = 6;
+/// Instruction tag of `cancel_admin_transfer`
+pub const CANCEL_ADMIN_TRANSFER_TAG: u8 = 7;
+/// Instruction tag of the farm instructions, followed by a [FarmInstruction](enum.FarmInstruction.html)
+pub const FARM_INSTRUCTION_TAG: u8 = 8;
+/// Instruction tag of `set_referrer`
+pub const SET_REFERRER_TAG: u8 = 9;
+/// Instruction tag of `update_twap`
+pub const UPDATE_TWAP_TAG: u8 = 10;
+
+/// Data of the 'initialize_config' instruction
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InitializeConfigData {
+    /// fees of newly created pools
+    pub fees: Fees,
+    /// pyth oracle program
+    pub pyth_program_id: Pubkey,
+    /// serum dex program
+    pub serum_program_id: Pubkey,
+}
+
+const INITIALIZE_CONFIG_DATA_LEN: usize = 112;
+
+impl InitializeConfigData {
+    /// Pack into instruction data following the tag
+    pub fn pack(&self, dst: &mut Vec<u8>) {
+        dst.extend_from_slice(&self.fees.trade_fee.to_scaled_val().to_le_bytes());
+        dst.extend_from_slice(&self.fees.admin_fee.to_scaled_val().to_le_bytes());
+        dst.extend_from_slice(&self.fees.referrer_fee.to_scaled_val().to_le_bytes());
+        dst.extend_from_slice(self.pyth_program_id.as_ref());
+        dst.extend_from_slice(self.serum_program_id.as_ref());
+    }
+
+    /// Unpack from instruction data following the tag
+    pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        if input.len() != INITIALIZE_CONFIG_DATA_LEN {
+            return Err(SwapError::InstructionUnpackError.into());
+        }
+        let input = array_ref![input, 0, INITIALIZE_CONFIG_DATA_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (trade_fee, admin_fee, referrer_fee, pyth_program_id, serum_program_id) =
+            array_refs![input, 16, 16, 16, 32, 32];
+        Ok(Self {
+            fees: Fees {
+                trade_fee: Rate::from_scaled_val(u128::from_le_bytes(*trade_fee)),
+                admin_fee: Rate::from_scaled_val(u128::from_le_bytes(*admin_fee)),
+                referrer_fee: Rate::from_scaled_val(u128::from_le_bytes(*referrer_fee)),
+            },
+            pyth_program_id: Pubkey::new_from_array(*pyth_program_id),
+            serum_program_id: Pubkey::new_from_array(*serum_program_id),
+        })
+    }
+}
+
+/// Data of the 'farm_initialize' instruction
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FarmInitializeData {
+    /// rewards distributed per second among all stakers
+    pub reward_per_second: u64,
+    /// seconds a position has to wait between claims
+    pub min_claim_period: i64,
+    /// bump seed of the farm authority
+    pub bump_seed: u8,
+}
+
+/// Data of the 'farm_deposit' instruction
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FarmDepositData {
+    /// pool tokens to stake
+    pub amount: u64,
+}
+
+/// Data of the 'farm_withdraw' instruction
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FarmWithdrawData {
+    /// pool tokens to unstake
+    pub amount: u64,
+}
+
+/// Instructions of the liquidity farms
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FarmInstruction {
+    /// Initialize a farm for a pool token, signed by the config admin
+    FarmInitialize(FarmInitializeData),
+    /// Stake pool tokens, creating the position on the first deposit
+    FarmDeposit(FarmDepositData),
+    /// Unstake pool tokens
+    FarmWithdraw(FarmWithdrawData),
+    /// Claim the rewards earned
+    FarmClaim,
+}
+
+impl FarmInstruction {
+    /// Unpack from instruction data following `FARM_INSTRUCTION_TAG`
+    pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        let (&tag, rest) = input
+            .split_first()
+            .ok_or(SwapError::InstructionUnpackError)?;
+        match (tag, rest.len()) {
+            (0, 17) => {
+                let rest = array_ref![rest, 0, 17];
+                #[allow(clippy::ptr_offset_with_cast)]
+                let (reward_per_second, min_claim_period, bump_seed) = array_refs![rest, 8, 8, 1];
+                Ok(Self::FarmInitialize(FarmInitializeData {
+                    reward_per_second: u64::from_le_bytes(*reward_per_second),
+                    min_claim_period: i64::from_le_bytes(*min_claim_period),
+                    bump_seed: bump_seed[0],
+                }))
+            }
+            (1, 8) => Ok(Self::FarmDeposit(FarmDepositData {
+                amount: u64::from_le_bytes(*array_ref![rest, 0, 8]),
+            })),
+            (2, 8) => Ok(Self::FarmWithdraw(FarmWithdrawData {
+                amount: u64::from_le_bytes(*array_ref![rest, 0, 8]),
+            })),
+            (3, 0) => Ok(Self::FarmClaim),
+            _ => Err(SwapError::InstructionUnpackError.into()),
+        }
+    }
+
+    /// Pack into instruction data, `FARM_INSTRUCTION_TAG` included
+    pub fn pack(&self) -> Vec<u8> {
+        let mut data = vec!(FARM_INSTRUCTION_TAG);
+        match self {
+            Self::FarmInitialize(FarmInitializeData {
+                reward_per_second,
+                min_claim_period,
+                bump_seed,
+            }) => {
+                data.push(0);
+                data.extend_from_slice(&reward_per_second.to_le_bytes());
+                data.extend_from_slice(&min_claim_period.to_le_bytes());
+                data.push(*bump_seed);
+            }
+            Self::FarmDeposit(FarmDepositData { amount }) => {
+                data.push(1);
+                data.extend_from_slice(&amount.to_le_bytes());
+            }
+            Self::FarmWithdraw(FarmWithdrawData { amount }) => {
+                data.push(2);
+                data.extend_from_slice(&amount.to_le_bytes());
+            }
+            Self::FarmClaim => data.push(3),
+        }
+        data
+    }
+}
+
+/// Creates a 'mock_swap' instruction selling token a of `source_key`, owned by the payer,
+/// for token b paid to `destination_key`.
+/// `referrer_key` is the token account registered as the referrer of the payer, none for
+/// payers who never registered one or registered the dummy referrer. The referrer fee is
+/// paid from `pool_token_b_key`, the output token account of the pool.
 pub fn mock_swap(
     program_id: Pubkey,
     mocked_swap_key: Pubkey,
     payer_key: Pubkey,
+    config_key: Pubkey,
+    referrer_key: Option<Pubkey>,
+    source_key: Pubkey,
+    destination_key: Pubkey,
+    pool_token_a_key: Pubkey,
+    pool_token_b_key: Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = vec!(MOCK_SWAP_TAG);
+
+    let (user_referrer_key, _) =
+        UserReferrerData::find_address(&program_id, &config_key, &payer_key);
+    let referrer_key = match referrer_key {
+        Some(referrer_key) => referrer_key,
+        None => Pubkey::from_str(DUMMY_REFERRER_ADDRESS)
+            .map_err(|_| SwapError::InvalidReferrer)?,
+    };
+    let (authority_key, _) = MockedSwap::find_authority(&program_id, &mocked_swap_key);
+    let accounts = vec![
+        AccountMeta::new(mocked_swap_key, false),
+        AccountMeta::new_readonly(payer_key, true),
+        AccountMeta::new_readonly(config_key, false),
+        AccountMeta::new_readonly(user_referrer_key, false),
+        AccountMeta::new(referrer_key, false),
+        AccountMeta::new(source_key, false),
+        AccountMeta::new(destination_key, false),
+        AccountMeta::new(pool_token_a_key, false),
+        AccountMeta::new(pool_token_b_key, false),
+        AccountMeta::new_readonly(authority_key, false),
+        AccountMeta::new_readonly(SPL_TOKEN_PROGRAM_ID, false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'migrate_account' instruction upgrading a state account to its current layout,
+/// signed by the config admin
+pub fn migrate_account(
+    program_id: Pubkey,
+    account_key: Pubkey,
+    payer_key: Pubkey,
+    config_key: Pubkey,
+    admin_key: Pubkey,
 ) -> Result<Instruction, ProgramError> {
-    let data = vec!(1u8);
+    let data = vec!(MIGRATE_ACCOUNT_TAG);
 
     let accounts = vec![
-        AccountMeta::new_readonly(mocked_swap_key, false),
-        AccountMeta::new_readonly(payer_key, true)
+        AccountMeta::new(account_key, false),
+        AccountMeta::new(payer_key, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(config_key, false),
+        AccountMeta::new_readonly(admin_key, true),
     ];
 
     Ok(Instruction {
@@ -25,4 +253,273 @@ pub fn mock_swap(
         accounts,
         data,
     })
-}
\ No newline at end of file
+}
+
+/// Creates an 'initialize_config' instruction creating the config of the program,
+/// `admin_key` becomes the program admin and pays its rent
+pub fn initialize_config(
+    program_id: Pubkey,
+    admin_key: Pubkey,
+    config_data: InitializeConfigData,
+) -> Result<Instruction, ProgramError> {
+    let mut data = vec!(INITIALIZE_CONFIG_TAG);
+    config_data.pack(&mut data);
+
+    let (config_key, _) = ConfigInfo::find_address(&program_id);
+    let accounts = vec![
+        AccountMeta::new(config_key, false),
+        AccountMeta::new(admin_key, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_paused' instruction switching the global pause
+pub fn set_paused(
+    program_id: Pubkey,
+    config_key: Pubkey,
+    admin_key: Pubkey,
+    is_paused: bool,
+) -> Result<Instruction, ProgramError> {
+    let data = vec!(SET_PAUSED_TAG, is_paused as u8);
+
+    let accounts = vec![
+        AccountMeta::new(config_key, false),
+        AccountMeta::new_readonly(admin_key, true),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'commit_new_admin' instruction proposing `new_admin_key` as the next admin
+pub fn commit_new_admin(
+    program_id: Pubkey,
+    config_key: Pubkey,
+    admin_key: Pubkey,
+    new_admin_key: Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = vec!(COMMIT_NEW_ADMIN_TAG);
+
+    let accounts = vec![
+        AccountMeta::new(config_key, false),
+        AccountMeta::new_readonly(admin_key, true),
+        AccountMeta::new_readonly(new_admin_key, false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates an 'apply_new_admin' instruction, signed by the proposed admin to accept
+pub fn apply_new_admin(
+    program_id: Pubkey,
+    config_key: Pubkey,
+    new_admin_key: Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = vec!(APPLY_NEW_ADMIN_TAG);
+
+    let accounts = vec![
+        AccountMeta::new(config_key, false),
+        AccountMeta::new_readonly(new_admin_key, true),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'cancel_admin_transfer' instruction dropping the proposed admin
+pub fn cancel_admin_transfer(
+    program_id: Pubkey,
+    config_key: Pubkey,
+    admin_key: Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = vec!(CANCEL_ADMIN_TRANSFER_TAG);
+
+    let accounts = vec![
+        AccountMeta::new(config_key, false),
+        AccountMeta::new_readonly(admin_key, true),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'farm_initialize' instruction.
+/// The token accounts must be owned by the farm authority derived from the farm key and bump seed.
+pub fn farm_initialize(
+    program_id: Pubkey,
+    config_key: Pubkey,
+    farm_key: Pubkey,
+    admin_key: Pubkey,
+    pool_mint_key: Pubkey,
+    pool_token_key: Pubkey,
+    reward_token_key: Pubkey,
+    data: FarmInitializeData,
+) -> Result<Instruction, ProgramError> {
+    let data = FarmInstruction::FarmInitialize(data).pack();
+
+    let accounts = vec![
+        AccountMeta::new_readonly(config_key, false),
+        AccountMeta::new(farm_key, false),
+        AccountMeta::new_readonly(admin_key, true),
+        AccountMeta::new_readonly(pool_mint_key, false),
+        AccountMeta::new_readonly(pool_token_key, false),
+        AccountMeta::new_readonly(reward_token_key, false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'farm_deposit' instruction staking pool tokens of `source_key`
+pub fn farm_deposit(
+    program_id: Pubkey,
+    config_key: Pubkey,
+    farm_key: Pubkey,
+    farm_user_key: Pubkey,
+    owner_key: Pubkey,
+    source_key: Pubkey,
+    pool_token_key: Pubkey,
+    data: FarmDepositData,
+) -> Result<Instruction, ProgramError> {
+    let data = FarmInstruction::FarmDeposit(data).pack();
+
+    let accounts = vec![
+        AccountMeta::new_readonly(config_key, false),
+        AccountMeta::new(farm_key, false),
+        AccountMeta::new(farm_user_key, false),
+        AccountMeta::new_readonly(owner_key, true),
+        AccountMeta::new(source_key, false),
+        AccountMeta::new(pool_token_key, false),
+        AccountMeta::new_readonly(SPL_TOKEN_PROGRAM_ID, false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'farm_withdraw' instruction returning pool tokens to `destination_key`
+pub fn farm_withdraw(
+    program_id: Pubkey,
+    farm_key: Pubkey,
+    farm_user_key: Pubkey,
+    owner_key: Pubkey,
+    farm_authority_key: Pubkey,
+    pool_token_key: Pubkey,
+    destination_key: Pubkey,
+    data: FarmWithdrawData,
+) -> Result<Instruction, ProgramError> {
+    let data = FarmInstruction::FarmWithdraw(data).pack();
+
+    let accounts = vec![
+        AccountMeta::new(farm_key, false),
+        AccountMeta::new(farm_user_key, false),
+        AccountMeta::new_readonly(owner_key, true),
+        AccountMeta::new_readonly(farm_authority_key, false),
+        AccountMeta::new(pool_token_key, false),
+        AccountMeta::new(destination_key, false),
+        AccountMeta::new_readonly(SPL_TOKEN_PROGRAM_ID, false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'farm_claim' instruction sending the rewards earned to `destination_key`
+pub fn farm_claim(
+    program_id: Pubkey,
+    farm_key: Pubkey,
+    farm_user_key: Pubkey,
+    owner_key: Pubkey,
+    farm_authority_key: Pubkey,
+    reward_token_key: Pubkey,
+    destination_key: Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = FarmInstruction::FarmClaim.pack();
+
+    let accounts = vec![
+        AccountMeta::new(farm_key, false),
+        AccountMeta::new(farm_user_key, false),
+        AccountMeta::new_readonly(owner_key, true),
+        AccountMeta::new_readonly(farm_authority_key, false),
+        AccountMeta::new(reward_token_key, false),
+        AccountMeta::new(destination_key, false),
+        AccountMeta::new_readonly(SPL_TOKEN_PROGRAM_ID, false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_referrer' instruction registering `referrer_key`, a token account receiving
+/// the referrer fee, as the referrer of `owner_key`. Pass the dummy referrer when nobody referred.
+/// The registration is created at the address derived from the config and the owner, who pays
+/// its rent.
+pub fn set_referrer(
+    program_id: Pubkey,
+    config_key: Pubkey,
+    owner_key: Pubkey,
+    referrer_key: Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = vec!(SET_REFERRER_TAG);
+
+    let (user_referrer_key, _) =
+        UserReferrerData::find_address(&program_id, &config_key, &owner_key);
+    let accounts = vec![
+        AccountMeta::new_readonly(config_key, false),
+        AccountMeta::new(user_referrer_key, false),
+        AccountMeta::new(owner_key, true),
+        AccountMeta::new_readonly(referrer_key, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates an 'update_twap' instruction accumulating the current marginal price of a pool.
+/// Anyone can crank it.
+pub fn update_twap(program_id: Pubkey, swap_info_key: Pubkey) -> Result<Instruction, ProgramError> {
+    let data = vec!(UPDATE_TWAP_TAG);
+
+    let accounts = vec![AccountMeta::new(swap_info_key, false)];
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
@@ -9,6 +9,7 @@ pub mod curve;
 pub mod error;
 pub mod math;
 pub mod state;
+pub mod utils;
 
 // Export current solana-program types for downstream users who may also be
 // building with a different solana-program version
@@ -17,4 +18,7 @@ pub use solana_program;
 /// Serum-Dex V3 mainnet program id
 pub const SERUM_DEX_V3_PROGRAM_ID: &str = "9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin";
 
+/// Referrer registered by users nobody referred, earns no fee
+pub const DUMMY_REFERRER_ADDRESS: &str = "BFmmccapAXYaJDNCk5wHnnYcJg3M9eyUjUmSd9AkjbbH";
+
 solana_program::declare_id!("D3UC98n8VwyUUJFQeNshAb1VeZWKXjgWMzvAzK7JX3r7");
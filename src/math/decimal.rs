@@ -22,6 +22,11 @@ construct_uint! {
     pub struct U192(3);
 }
 
+construct_uint! {
+    /// Intermediate product of two U192 values
+    pub struct U384(6);
+}
+
 /// The Decimal number type U192 which is a list of u64 that represents a 192 bit integer
 /// The larget U192 us 2^192-1 = 6277101735386680763835789423207666416102355444464034512895
 /// We need this threshold to be (2^192-1)/(10^12)
@@ -29,6 +34,9 @@ construct_uint! {
 /// 6277101735386680763835789423207666416102355444 = 18446744*(2^128) + 1359701234448236303(2^64) + 7964717695892140532
 static SQRT_THRESHOLD: U192 = U192([7964717695892140532u64, 1359701234448236303u64, 18446744u64]);
 
+/// Binary digits of the exponent fraction evaluated by `try_powf`, 2^-40 is below the WAD precision
+const POWF_FRACTION_BITS: u8 = 40;
+
 /// Large decimal values, precise to 18 digits
 #[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Eq, Ord)]
 pub struct Decimal(pub U192);
@@ -189,6 +197,61 @@ impl Decimal {
                 .ok_or(SwapError::CalculationFailure)?,
         ))
     }
+
+    /// Calculates `self * mul / div` rounded down, the product does not overflow
+    pub fn try_mul_div(&self, mul: Decimal, div: Decimal) -> Result<Self, ProgramError> {
+        let widen = |value: U192| U384([value.0[0], value.0[1], value.0[2], 0, 0, 0]);
+        let result = widen(self.0)
+            .checked_mul(widen(mul.0))
+            .ok_or(SwapError::CalculationFailure)?
+            .checked_div(widen(div.0))
+            .ok_or(SwapError::CalculationFailure)?;
+        if result.0[3..].iter().any(|word| *word != 0) {
+            return Err(SwapError::CalculationFailure.into());
+        }
+        Ok(Self(U192([result.0[0], result.0[1], result.0[2]])))
+    }
+
+    /// Calculates base^exp
+    pub fn try_pow(&self, mut exp: u64) -> Result<Self, ProgramError> {
+        let mut base = *self;
+        let mut ret = if exp % 2 != 0 { base } else { Self::one() };
+
+        while exp > 0 {
+            exp /= 2;
+            base = base.try_mul(base)?;
+
+            if exp % 2 != 0 {
+                ret = ret.try_mul(base)?;
+            }
+        }
+
+        Ok(ret)
+    }
+
+    /// Calculates base^exp for a fractional exponent without floating point.
+    /// The integer part of the exponent uses `try_pow`, each binary digit of the
+    /// fractional part multiplies in the matching repeated square root of the base.
+    pub fn try_powf(&self, exp: Decimal) -> Result<Self, ProgramError> {
+        let integer_part = exp.try_floor_u64()?;
+        let mut fraction = exp.try_sub(Self::from(integer_part))?;
+        let mut ret = self.try_pow(integer_part)?;
+
+        let mut root = *self;
+        for _ in 0..POWF_FRACTION_BITS {
+            if fraction.is_zero() {
+                break;
+            }
+            root = root.sqrt()?;
+            fraction = fraction.try_mul(2u64)?;
+            if fraction >= Self::one() {
+                ret = ret.try_mul(root)?;
+                fraction = fraction.try_sub(Self::one())?;
+            }
+        }
+
+        Ok(ret)
+    }
 }
 
 impl fmt::Display for Decimal {
@@ -318,6 +381,61 @@ mod test {
         println!("float {}", back_to_float);
     }
 
+    #[test]
+    fn test_try_pow() {
+        assert_eq!(Decimal::from(2u64).try_pow(0).unwrap(), Decimal::one());
+        assert_eq!(Decimal::from(2u64).try_pow(3).unwrap(), Decimal::from(8u64));
+        assert_eq!(
+            Decimal::from_percent(50).try_pow(2).unwrap(),
+            Decimal::from_percent(25)
+        );
+        assert!(Decimal::from(u64::MAX).try_pow(4).is_err());
+    }
+
+    #[test]
+    fn test_try_mul_div() {
+        let large = Decimal::from(u64::MAX as u128 * 1_000);
+        assert!(large.try_mul(large).is_err());
+        assert_eq!(large.try_mul_div(large, large).unwrap(), large);
+        assert_eq!(
+            Decimal::from(10u64)
+                .try_mul_div(Decimal::one(), Decimal::from(3u64))
+                .unwrap(),
+            Decimal::from_scaled_val(3_333_333_333_333)
+        );
+        assert!(large
+            .try_mul_div(large, Decimal::from_scaled_val(1))
+            .is_err());
+        assert!(large.try_mul_div(large, Decimal::zero()).is_err());
+    }
+
+    #[test]
+    fn test_try_powf() {
+        assert_eq!(
+            Decimal::from(4u64)
+                .try_powf(Decimal::from_percent(50))
+                .unwrap(),
+            Decimal::from(2u64)
+        );
+        assert_eq!(
+            Decimal::from(2u64).try_powf(Decimal::from(3u64)).unwrap(),
+            Decimal::from(8u64)
+        );
+        assert_eq!(
+            Decimal::from(7u64).try_powf(Decimal::zero()).unwrap(),
+            Decimal::one()
+        );
+
+        for (base, exp) in [(0.5f64, 0.3f64), (0.9, 2.75), (1.5, 1.25), (123.0, 0.1)] {
+            let result = Decimal::from_float64(base)
+                .try_powf(Decimal::from_float64(exp))
+                .unwrap()
+                .to_float64()
+                .unwrap();
+            assert!((result - base.powf(exp)).abs() < 1e-9);
+        }
+    }
+
     // #[test]
     // fn test_decimal() {
     //     assert_eq!(Decimal::from(0u64), Decimal::zero());
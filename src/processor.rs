@@ -3,20 +3,40 @@
 #![allow(clippy::too_many_arguments)]
 
 // use std::{cmp::min, convert::TryInto, str::FromStr};
+use std::{convert::TryFrom, str::FromStr};
 
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
-    program_pack::Pack
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::Pack,
+    rent::Rent,
+    system_instruction,
 };
 
 use solana_program::msg;
 use solana_program::pubkey::Pubkey;
 use solana_program::entrypoint::ProgramResult;
+use solana_program::sysvar::{clock::Clock, Sysvar};
 
 use crate::{
-    state::MockedSwap,
-    curve::calc::simple_powf,
-    math::Decimal
+    error::SwapError,
+    instruction::{
+        FarmDepositData, FarmInitializeData, FarmInstruction, FarmWithdrawData,
+        InitializeConfigData, APPLY_NEW_ADMIN_TAG, CANCEL_ADMIN_TRANSFER_TAG,
+        COMMIT_NEW_ADMIN_TAG, FARM_INSTRUCTION_TAG, INITIALIZE_CONFIG_TAG, MIGRATE_ACCOUNT_TAG,
+        MOCK_SWAP_TAG, SET_PAUSED_TAG, SET_REFERRER_TAG, UPDATE_TWAP_TAG,
+    },
+    state::{
+        account_type_of, migrate_account, unpack_bool, AccountType, ConfigInfo, FarmInfo,
+        FarmInfoViewMut, FarmPosition, FarmUser, MockedSwap, SwapInfo, SwapInfoViewMut,
+        UserReferrerData, ADMIN_TRANSFER_DELAY, CONFIG_SEED, CONFIG_VERSION, FARM_VERSION,
+        USER_REFERRER_VERSION,
+    },
+    utils::{token_transfer, unpack_token_account},
+    DUMMY_REFERRER_ADDRESS,
+    curve::{verify_swap, CurveType, FeeBreakdown, PoolState, TokenDecimals},
+    math::{Decimal, TryAdd, TrySub},
 };
 
 // use solana_program::pubkey::PubkeyError;
@@ -58,7 +78,7 @@ use crate::{
 
 
 /// Processes an [Instruction](enum.Instruction.html).
-pub fn process(program_id: &Pubkey, accounts: &[AccountInfo], _input: &[u8]) -> ProgramResult {
+pub fn process(program_id: &Pubkey, accounts: &[AccountInfo], input: &[u8]) -> ProgramResult {
 
     // match InstructionType::check(input) {
     //     Some(InstructionType::Admin) => process_admin_instruction(program_id, accounts, input),
@@ -69,25 +89,766 @@ pub fn process(program_id: &Pubkey, accounts: &[AccountInfo], _input: &[u8]) ->
     //     Some(InstructionType::Farm) => process_farm_instruction(program_id, accounts, input),
     //     _ => Err(ProgramError::InvalidInstructionData),
     // }
-    process_native_powf(program_id, accounts)
+    match input.first() {
+        Some(&MOCK_SWAP_TAG) => process_native_powf(program_id, accounts),
+        Some(&MIGRATE_ACCOUNT_TAG) => process_migrate_account(program_id, accounts),
+        Some(&INITIALIZE_CONFIG_TAG) => {
+            process_initialize_config(program_id, accounts, &input[1..])
+        }
+        Some(&SET_PAUSED_TAG) => process_set_paused(program_id, accounts, &input[1..]),
+        Some(&COMMIT_NEW_ADMIN_TAG) => process_commit_new_admin(program_id, accounts),
+        Some(&APPLY_NEW_ADMIN_TAG) => process_apply_new_admin(program_id, accounts),
+        Some(&CANCEL_ADMIN_TRANSFER_TAG) => process_cancel_admin_transfer(program_id, accounts),
+        Some(&FARM_INSTRUCTION_TAG) => process_farm_instruction(program_id, accounts, &input[1..]),
+        Some(&SET_REFERRER_TAG) => process_set_referrer(program_id, accounts),
+        Some(&UPDATE_TWAP_TAG) => process_update_twap(program_id, accounts),
+        _ => Err(ProgramError::InvalidInstructionData),
+    }
+}
+
+/// Unpack the config account, which must be the one config of the program
+fn unpack_config(program_id: &Pubkey, config_info: &AccountInfo) -> Result<ConfigInfo, ProgramError> {
+    if config_info.owner != program_id {
+        return Err(SwapError::InvalidAccountOwner.into());
+    }
+    let config = ConfigInfo::unpack(&config_info.data.borrow())?;
+    if *config_info.key != ConfigInfo::address(program_id, config.bump_seed)? {
+        return Err(SwapError::InvalidProgramAddress.into());
+    }
+    Ok(config)
+}
+
+/// Check `admin_info` is the config admin and signed the instruction
+fn check_admin(config: &ConfigInfo, admin_info: &AccountInfo) -> ProgramResult {
+    if config.admin_key != *admin_info.key {
+        return Err(SwapError::InvalidAdmin.into());
+    }
+    if !admin_info.is_signer {
+        return Err(SwapError::InvalidSigner.into());
+    }
+    Ok(())
+}
+
+fn process_initialize_config(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    input: &[u8],
+) -> ProgramResult {
+    let data = InitializeConfigData::unpack(input)?;
+    let account_info_iter = &mut accounts.iter();
+    let config_info = next_account_info(account_info_iter)?;
+    let admin_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+
+    let (config_key, bump_seed) = ConfigInfo::find_address(program_id);
+    if *config_info.key != config_key {
+        return Err(SwapError::InvalidProgramAddress.into());
+    }
+    if config_info.owner == program_id {
+        return Err(SwapError::AlreadyInUse.into());
+    }
+    if !admin_info.is_signer {
+        return Err(SwapError::InvalidSigner.into());
+    }
+    data.fees.validate()?;
+
+    create_program_account(
+        program_id,
+        admin_info,
+        config_info,
+        system_program_info,
+        ConfigInfo::LEN,
+        &[CONFIG_SEED, &[bump_seed]],
+    )?;
+    ConfigInfo::pack(
+        ConfigInfo {
+            version: CONFIG_VERSION,
+            is_initialized: true,
+            is_paused: false,
+            bump_seed,
+            admin_key: *admin_info.key,
+            future_admin_key: Pubkey::default(),
+            future_admin_deadline: 0,
+            fees: data.fees,
+            pyth_program_id: data.pyth_program_id,
+            serum_program_id: data.serum_program_id,
+        },
+        &mut config_info.data.borrow_mut(),
+    )
+}
+
+fn process_set_paused(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    input: &[u8],
+) -> ProgramResult {
+    if input.len() != 1 {
+        return Err(SwapError::InstructionUnpackError.into());
+    }
+    let is_paused = unpack_bool(&[input[0]])?;
+    let account_info_iter = &mut accounts.iter();
+    let config_info = next_account_info(account_info_iter)?;
+    let admin_info = next_account_info(account_info_iter)?;
+
+    let mut config = unpack_config(program_id, config_info)?;
+    check_admin(&config, admin_info)?;
+    config.is_paused = is_paused;
+    ConfigInfo::pack(config, &mut config_info.data.borrow_mut())
+}
+
+/// Referrer of users nobody referred
+fn dummy_referrer() -> Result<Pubkey, ProgramError> {
+    Pubkey::from_str(DUMMY_REFERRER_ADDRESS).map_err(|_| SwapError::InvalidReferrer.into())
+}
+
+/// Validate the referral of `user_info` and return the token account owed the referrer fee.
+/// `user_referrer_info` must be the registration address of the user. Users who never
+/// registered, or registered the dummy referrer, pass the dummy referrer and get none.
+fn check_referrer(
+    program_id: &Pubkey,
+    config_key: &Pubkey,
+    user_info: &AccountInfo,
+    user_referrer_info: &AccountInfo,
+    referrer_info: &AccountInfo,
+) -> Result<Option<Pubkey>, ProgramError> {
+    let (user_referrer_key, _) =
+        UserReferrerData::find_address(program_id, config_key, user_info.key);
+    if *user_referrer_info.key != user_referrer_key {
+        return Err(SwapError::InvalidProgramAddress.into());
+    }
+    // only the program creates registrations, any other owner means there is none
+    if user_referrer_info.owner != program_id {
+        if *referrer_info.key != dummy_referrer()? {
+            return Err(SwapError::InvalidReferrer.into());
+        }
+        return Ok(None);
+    }
+    let user_referrer = UserReferrerData::unpack(&user_referrer_info.data.borrow())?;
+    if user_referrer.config_key != *config_key
+        || user_referrer.owner != *user_info.key
+        || user_referrer.referrer != *referrer_info.key
+    {
+        return Err(SwapError::InvalidReferrer.into());
+    }
+    if user_referrer.referrer == dummy_referrer()? {
+        return Ok(None);
+    }
+    Ok(Some(user_referrer.referrer))
+}
+
+fn process_set_referrer(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let config_info = next_account_info(account_info_iter)?;
+    let user_referrer_info = next_account_info(account_info_iter)?;
+    let owner_info = next_account_info(account_info_iter)?;
+    let referrer_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+
+    unpack_config(program_id, config_info)?;
+    let (user_referrer_key, bump_seed) =
+        UserReferrerData::find_address(program_id, config_info.key, owner_info.key);
+    if *user_referrer_info.key != user_referrer_key {
+        return Err(SwapError::InvalidProgramAddress.into());
+    }
+    if user_referrer_info.owner == program_id {
+        return Err(SwapError::AlreadyInUse.into());
+    }
+    if !owner_info.is_signer {
+        return Err(SwapError::InvalidSigner.into());
+    }
+    // a real referrer is a token account of somebody else
+    if *referrer_info.key != dummy_referrer()?
+        && unpack_token_account(referrer_info)
+            .map_err(|_| SwapError::InvalidReferrer)?
+            .owner
+            == *owner_info.key
+    {
+        return Err(SwapError::InvalidReferrer.into());
+    }
+
+    create_program_account(
+        program_id,
+        owner_info,
+        user_referrer_info,
+        system_program_info,
+        UserReferrerData::LEN,
+        &[config_info.key.as_ref(), owner_info.key.as_ref(), &[bump_seed]],
+    )?;
+    UserReferrerData::pack(
+        UserReferrerData {
+            version: USER_REFERRER_VERSION,
+            is_initialized: true,
+            config_key: *config_info.key,
+            owner: *owner_info.key,
+            referrer: *referrer_info.key,
+        },
+        &mut user_referrer_info.data.borrow_mut(),
+    )
+}
+
+fn process_commit_new_admin(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let config_info = next_account_info(account_info_iter)?;
+    let admin_info = next_account_info(account_info_iter)?;
+    let new_admin_info = next_account_info(account_info_iter)?;
+
+    let mut config = unpack_config(program_id, config_info)?;
+    check_admin(&config, admin_info)?;
+    let clock = Clock::get()?;
+    // an expired proposal no longer blocks a new one
+    if config.has_active_transfer() && clock.unix_timestamp <= config.future_admin_deadline {
+        return Err(SwapError::ActiveTransfer.into());
+    }
+
+    config.future_admin_key = *new_admin_info.key;
+    config.future_admin_deadline = clock
+        .unix_timestamp
+        .checked_add(ADMIN_TRANSFER_DELAY)
+        .ok_or(SwapError::CalculationFailure)?;
+    ConfigInfo::pack(config, &mut config_info.data.borrow_mut())
+}
+
+fn process_apply_new_admin(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let config_info = next_account_info(account_info_iter)?;
+    let new_admin_info = next_account_info(account_info_iter)?;
+
+    let mut config = unpack_config(program_id, config_info)?;
+    if !config.has_active_transfer() {
+        return Err(SwapError::NoActiveTransfer.into());
+    }
+    if config.future_admin_key != *new_admin_info.key {
+        return Err(SwapError::InvalidAdmin.into());
+    }
+    if !new_admin_info.is_signer {
+        return Err(SwapError::InvalidSigner.into());
+    }
+    if Clock::get()?.unix_timestamp > config.future_admin_deadline {
+        return Err(SwapError::AdminDeadlineExceeded.into());
+    }
+
+    config.admin_key = config.future_admin_key;
+    config.future_admin_key = Pubkey::default();
+    config.future_admin_deadline = 0;
+    ConfigInfo::pack(config, &mut config_info.data.borrow_mut())
+}
+
+fn process_cancel_admin_transfer(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let config_info = next_account_info(account_info_iter)?;
+    let admin_info = next_account_info(account_info_iter)?;
+
+    let mut config = unpack_config(program_id, config_info)?;
+    check_admin(&config, admin_info)?;
+    if !config.has_active_transfer() {
+        return Err(SwapError::NoActiveTransfer.into());
+    }
+
+    config.future_admin_key = Pubkey::default();
+    config.future_admin_deadline = 0;
+    ConfigInfo::pack(config, &mut config_info.data.borrow_mut())
+}
+
+fn process_farm_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    input: &[u8],
+) -> ProgramResult {
+    match FarmInstruction::unpack(input)? {
+        FarmInstruction::FarmInitialize(data) => {
+            msg!("Instruction: Farm Initialize");
+            process_farm_initialize(program_id, accounts, data)
+        }
+        FarmInstruction::FarmDeposit(data) => {
+            msg!("Instruction: Farm Deposit");
+            process_farm_deposit(program_id, accounts, data)
+        }
+        FarmInstruction::FarmWithdraw(data) => {
+            msg!("Instruction: Farm Withdraw");
+            process_farm_withdraw(program_id, accounts, data)
+        }
+        FarmInstruction::FarmClaim => {
+            msg!("Instruction: Farm Claim");
+            process_farm_claim(program_id, accounts)
+        }
+    }
+}
+
+/// Create the program derived `account` with `len` bytes owned by the program, `payer` funds
+/// the rent exemption. Lamports already sent to the address count towards it.
+fn create_program_account<'a>(
+    program_id: &Pubkey,
+    payer_info: &AccountInfo<'a>,
+    account_info: &AccountInfo<'a>,
+    system_program_info: &AccountInfo<'a>,
+    len: usize,
+    signer_seeds: &[&[u8]],
+) -> ProgramResult {
+    let top_up = Rent::get()?
+        .minimum_balance(len)
+        .saturating_sub(account_info.lamports());
+    if top_up > 0 {
+        invoke(
+            &system_instruction::transfer(payer_info.key, account_info.key, top_up),
+            &[payer_info.clone(), account_info.clone(), system_program_info.clone()],
+        )?;
+    }
+    invoke_signed(
+        &system_instruction::allocate(account_info.key, len as u64),
+        &[account_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+    invoke_signed(
+        &system_instruction::assign(account_info.key, program_id),
+        &[account_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )
+}
+
+/// Authority owning the token accounts of a farm
+fn farm_authority(program_id: &Pubkey, farm_key: &Pubkey, bump_seed: u8) -> Result<Pubkey, ProgramError> {
+    Pubkey::create_program_address(&[farm_key.as_ref(), &[bump_seed]], program_id)
+        .map_err(|_| SwapError::InvalidProgramAddress.into())
+}
+
+/// Unpack the farm account, which must be owned by the program
+fn unpack_farm(program_id: &Pubkey, farm_info: &AccountInfo) -> Result<FarmInfo, ProgramError> {
+    if farm_info.owner != program_id {
+        return Err(SwapError::InvalidAccountOwner.into());
+    }
+    FarmInfo::unpack(&farm_info.data.borrow())
+}
+
+/// Unpack the position of `owner_info` in the farm `farm_key`
+fn unpack_farm_user(
+    program_id: &Pubkey,
+    farm_user_info: &AccountInfo,
+    farm_key: &Pubkey,
+    owner_info: &AccountInfo,
+) -> Result<FarmUser, ProgramError> {
+    if farm_user_info.owner != program_id {
+        return Err(SwapError::InvalidAccountOwner.into());
+    }
+    let farm_user = FarmUser::unpack(&farm_user_info.data.borrow())?;
+    if farm_user.farm_key != *farm_key {
+        return Err(SwapError::InvalidPositionKey.into());
+    }
+    if farm_user.owner != *owner_info.key {
+        return Err(SwapError::InvalidOwner.into());
+    }
+    if !owner_info.is_signer {
+        return Err(SwapError::InvalidSigner.into());
+    }
+    Ok(farm_user)
+}
+
+fn process_farm_initialize(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: FarmInitializeData,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let config_info = next_account_info(account_info_iter)?;
+    let farm_info = next_account_info(account_info_iter)?;
+    let admin_info = next_account_info(account_info_iter)?;
+    let pool_mint_info = next_account_info(account_info_iter)?;
+    let pool_token_info = next_account_info(account_info_iter)?;
+    let reward_token_info = next_account_info(account_info_iter)?;
+
+    let config = unpack_config(program_id, config_info)?;
+    check_admin(&config, admin_info)?;
+    if farm_info.owner != program_id {
+        return Err(SwapError::InvalidAccountOwner.into());
+    }
+    if FarmInfo::unpack_unchecked(&farm_info.data.borrow())?.is_initialized {
+        return Err(SwapError::AlreadyInUse.into());
+    }
+    if data.min_claim_period < 0 {
+        return Err(SwapError::InvalidInput.into());
+    }
+
+    let authority = farm_authority(program_id, farm_info.key, data.bump_seed)?;
+    let pool_token = unpack_token_account(pool_token_info)?;
+    let reward_token = unpack_token_account(reward_token_info)?;
+    if pool_token.owner != authority || reward_token.owner != authority {
+        return Err(SwapError::InvalidOwner.into());
+    }
+    if pool_token.mint != *pool_mint_info.key {
+        return Err(SwapError::IncorrectMint.into());
+    }
+
+    FarmInfo::pack(
+        FarmInfo {
+            version: FARM_VERSION,
+            is_initialized: true,
+            bump_seed: data.bump_seed,
+            config_key: *config_info.key,
+            pool_mint: *pool_mint_info.key,
+            pool_token: *pool_token_info.key,
+            reward_token: *reward_token_info.key,
+            reward_per_second: data.reward_per_second,
+            min_claim_period: data.min_claim_period,
+            acc_reward_per_share: Decimal::zero(),
+            last_update_timestamp: Clock::get()?.unix_timestamp,
+            total_staked: 0,
+        },
+        &mut farm_info.data.borrow_mut(),
+    )
+}
+
+fn process_farm_deposit(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: FarmDepositData,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let config_info = next_account_info(account_info_iter)?;
+    let farm_info = next_account_info(account_info_iter)?;
+    let farm_user_info = next_account_info(account_info_iter)?;
+    let owner_info = next_account_info(account_info_iter)?;
+    let source_info = next_account_info(account_info_iter)?;
+    let pool_token_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+
+    let mut farm = unpack_farm(program_id, farm_info)?;
+    if *config_info.key != farm.config_key {
+        return Err(SwapError::InvalidAccount.into());
+    }
+    if unpack_config(program_id, config_info)?.is_paused {
+        return Err(SwapError::IsPaused.into());
+    }
+    if *pool_token_info.key != farm.pool_token {
+        return Err(SwapError::IncorrectSwapAccount.into());
+    }
+    let timestamp = Clock::get()?.unix_timestamp;
+
+    // the first deposit creates the position in an account allocated to the program
+    if farm_user_info.owner != program_id {
+        return Err(SwapError::InvalidAccountOwner.into());
+    }
+    let is_new_position = !FarmUser::unpack_unchecked(&farm_user_info.data.borrow())?.is_initialized;
+    let mut farm_user = if is_new_position {
+        if !owner_info.is_signer {
+            return Err(SwapError::InvalidSigner.into());
+        }
+        FarmUser {
+            version: FARM_VERSION,
+            is_initialized: true,
+            farm_key: *farm_info.key,
+            owner: *owner_info.key,
+            position: FarmPosition {
+                last_claim_timestamp: timestamp,
+                ..FarmPosition::default()
+            },
+        }
+    } else {
+        unpack_farm_user(program_id, farm_user_info, farm_info.key, owner_info)?
+    };
+
+    farm_user.position.deposit(&mut farm, data.amount, timestamp)?;
+    token_transfer(
+        token_program_info.clone(),
+        source_info.clone(),
+        pool_token_info.clone(),
+        owner_info.clone(),
+        data.amount,
+        &[],
+    )?;
+
+    FarmInfoViewMut::new(&mut farm_info.data.borrow_mut())?.set_rewards(&farm);
+    FarmUser::pack(farm_user, &mut farm_user_info.data.borrow_mut())
+}
+
+fn process_farm_withdraw(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: FarmWithdrawData,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let farm_info = next_account_info(account_info_iter)?;
+    let farm_user_info = next_account_info(account_info_iter)?;
+    let owner_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+    let pool_token_info = next_account_info(account_info_iter)?;
+    let destination_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+
+    let mut farm = unpack_farm(program_id, farm_info)?;
+    let mut farm_user = unpack_farm_user(program_id, farm_user_info, farm_info.key, owner_info)?;
+    if *authority_info.key != farm_authority(program_id, farm_info.key, farm.bump_seed)? {
+        return Err(SwapError::InvalidProgramAddress.into());
+    }
+    if *pool_token_info.key != farm.pool_token {
+        return Err(SwapError::IncorrectSwapAccount.into());
+    }
+
+    // withdrawals stay open while the program is paused
+    farm_user
+        .position
+        .withdraw(&mut farm, data.amount, Clock::get()?.unix_timestamp)?;
+    token_transfer(
+        token_program_info.clone(),
+        pool_token_info.clone(),
+        destination_info.clone(),
+        authority_info.clone(),
+        data.amount,
+        &[&[farm_info.key.as_ref(), &[farm.bump_seed]]],
+    )?;
+
+    FarmInfoViewMut::new(&mut farm_info.data.borrow_mut())?.set_rewards(&farm);
+    FarmUser::pack(farm_user, &mut farm_user_info.data.borrow_mut())
+}
+
+fn process_farm_claim(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let farm_info = next_account_info(account_info_iter)?;
+    let farm_user_info = next_account_info(account_info_iter)?;
+    let owner_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+    let reward_token_info = next_account_info(account_info_iter)?;
+    let destination_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+
+    let mut farm = unpack_farm(program_id, farm_info)?;
+    let mut farm_user = unpack_farm_user(program_id, farm_user_info, farm_info.key, owner_info)?;
+    if *authority_info.key != farm_authority(program_id, farm_info.key, farm.bump_seed)? {
+        return Err(SwapError::InvalidProgramAddress.into());
+    }
+    if *reward_token_info.key != farm.reward_token {
+        return Err(SwapError::IncorrectSwapAccount.into());
+    }
+
+    let amount = farm_user
+        .position
+        .claim(&mut farm, Clock::get()?.unix_timestamp)?;
+    token_transfer(
+        token_program_info.clone(),
+        reward_token_info.clone(),
+        destination_info.clone(),
+        authority_info.clone(),
+        amount,
+        &[&[farm_info.key.as_ref(), &[farm.bump_seed]]],
+    )?;
+
+    FarmInfoViewMut::new(&mut farm_info.data.borrow_mut())?.set_rewards(&farm);
+    FarmUser::pack(farm_user, &mut farm_user_info.data.borrow_mut())
+}
+
+/// Accumulate the marginal price of a pool, reading and writing the swap info in place
+fn process_update_twap(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let swap_info_info = next_account_info(account_info_iter)?;
+    if swap_info_info.owner != program_id {
+        return Err(SwapError::InvalidAccountOwner.into());
+    }
+
+    let clock = Clock::get()?;
+    let mut data = swap_info_info.data.borrow_mut();
+    let mut swap_info = SwapInfoViewMut::new(&mut data)?;
+    let view = swap_info.as_view();
+    // the normalized marginal price is in whole tokens, the unit of the market price
+    let pool = view
+        .token_decimals()?
+        .normalize_reserves(&view.pool_state())?;
+    let marginal_price = view.curve_type()?.marginal_price(&pool)?;
+    let mut price_accumulator = view.price_accumulator()?;
+    price_accumulator.update(marginal_price, &clock)?;
+    swap_info.set_price_accumulator(&price_accumulator);
+    Ok(())
+}
+
+fn process_migrate_account(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let account_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let config_info = next_account_info(account_info_iter)?;
+    let admin_info = next_account_info(account_info_iter)?;
+
+    if account_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if !payer_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let config = unpack_config(program_id, config_info)?;
+    check_admin(&config, admin_info)?;
+
+    let account_type = account_type_of(&account_info.data.borrow())?;
+    match account_type {
+        AccountType::MockedSwap => {
+            migrate_account::<MockedSwap>(account_info, payer_info, system_program_info)
+        }
+        AccountType::SwapInfo => {
+            migrate_account::<SwapInfo>(account_info, payer_info, system_program_info)
+        }
+        AccountType::ConfigInfo => {
+            migrate_account::<ConfigInfo>(account_info, payer_info, system_program_info)
+        }
+        AccountType::FarmInfo => {
+            migrate_account::<FarmInfo>(account_info, payer_info, system_program_info)
+        }
+        AccountType::FarmUser => {
+            migrate_account::<FarmUser>(account_info, payer_info, system_program_info)
+        }
+        AccountType::UserReferrerData => {
+            migrate_account::<UserReferrerData>(account_info, payer_info, system_program_info)
+        }
+        AccountType::Uninitialized => Err(ProgramError::InvalidAccountData),
+    }
 }
 
 fn process_native_powf(
-    _program_id: &Pubkey,
+    program_id: &Pubkey,
     accounts: &[AccountInfo],
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
     let mocked_swap_info = next_account_info(account_info_iter)?;
-    let mocked_swap = MockedSwap::unpack(&mocked_swap_info.data.borrow())?;
-    
-    let result = simple_powf(
-        Decimal::from(mocked_swap.market_price), 
-        Decimal::from(mocked_swap.target_reserve_a), 
-        Decimal::from(mocked_swap.target_reserve_b), 
-        Decimal::from(mocked_swap.current_reserve_a), 
-        Decimal::from(mocked_swap.current_reserve_b), 
-        Decimal::from(13u64))?;
-    
-    msg!("result: {}", result);
+    let payer_info = next_account_info(account_info_iter)?;
+    let config_info = next_account_info(account_info_iter)?;
+    let user_referrer_info = next_account_info(account_info_iter)?;
+    let referrer_info = next_account_info(account_info_iter)?;
+    let source_info = next_account_info(account_info_iter)?;
+    let destination_info = next_account_info(account_info_iter)?;
+    let pool_token_a_info = next_account_info(account_info_iter)?;
+    let pool_token_b_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+
+    if mocked_swap_info.owner != program_id {
+        return Err(SwapError::InvalidAccountOwner.into());
+    }
+    let config = unpack_config(program_id, config_info)?;
+    if config.is_paused {
+        return Err(SwapError::IsPaused.into());
+    }
+    if !payer_info.is_signer {
+        return Err(SwapError::InvalidSigner.into());
+    }
+    // without a referrer the referrer share stays in the pool
+    let referrer = check_referrer(
+        program_id,
+        config_info.key,
+        payer_info,
+        user_referrer_info,
+        referrer_info,
+    )?;
+    let mut mocked_swap = MockedSwap::unpack(&mocked_swap_info.data.borrow())?;
+    if mocked_swap.config_key != *config_info.key {
+        return Err(SwapError::InvalidAccount.into());
+    }
+    if *pool_token_a_info.key != mocked_swap.token_a
+        || *pool_token_b_info.key != mocked_swap.token_b
+    {
+        return Err(SwapError::IncorrectSwapAccount.into());
+    }
+    let (authority_key, bump_seed) = MockedSwap::find_authority(program_id, mocked_swap_info.key);
+    if *authority_info.key != authority_key {
+        return Err(SwapError::InvalidProgramAddress.into());
+    }
+    // curve math runs on amounts normalized to the mint with the most decimals
+    let decimals = TokenDecimals::new(mocked_swap.token_a_decimals, mocked_swap.token_b_decimals)?;
+    let pool = PoolState::try_from(&mocked_swap)?;
+    let amount_in = 13;
+    let input_amount = decimals.normalize_a(amount_in)?;
+
+    let output = CurveType::Oracle.swap_exact_in(&pool, input_amount)?;
+    // amounts leaving the pool round down, fees are charged on the raw output
+    let result = mocked_swap
+        .fees
+        .apply(decimals.denormalize_b_floor(output)?, referrer.is_some())?;
+    let FeeBreakdown {
+        amount_out,
+        lp_fee,
+        admin_fee,
+        referrer_fee,
+        ..
+    } = result;
+    // the lp fee stays in the pool, everything else leaves it
+    let amount_leaving = amount_out
+        .checked_add(admin_fee)
+        .and_then(|amount| amount.checked_add(referrer_fee))
+        .ok_or(SwapError::CalculationFailure)?;
+    verify_swap(
+        &CurveType::Oracle,
+        &pool,
+        input_amount,
+        decimals.normalize_b(amount_leaving)?,
+    )?;
+    msg!("result: {}", amount_out);
+    msg!(
+        "fees: lp {}, admin {}, referrer {}",
+        lp_fee,
+        admin_fee,
+        referrer_fee
+    );
+    let authority_seeds: &[&[u8]] = &[mocked_swap_info.key.as_ref(), &[bump_seed]];
+    token_transfer(
+        token_program_info.clone(),
+        source_info.clone(),
+        pool_token_a_info.clone(),
+        payer_info.clone(),
+        amount_in,
+        &[],
+    )?;
+    token_transfer(
+        token_program_info.clone(),
+        pool_token_b_info.clone(),
+        destination_info.clone(),
+        authority_info.clone(),
+        amount_out,
+        &[authority_seeds],
+    )?;
+    if referrer.is_some() {
+        // the fee is paid in the output token
+        if unpack_token_account(referrer_info)?.mint
+            != unpack_token_account(pool_token_b_info)?.mint
+        {
+            return Err(SwapError::IncorrectMint.into());
+        }
+        token_transfer(
+            token_program_info.clone(),
+            pool_token_b_info.clone(),
+            referrer_info.clone(),
+            authority_info.clone(),
+            referrer_fee,
+            &[authority_seeds],
+        )?;
+    }
+    // the admin fee stays in the token b account, outside the reserves
+    mocked_swap.current_reserve_a = mocked_swap
+        .current_reserve_a
+        .try_add(Decimal::from(amount_in))?;
+    mocked_swap.current_reserve_b = mocked_swap
+        .current_reserve_b
+        .try_sub(Decimal::from(amount_leaving))?;
+    // the twap weighs the price the pool was left at by the previous update, this trade
+    // only sets the price of the time to come
+    let marginal_price = CurveType::Oracle.marginal_price(&PoolState::try_from(&mocked_swap)?)?;
+    mocked_swap
+        .price_accumulator
+        .update(marginal_price, &Clock::get()?)?;
+    MockedSwap::pack(mocked_swap, &mut mocked_swap_info.data.borrow_mut())?;
     Ok(())
 }
\ No newline at end of file
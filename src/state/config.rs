@@ -0,0 +1,327 @@
+//! Program-wide configuration account
+
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+
+use solana_program::{
+    clock::UnixTimestamp,
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+    pubkey::Pubkey,
+};
+
+use crate::{curve::Fees, error::SwapError, math::Rate};
+
+use super::{
+    pack_account_type, pack_bool, pack_rate, unpack_account_type, unpack_bool, unpack_rate,
+    AccountLayout, AccountType, VersionedAccount,
+};
+
+/// Current version of the config layout
+pub const CONFIG_VERSION: u8 = 1;
+/// Seed of the config address
+pub const CONFIG_SEED: &[u8] = b"config";
+/// Seconds the proposed admin has to accept an admin transfer
+pub const ADMIN_TRANSFER_DELAY: UnixTimestamp = 3 * 24 * 60 * 60;
+
+/// Program-wide configuration
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConfigInfo {
+    /// layout version
+    pub version: u8,
+    /// whether the config is initialized
+    pub is_initialized: bool,
+    /// whether every pool of the program is paused
+    pub is_paused: bool,
+    /// bump seed of the config address
+    pub bump_seed: u8,
+    /// admin of the program
+    pub admin_key: Pubkey,
+    /// admin proposed by the current one, default while no transfer is active
+    pub future_admin_key: Pubkey,
+    /// unix timestamp after which the proposed admin can no longer accept
+    pub future_admin_deadline: UnixTimestamp,
+    /// fees of newly created pools
+    pub fees: Fees,
+    /// pyth oracle program
+    pub pyth_program_id: Pubkey,
+    /// serum dex program used as a fallback price source
+    pub serum_program_id: Pubkey,
+}
+
+impl Default for ConfigInfo {
+    fn default() -> Self {
+        Self {
+            version: 0,
+            is_initialized: false,
+            is_paused: false,
+            bump_seed: 0,
+            admin_key: Pubkey::default(),
+            future_admin_key: Pubkey::default(),
+            future_admin_deadline: 0,
+            // zeroed like a freshly allocated account
+            fees: Fees {
+                trade_fee: Rate::zero(),
+                admin_fee: Rate::zero(),
+                referrer_fee: Rate::zero(),
+            },
+            pyth_program_id: Pubkey::default(),
+            serum_program_id: Pubkey::default(),
+        }
+    }
+}
+
+impl ConfigInfo {
+    /// Address and bump seed of the config, one per program
+    pub fn find_address(program_id: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[CONFIG_SEED], program_id)
+    }
+
+    /// Address of the config from its stored bump seed
+    pub fn address(program_id: &Pubkey, bump_seed: u8) -> Result<Pubkey, ProgramError> {
+        Pubkey::create_program_address(&[CONFIG_SEED, &[bump_seed]], program_id)
+            .map_err(|_| SwapError::InvalidProgramAddress.into())
+    }
+
+    /// Whether an admin transfer is waiting for the proposed admin
+    pub fn has_active_transfer(&self) -> bool {
+        self.future_admin_key != Pubkey::default()
+    }
+}
+
+const CONFIG_RESERVED_LEN: usize = 63;
+const CONFIG_LEN: usize = 252;
+
+impl Pack for ConfigInfo {
+    const LEN: usize = CONFIG_LEN;
+
+    /// Unpacks a byte buffer into a ConfigInfo
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![src, 0, CONFIG_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            version,
+            account_type,
+            is_initialized,
+            is_paused,
+            bump_seed,
+            admin_key,
+            future_admin_key,
+            future_admin_deadline,
+            trade_fee,
+            admin_fee,
+            referrer_fee,
+            pyth_program_id,
+            serum_program_id,
+            _reserved,
+        ) = array_refs![
+            input,
+            1,
+            1,
+            1,
+            1,
+            1,
+            32,
+            32,
+            8,
+            16,
+            16,
+            16,
+            32,
+            32,
+            CONFIG_RESERVED_LEN
+        ];
+
+        let account_type = unpack_account_type(account_type)?;
+        if account_type != AccountType::ConfigInfo && account_type != AccountType::Uninitialized {
+            return Err(SwapError::InvalidAccount.into());
+        }
+        let is_initialized = unpack_bool(is_initialized)?;
+        if is_initialized {
+            if account_type != AccountType::ConfigInfo {
+                return Err(SwapError::InvalidAccount.into());
+            }
+            if version[0] != CONFIG_VERSION {
+                return Err(ProgramError::InvalidAccountData);
+            }
+        }
+
+        Ok(Self {
+            version: version[0],
+            is_initialized,
+            is_paused: unpack_bool(is_paused)?,
+            bump_seed: bump_seed[0],
+            admin_key: Pubkey::new_from_array(*admin_key),
+            future_admin_key: Pubkey::new_from_array(*future_admin_key),
+            future_admin_deadline: i64::from_le_bytes(*future_admin_deadline),
+            fees: Fees {
+                trade_fee: unpack_rate(trade_fee),
+                admin_fee: unpack_rate(admin_fee),
+                referrer_fee: unpack_rate(referrer_fee),
+            },
+            pyth_program_id: Pubkey::new_from_array(*pyth_program_id),
+            serum_program_id: Pubkey::new_from_array(*serum_program_id),
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, CONFIG_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            version,
+            account_type,
+            is_initialized,
+            is_paused,
+            bump_seed,
+            admin_key,
+            future_admin_key,
+            future_admin_deadline,
+            trade_fee,
+            admin_fee,
+            referrer_fee,
+            pyth_program_id,
+            serum_program_id,
+            reserved,
+        ) = mut_array_refs![
+            output,
+            1,
+            1,
+            1,
+            1,
+            1,
+            32,
+            32,
+            8,
+            16,
+            16,
+            16,
+            32,
+            32,
+            CONFIG_RESERVED_LEN
+        ];
+
+        version[0] = CONFIG_VERSION;
+        pack_account_type(AccountType::ConfigInfo, account_type);
+        pack_bool(self.is_initialized, is_initialized);
+        pack_bool(self.is_paused, is_paused);
+        bump_seed[0] = self.bump_seed;
+        admin_key.copy_from_slice(self.admin_key.as_ref());
+        future_admin_key.copy_from_slice(self.future_admin_key.as_ref());
+        *future_admin_deadline = self.future_admin_deadline.to_le_bytes();
+        pack_rate(self.fees.trade_fee, trade_fee);
+        pack_rate(self.fees.admin_fee, admin_fee);
+        pack_rate(self.fees.referrer_fee, referrer_fee);
+        pyth_program_id.copy_from_slice(self.pyth_program_id.as_ref());
+        serum_program_id.copy_from_slice(self.serum_program_id.as_ref());
+        *reserved = [0u8; CONFIG_RESERVED_LEN];
+    }
+}
+
+impl VersionedAccount for ConfigInfo {
+    const LAYOUTS: &'static [AccountLayout] = &[AccountLayout {
+        version: CONFIG_VERSION,
+        len: CONFIG_LEN,
+    }];
+
+    fn unpack_layout(layout: &AccountLayout, src: &[u8]) -> Result<Self, ProgramError> {
+        match layout.version {
+            CONFIG_VERSION => Self::unpack_from_slice(src),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+}
+
+impl Sealed for ConfigInfo {}
+impl IsInitialized for ConfigInfo {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{MockedSwap, SwapInfo};
+
+    fn config_info() -> ConfigInfo {
+        ConfigInfo {
+            version: CONFIG_VERSION,
+            is_initialized: true,
+            is_paused: true,
+            bump_seed: 253,
+            admin_key: Pubkey::new_unique(),
+            future_admin_key: Pubkey::new_unique(),
+            future_admin_deadline: 1_650_000_000,
+            fees: Fees {
+                trade_fee: Rate::from_scaled_val(2_500_000_000),
+                ..Fees::default()
+            },
+            pyth_program_id: Pubkey::new_unique(),
+            serum_program_id: Pubkey::new_unique(),
+        }
+    }
+
+    #[test]
+    fn test_config_packing() {
+        let config_info = config_info();
+        let mut packed = [0u8; ConfigInfo::LEN];
+        ConfigInfo::pack(config_info.clone(), &mut packed).unwrap();
+        assert_eq!(ConfigInfo::unpack(&packed).unwrap(), config_info);
+        assert!(config_info.has_active_transfer());
+
+        let packed = [0u8; ConfigInfo::LEN];
+        assert_eq!(
+            ConfigInfo::unpack(&packed).unwrap_err(),
+            ProgramError::UninitializedAccount
+        );
+        let config_info = ConfigInfo::unpack_unchecked(&packed).unwrap();
+        assert_eq!(config_info, ConfigInfo::default());
+        assert!(!config_info.has_active_transfer());
+    }
+
+    #[test]
+    fn test_config_address() {
+        let program_id = Pubkey::new_unique();
+        let (config_key, bump_seed) = ConfigInfo::find_address(&program_id);
+        assert_eq!(
+            ConfigInfo::address(&program_id, bump_seed).unwrap(),
+            config_key
+        );
+        assert_ne!(
+            ConfigInfo::address(&program_id, bump_seed.wrapping_sub(1)),
+            Ok(config_key)
+        );
+    }
+
+    #[test]
+    fn test_config_type_confusion() {
+        let mut config_data = [0u8; SwapInfo::LEN];
+        config_info().pack_into_slice(&mut config_data);
+        let mut swap_info_data = [0u8; SwapInfo::LEN];
+        SwapInfo {
+            version: 0,
+            is_initialized: true,
+            ..SwapInfo::default()
+        }
+        .pack_into_slice(&mut swap_info_data);
+        let mut mocked_swap_data = [0u8; SwapInfo::LEN];
+        MockedSwap::default().pack_into_slice(&mut mocked_swap_data);
+
+        assert_eq!(
+            SwapInfo::unpack_from_slice(&config_data).unwrap_err(),
+            SwapError::InvalidAccount.into()
+        );
+        assert_eq!(
+            MockedSwap::unpack_from_slice(&config_data).unwrap_err(),
+            SwapError::InvalidAccount.into()
+        );
+        assert_eq!(
+            ConfigInfo::unpack_from_slice(&swap_info_data).unwrap_err(),
+            SwapError::InvalidAccount.into()
+        );
+        assert_eq!(
+            ConfigInfo::unpack_from_slice(&mocked_swap_data).unwrap_err(),
+            SwapError::InvalidAccount.into()
+        );
+    }
+}
@@ -0,0 +1,648 @@
+//! Liquidity farms staking pool tokens for rewards
+//!
+//! Rewards accrue per second at the farm reward rate and are shared by the staked amount:
+//! the farm keeps the accumulated reward per staked token, a position records the part of it
+//! already accounted for in `reward_debt` and settles the difference into `pending_rewards`
+//! whenever its stake changes or it claims.
+
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+
+use solana_program::{
+    clock::UnixTimestamp,
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+    pubkey::Pubkey,
+};
+
+use crate::{
+    error::SwapError,
+    math::{Decimal, TryAdd, TryDiv, TryMul, TrySub},
+};
+
+use super::{
+    pack_account_type, pack_bool, pack_decimal, unpack_account_type, unpack_bool, unpack_decimal,
+    AccountLayout, AccountType, VersionedAccount,
+};
+
+/// Current version of the farm layouts
+pub const FARM_VERSION: u8 = 1;
+
+/// Farm distributing rewards to pool token stakers
+#[repr(C)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FarmInfo {
+    /// layout version
+    pub version: u8,
+    /// whether the farm is initialized
+    pub is_initialized: bool,
+    /// bump seed of the farm authority owning the token accounts
+    pub bump_seed: u8,
+    /// config whose admin created the farm
+    pub config_key: Pubkey,
+    /// mint of the staked pool token
+    pub pool_mint: Pubkey,
+    /// token account holding the staked pool tokens
+    pub pool_token: Pubkey,
+    /// token account holding the rewards
+    pub reward_token: Pubkey,
+    /// rewards distributed per second among all stakers
+    pub reward_per_second: u64,
+    /// seconds a position has to wait between claims
+    pub min_claim_period: UnixTimestamp,
+    /// rewards accumulated per staked token
+    pub acc_reward_per_share: Decimal,
+    /// unix timestamp rewards were accumulated up to
+    pub last_update_timestamp: UnixTimestamp,
+    /// pool tokens staked in the farm
+    pub total_staked: u64,
+}
+
+impl FarmInfo {
+    /// Accumulate the rewards of the time elapsed up to `timestamp`
+    pub fn accrue(&mut self, timestamp: UnixTimestamp) -> Result<(), ProgramError> {
+        // clock timestamps are not strictly monotonic, an earlier one adds no time
+        let elapsed = timestamp.saturating_sub(self.last_update_timestamp).max(0) as u64;
+        if elapsed > 0 && self.total_staked > 0 {
+            let rewards = self
+                .reward_per_second
+                .checked_mul(elapsed)
+                .ok_or(SwapError::CalculationFailure)?;
+            self.acc_reward_per_share = self
+                .acc_reward_per_share
+                .try_add(Decimal::from(rewards).try_div(self.total_staked)?)?;
+        }
+        self.last_update_timestamp = self.last_update_timestamp.max(timestamp);
+        Ok(())
+    }
+}
+
+/// Stake of a user in a farm
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FarmPosition {
+    /// pool tokens staked
+    pub deposited_amount: u64,
+    /// accumulated reward per share already accounted for, times the stake
+    pub reward_debt: Decimal,
+    /// rewards earned and not claimed yet
+    pub pending_rewards: Decimal,
+    /// unix timestamp of the last claim, or of the first deposit
+    pub last_claim_timestamp: UnixTimestamp,
+}
+
+impl FarmPosition {
+    /// Move the rewards earned since the last settlement into `pending_rewards`
+    fn settle(&mut self, farm: &FarmInfo) -> Result<(), ProgramError> {
+        let earned = farm
+            .acc_reward_per_share
+            .try_mul(self.deposited_amount)?
+            .try_sub(self.reward_debt)?;
+        self.pending_rewards = self.pending_rewards.try_add(earned)?;
+        Ok(())
+    }
+
+    fn reset_debt(&mut self, farm: &FarmInfo) -> Result<(), ProgramError> {
+        self.reward_debt = farm.acc_reward_per_share.try_mul(self.deposited_amount)?;
+        Ok(())
+    }
+
+    /// Stake `amount` more pool tokens at `timestamp`
+    pub fn deposit(
+        &mut self,
+        farm: &mut FarmInfo,
+        amount: u64,
+        timestamp: UnixTimestamp,
+    ) -> Result<(), ProgramError> {
+        if amount == 0 {
+            return Err(SwapError::InvalidInput.into());
+        }
+        farm.accrue(timestamp)?;
+        self.settle(farm)?;
+        self.deposited_amount = self
+            .deposited_amount
+            .checked_add(amount)
+            .ok_or(SwapError::CalculationFailure)?;
+        farm.total_staked = farm
+            .total_staked
+            .checked_add(amount)
+            .ok_or(SwapError::CalculationFailure)?;
+        self.reset_debt(farm)
+    }
+
+    /// Unstake `amount` pool tokens at `timestamp`, the rewards earned stay pending
+    pub fn withdraw(
+        &mut self,
+        farm: &mut FarmInfo,
+        amount: u64,
+        timestamp: UnixTimestamp,
+    ) -> Result<(), ProgramError> {
+        if self.deposited_amount == 0 {
+            return Err(SwapError::LiquidityPositionEmpty.into());
+        }
+        if amount == 0 {
+            return Err(SwapError::InvalidInput.into());
+        }
+        if amount > self.deposited_amount {
+            return Err(SwapError::InsufficientFunds.into());
+        }
+        farm.accrue(timestamp)?;
+        self.settle(farm)?;
+        self.deposited_amount -= amount;
+        farm.total_staked = farm
+            .total_staked
+            .checked_sub(amount)
+            .ok_or(SwapError::CalculationFailure)?;
+        self.reset_debt(farm)
+    }
+
+    /// Claim the whole rewards earned at `timestamp`, the fraction below one token stays pending.
+    /// Claims are spaced by at least the farm `min_claim_period`.
+    pub fn claim(
+        &mut self,
+        farm: &mut FarmInfo,
+        timestamp: UnixTimestamp,
+    ) -> Result<u64, ProgramError> {
+        let unlock_timestamp = self
+            .last_claim_timestamp
+            .checked_add(farm.min_claim_period)
+            .ok_or(SwapError::CalculationFailure)?;
+        if timestamp < unlock_timestamp {
+            return Err(SwapError::InvalidClaimTime.into());
+        }
+        farm.accrue(timestamp)?;
+        self.settle(farm)?;
+        self.reset_debt(farm)?;
+
+        let amount = self.pending_rewards.try_floor_u64()?;
+        if amount == 0 {
+            return Err(SwapError::InsufficientClaimAmount.into());
+        }
+        self.pending_rewards = self.pending_rewards.try_sub(Decimal::from(amount))?;
+        self.last_claim_timestamp = timestamp;
+        Ok(amount)
+    }
+}
+
+/// Position of a user in one farm
+#[repr(C)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FarmUser {
+    /// layout version
+    pub version: u8,
+    /// whether the account is initialized
+    pub is_initialized: bool,
+    /// farm of the position
+    pub farm_key: Pubkey,
+    /// owner of the position
+    pub owner: Pubkey,
+    /// stake and rewards of the owner
+    pub position: FarmPosition,
+}
+
+const FARM_RESERVED_LEN: usize = 64;
+const FARM_INFO_LEN: usize = 244;
+const FARM_POSITION_LEN: usize = 48;
+const FARM_USER_RESERVED_LEN: usize = 32;
+const FARM_USER_LEN: usize = 147;
+
+/// Check the header of a farm account unpacked as `expected` and return whether it is
+/// initialized. The discriminator comes first, the rest may belong to another account type.
+fn unpack_farm_header(
+    version: &[u8; 1],
+    account_type: &[u8; 1],
+    is_initialized: &[u8; 1],
+    expected: AccountType,
+) -> Result<bool, ProgramError> {
+    let account_type = unpack_account_type(account_type)?;
+    if account_type != expected && account_type != AccountType::Uninitialized {
+        return Err(SwapError::InvalidAccount.into());
+    }
+    let is_initialized = unpack_bool(is_initialized)?;
+    if is_initialized {
+        if account_type != expected {
+            return Err(SwapError::InvalidAccount.into());
+        }
+        if version[0] != FARM_VERSION {
+            return Err(ProgramError::InvalidAccountData);
+        }
+    }
+    Ok(is_initialized)
+}
+
+impl Pack for FarmInfo {
+    const LEN: usize = FARM_INFO_LEN;
+
+    /// Unpacks a byte buffer into a FarmInfo
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![src, 0, FARM_INFO_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            version,
+            account_type,
+            is_initialized,
+            bump_seed,
+            config_key,
+            pool_mint,
+            pool_token,
+            reward_token,
+            reward_per_second,
+            min_claim_period,
+            acc_reward_per_share,
+            last_update_timestamp,
+            total_staked,
+            _reserved,
+        ) = array_refs![
+            input,
+            1,
+            1,
+            1,
+            1,
+            32,
+            32,
+            32,
+            32,
+            8,
+            8,
+            16,
+            8,
+            8,
+            FARM_RESERVED_LEN
+        ];
+
+        let is_initialized =
+            unpack_farm_header(version, account_type, is_initialized, AccountType::FarmInfo)?;
+
+        Ok(Self {
+            version: version[0],
+            is_initialized,
+            bump_seed: bump_seed[0],
+            config_key: Pubkey::new_from_array(*config_key),
+            pool_mint: Pubkey::new_from_array(*pool_mint),
+            pool_token: Pubkey::new_from_array(*pool_token),
+            reward_token: Pubkey::new_from_array(*reward_token),
+            reward_per_second: u64::from_le_bytes(*reward_per_second),
+            min_claim_period: i64::from_le_bytes(*min_claim_period),
+            acc_reward_per_share: unpack_decimal(acc_reward_per_share),
+            last_update_timestamp: i64::from_le_bytes(*last_update_timestamp),
+            total_staked: u64::from_le_bytes(*total_staked),
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, FARM_INFO_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            version,
+            account_type,
+            is_initialized,
+            bump_seed,
+            config_key,
+            pool_mint,
+            pool_token,
+            reward_token,
+            reward_per_second,
+            min_claim_period,
+            acc_reward_per_share,
+            last_update_timestamp,
+            total_staked,
+            reserved,
+        ) = mut_array_refs![
+            output,
+            1,
+            1,
+            1,
+            1,
+            32,
+            32,
+            32,
+            32,
+            8,
+            8,
+            16,
+            8,
+            8,
+            FARM_RESERVED_LEN
+        ];
+
+        version[0] = FARM_VERSION;
+        pack_account_type(AccountType::FarmInfo, account_type);
+        pack_bool(self.is_initialized, is_initialized);
+        bump_seed[0] = self.bump_seed;
+        config_key.copy_from_slice(self.config_key.as_ref());
+        pool_mint.copy_from_slice(self.pool_mint.as_ref());
+        pool_token.copy_from_slice(self.pool_token.as_ref());
+        reward_token.copy_from_slice(self.reward_token.as_ref());
+        *reward_per_second = self.reward_per_second.to_le_bytes();
+        *min_claim_period = self.min_claim_period.to_le_bytes();
+        pack_decimal(self.acc_reward_per_share, acc_reward_per_share);
+        *last_update_timestamp = self.last_update_timestamp.to_le_bytes();
+        *total_staked = self.total_staked.to_le_bytes();
+        *reserved = [0u8; FARM_RESERVED_LEN];
+    }
+}
+
+impl VersionedAccount for FarmInfo {
+    const LAYOUTS: &'static [AccountLayout] = &[AccountLayout {
+        version: FARM_VERSION,
+        len: FARM_INFO_LEN,
+    }];
+
+    fn unpack_layout(layout: &AccountLayout, src: &[u8]) -> Result<Self, ProgramError> {
+        match layout.version {
+            FARM_VERSION => Self::unpack_from_slice(src),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+}
+
+impl Sealed for FarmInfo {}
+impl IsInitialized for FarmInfo {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for FarmPosition {
+    const LEN: usize = FARM_POSITION_LEN;
+
+    /// Unpacks a byte buffer into a FarmPosition
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![src, 0, FARM_POSITION_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (deposited_amount, reward_debt, pending_rewards, last_claim_timestamp) =
+            array_refs![input, 8, 16, 16, 8];
+
+        Ok(Self {
+            deposited_amount: u64::from_le_bytes(*deposited_amount),
+            reward_debt: unpack_decimal(reward_debt),
+            pending_rewards: unpack_decimal(pending_rewards),
+            last_claim_timestamp: i64::from_le_bytes(*last_claim_timestamp),
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, FARM_POSITION_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (deposited_amount, reward_debt, pending_rewards, last_claim_timestamp) =
+            mut_array_refs![output, 8, 16, 16, 8];
+
+        *deposited_amount = self.deposited_amount.to_le_bytes();
+        pack_decimal(self.reward_debt, reward_debt);
+        pack_decimal(self.pending_rewards, pending_rewards);
+        *last_claim_timestamp = self.last_claim_timestamp.to_le_bytes();
+    }
+}
+
+impl Sealed for FarmPosition {}
+impl IsInitialized for FarmPosition {
+    fn is_initialized(&self) -> bool {
+        true
+    }
+}
+
+impl Pack for FarmUser {
+    const LEN: usize = FARM_USER_LEN;
+
+    /// Unpacks a byte buffer into a FarmUser
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![src, 0, FARM_USER_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (version, account_type, is_initialized, farm_key, owner, position, _reserved) = array_refs![
+            input,
+            1,
+            1,
+            1,
+            32,
+            32,
+            FARM_POSITION_LEN,
+            FARM_USER_RESERVED_LEN
+        ];
+
+        let is_initialized =
+            unpack_farm_header(version, account_type, is_initialized, AccountType::FarmUser)?;
+
+        Ok(Self {
+            version: version[0],
+            is_initialized,
+            farm_key: Pubkey::new_from_array(*farm_key),
+            owner: Pubkey::new_from_array(*owner),
+            position: FarmPosition::unpack_from_slice(position)?,
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, FARM_USER_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (version, account_type, is_initialized, farm_key, owner, position, reserved) = mut_array_refs![
+            output,
+            1,
+            1,
+            1,
+            32,
+            32,
+            FARM_POSITION_LEN,
+            FARM_USER_RESERVED_LEN
+        ];
+
+        version[0] = FARM_VERSION;
+        pack_account_type(AccountType::FarmUser, account_type);
+        pack_bool(self.is_initialized, is_initialized);
+        farm_key.copy_from_slice(self.farm_key.as_ref());
+        owner.copy_from_slice(self.owner.as_ref());
+        self.position.pack_into_slice(position);
+        *reserved = [0u8; FARM_USER_RESERVED_LEN];
+    }
+}
+
+impl VersionedAccount for FarmUser {
+    const LAYOUTS: &'static [AccountLayout] = &[AccountLayout {
+        version: FARM_VERSION,
+        len: FARM_USER_LEN,
+    }];
+
+    fn unpack_layout(layout: &AccountLayout, src: &[u8]) -> Result<Self, ProgramError> {
+        match layout.version {
+            FARM_VERSION => Self::unpack_from_slice(src),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+}
+
+impl Sealed for FarmUser {}
+impl IsInitialized for FarmUser {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{ConfigInfo, SwapInfo};
+
+    fn farm() -> FarmInfo {
+        FarmInfo {
+            version: FARM_VERSION,
+            is_initialized: true,
+            reward_per_second: 100,
+            min_claim_period: 60,
+            last_update_timestamp: 1_000,
+            ..FarmInfo::default()
+        }
+    }
+
+    fn position(timestamp: UnixTimestamp) -> FarmPosition {
+        FarmPosition {
+            last_claim_timestamp: timestamp,
+            ..FarmPosition::default()
+        }
+    }
+
+    #[test]
+    fn test_rewards_shared_by_stake() {
+        let mut farm = farm();
+        let mut alice = position(1_000);
+        let mut bob = position(1_000);
+
+        // nothing accrues while the farm is empty
+        farm.accrue(1_100).unwrap();
+        assert_eq!(farm.acc_reward_per_share, Decimal::zero());
+
+        alice.deposit(&mut farm, 1_000, 1_100).unwrap();
+        // alice alone for 10 seconds, then a quarter of the stake for 20 seconds
+        bob.deposit(&mut farm, 3_000, 1_110).unwrap();
+        assert_eq!(alice.claim(&mut farm, 1_130).unwrap(), 1_000 + 500);
+        assert_eq!(bob.claim(&mut farm, 1_130).unwrap(), 1_500);
+
+        // withdrawing settles the rewards earned so far and stops further accrual
+        alice.withdraw(&mut farm, 1_000, 1_190).unwrap();
+        assert_eq!(farm.total_staked, 3_000);
+        assert_eq!(alice.pending_rewards, Decimal::from(1_500u64));
+        assert_eq!(alice.claim(&mut farm, 1_250).unwrap(), 1_500);
+        assert_eq!(bob.claim(&mut farm, 1_250).unwrap(), 4_500 + 6_000);
+    }
+
+    #[test]
+    fn test_claim_lock_and_errors() {
+        let mut farm = FarmInfo {
+            reward_per_second: 1,
+            ..farm()
+        };
+        let mut alice = position(1_000);
+        alice.deposit(&mut farm, 3, 1_000).unwrap();
+
+        assert_eq!(
+            alice.claim(&mut farm, 1_059).unwrap_err(),
+            SwapError::InvalidClaimTime.into()
+        );
+        assert_eq!(alice.claim(&mut farm, 1_060).unwrap(), 60);
+        assert_eq!(
+            alice.claim(&mut farm, 1_100).unwrap_err(),
+            SwapError::InvalidClaimTime.into()
+        );
+
+        // a third of the stake earns a third of a token per second, kept until a whole one
+        alice.withdraw(&mut farm, 1, 1_120).unwrap();
+        let mut bob = position(1_000);
+        bob.deposit(&mut farm, 1, 1_120).unwrap();
+        assert_eq!(
+            bob.claim(&mut farm, 1_121).unwrap_err(),
+            SwapError::InsufficientClaimAmount.into()
+        );
+        assert!(bob.pending_rewards > Decimal::zero());
+        assert_eq!(bob.claim(&mut farm, 1_124).unwrap(), 1);
+
+        let mut empty = position(0);
+        assert_eq!(
+            empty.withdraw(&mut farm, 1, 1_200).unwrap_err(),
+            SwapError::LiquidityPositionEmpty.into()
+        );
+        assert_eq!(
+            alice.withdraw(&mut farm, 3, 1_200).unwrap_err(),
+            SwapError::InsufficientFunds.into()
+        );
+    }
+
+    #[test]
+    fn test_farm_packing() {
+        let farm_info = FarmInfo {
+            bump_seed: 253,
+            config_key: Pubkey::new_unique(),
+            pool_mint: Pubkey::new_unique(),
+            pool_token: Pubkey::new_unique(),
+            reward_token: Pubkey::new_unique(),
+            acc_reward_per_share: Decimal::from_scaled_val(123_456_789),
+            total_staked: 42,
+            ..farm()
+        };
+        let mut packed = [0u8; FarmInfo::LEN];
+        FarmInfo::pack(farm_info.clone(), &mut packed).unwrap();
+        assert_eq!(FarmInfo::unpack(&packed).unwrap(), farm_info);
+
+        let farm_user = FarmUser {
+            version: FARM_VERSION,
+            is_initialized: true,
+            farm_key: Pubkey::new_unique(),
+            owner: Pubkey::new_unique(),
+            position: FarmPosition {
+                deposited_amount: 1_000,
+                reward_debt: Decimal::from(7u64),
+                pending_rewards: Decimal::from_scaled_val(1_500_000_000_001),
+                last_claim_timestamp: 1_650_000_000,
+            },
+        };
+        let mut packed = [0u8; FarmUser::LEN];
+        FarmUser::pack(farm_user.clone(), &mut packed).unwrap();
+        assert_eq!(FarmUser::unpack(&packed).unwrap(), farm_user);
+
+        let packed = [0u8; FarmUser::LEN];
+        assert_eq!(
+            FarmUser::unpack_unchecked(&packed).unwrap(),
+            FarmUser::default()
+        );
+    }
+
+    #[test]
+    fn test_farm_type_confusion() {
+        let mut farm_data = [0u8; SwapInfo::LEN];
+        farm().pack_into_slice(&mut farm_data);
+        let mut farm_user_data = [0u8; SwapInfo::LEN];
+        FarmUser {
+            is_initialized: true,
+            ..FarmUser::default()
+        }
+        .pack_into_slice(&mut farm_user_data);
+        let mut config_data = [0u8; SwapInfo::LEN];
+        ConfigInfo {
+            is_initialized: true,
+            ..ConfigInfo::default()
+        }
+        .pack_into_slice(&mut config_data);
+        let mut swap_info_data = [0u8; SwapInfo::LEN];
+        SwapInfo {
+            is_initialized: true,
+            ..SwapInfo::default()
+        }
+        .pack_into_slice(&mut swap_info_data);
+
+        for data in [&farm_user_data, &config_data, &swap_info_data].iter() {
+            assert_eq!(
+                FarmInfo::unpack_from_slice(*data).unwrap_err(),
+                SwapError::InvalidAccount.into()
+            );
+        }
+        for data in [&farm_data, &config_data, &swap_info_data].iter() {
+            assert_eq!(
+                FarmUser::unpack_from_slice(*data).unwrap_err(),
+                SwapError::InvalidAccount.into()
+            );
+        }
+        assert_eq!(
+            SwapInfo::unpack_from_slice(&farm_data).unwrap_err(),
+            SwapError::InvalidAccount.into()
+        );
+        assert_eq!(
+            ConfigInfo::unpack_from_slice(&farm_user_data).unwrap_err(),
+            SwapError::InvalidAccount.into()
+        );
+    }
+}
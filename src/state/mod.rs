@@ -1,13 +1,97 @@
 //! State used in DeFi
 
+mod config;
+mod farm;
+mod referrer;
 mod swap;
+mod twap;
+mod version;
+mod view;
 
+pub use config::*;
+pub use farm::*;
+pub use referrer::*;
 pub use swap::*;
+pub use twap::*;
+pub use version::*;
+pub use view::*;
 
-pub use crate::math::Decimal;
+pub use crate::math::{Decimal, Rate};
 
+use crate::error::SwapError;
 use solana_program::program_error::ProgramError;
 
+/// Discriminator stored after the version byte of every state account
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccountType {
+    /// zeroed account not initialized yet
+    Uninitialized = 0,
+    /// mocked swap account
+    MockedSwap = 1,
+    /// pool account
+    SwapInfo = 2,
+    /// program-wide config account
+    ConfigInfo = 3,
+    /// liquidity farm account
+    FarmInfo = 4,
+    /// farm position of a user
+    FarmUser = 5,
+    /// referrer registration of a user
+    UserReferrerData = 6,
+}
+
+/// Pack account type
+pub fn pack_account_type(account_type: AccountType, dst: &mut [u8; 1]) {
+    dst[0] = account_type as u8;
+}
+
+/// Unpack account type
+pub fn unpack_account_type(src: &[u8; 1]) -> Result<AccountType, ProgramError> {
+    match src[0] {
+        0 => Ok(AccountType::Uninitialized),
+        1 => Ok(AccountType::MockedSwap),
+        2 => Ok(AccountType::SwapInfo),
+        3 => Ok(AccountType::ConfigInfo),
+        4 => Ok(AccountType::FarmInfo),
+        5 => Ok(AccountType::FarmUser),
+        6 => Ok(AccountType::UserReferrerData),
+        _ => Err(SwapError::InvalidAccount.into()),
+    }
+}
+
+/// Account type of a state account stored in any registered layout.
+/// The original mocked swap layout is the only one without version byte and discriminator
+/// and is recognized by its length, which no other layout has. Every other account is typed
+/// by its discriminator and must match a registered layout of that type in version byte and
+/// length.
+pub fn account_type_of(src: &[u8]) -> Result<AccountType, ProgramError> {
+    if matches!(MockedSwap::layout_of(src), Ok(layout) if layout.version == 0) {
+        return Ok(AccountType::MockedSwap);
+    }
+    let account_type = match src.get(1) {
+        Some(account_type) => unpack_account_type(&[*account_type])?,
+        None => return Err(ProgramError::InvalidAccountData),
+    };
+    let has_layout = match account_type {
+        AccountType::Uninitialized => false,
+        AccountType::MockedSwap => has_versioned_layout::<MockedSwap>(src),
+        AccountType::SwapInfo => has_versioned_layout::<SwapInfo>(src),
+        AccountType::ConfigInfo => has_versioned_layout::<ConfigInfo>(src),
+        AccountType::FarmInfo => has_versioned_layout::<FarmInfo>(src),
+        AccountType::FarmUser => has_versioned_layout::<FarmUser>(src),
+        AccountType::UserReferrerData => has_versioned_layout::<UserReferrerData>(src),
+    };
+    if !has_layout {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(account_type)
+}
+
+/// Whether `src` is stored in a registered layout of `T` with a version byte
+fn has_versioned_layout<T: VersionedAccount>(src: &[u8]) -> bool {
+    matches!(T::layout_of(src), Ok(layout) if layout.version != 0)
+}
+
 /// Pack decimal
 pub fn pack_decimal(decimal: Decimal, dst: &mut [u8; 16]) {
     *dst = decimal
@@ -21,6 +105,16 @@ pub fn unpack_decimal(src: &[u8; 16]) -> Decimal {
     Decimal::from_scaled_val(u128::from_le_bytes(*src))
 }
 
+/// Pack rate
+pub fn pack_rate(rate: Rate, dst: &mut [u8; 16]) {
+    *dst = rate.to_scaled_val().to_le_bytes();
+}
+
+/// Unpack rate
+pub fn unpack_rate(src: &[u8; 16]) -> Rate {
+    Rate::from_scaled_val(u128::from_le_bytes(*src))
+}
+
 /// Pack boolean
 pub fn pack_bool(boolean: bool, dst: &mut [u8; 1]) {
     *dst = (boolean as u8).to_le_bytes()
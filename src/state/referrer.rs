@@ -0,0 +1,167 @@
+//! Referrer registration of a user
+
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+
+use solana_program::{
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+    pubkey::Pubkey,
+};
+
+use crate::error::SwapError;
+
+use super::{
+    pack_account_type, pack_bool, unpack_account_type, unpack_bool, AccountLayout, AccountType,
+    VersionedAccount,
+};
+
+/// Current version of the user referrer layout
+pub const USER_REFERRER_VERSION: u8 = 1;
+
+/// Referrer of a user, paid a share of the trade fee of the user swaps
+#[repr(C)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UserReferrerData {
+    /// layout version
+    pub version: u8,
+    /// whether the account is initialized
+    pub is_initialized: bool,
+    /// config the registration belongs to
+    pub config_key: Pubkey,
+    /// user trading with the referral
+    pub owner: Pubkey,
+    /// token account receiving the referrer fee, the dummy referrer when nobody referred the user
+    pub referrer: Pubkey,
+}
+
+const USER_REFERRER_RESERVED_LEN: usize = 32;
+const USER_REFERRER_LEN: usize = 131;
+
+impl UserReferrerData {
+    /// Address and bump seed of the registration of `owner` under `config_key`.
+    /// Each user has exactly one, so a swap can not leave it out to skip the referrer.
+    pub fn find_address(program_id: &Pubkey, config_key: &Pubkey, owner: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[config_key.as_ref(), owner.as_ref()], program_id)
+    }
+}
+
+impl Pack for UserReferrerData {
+    const LEN: usize = USER_REFERRER_LEN;
+
+    /// Unpacks a byte buffer into a UserReferrerData
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![src, 0, USER_REFERRER_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (version, account_type, is_initialized, config_key, owner, referrer, _reserved) =
+            array_refs![input, 1, 1, 1, 32, 32, 32, USER_REFERRER_RESERVED_LEN];
+
+        // check the discriminator before reading fields of what may be another account type
+        let account_type = unpack_account_type(account_type)?;
+        if account_type != AccountType::UserReferrerData
+            && account_type != AccountType::Uninitialized
+        {
+            return Err(SwapError::InvalidAccount.into());
+        }
+        let is_initialized = unpack_bool(is_initialized)?;
+        if is_initialized {
+            if account_type != AccountType::UserReferrerData {
+                return Err(SwapError::InvalidAccount.into());
+            }
+            if version[0] != USER_REFERRER_VERSION {
+                return Err(ProgramError::InvalidAccountData);
+            }
+        }
+
+        Ok(Self {
+            version: version[0],
+            is_initialized,
+            config_key: Pubkey::new_from_array(*config_key),
+            owner: Pubkey::new_from_array(*owner),
+            referrer: Pubkey::new_from_array(*referrer),
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, USER_REFERRER_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (version, account_type, is_initialized, config_key, owner, referrer, reserved) =
+            mut_array_refs![output, 1, 1, 1, 32, 32, 32, USER_REFERRER_RESERVED_LEN];
+
+        version[0] = USER_REFERRER_VERSION;
+        pack_account_type(AccountType::UserReferrerData, account_type);
+        pack_bool(self.is_initialized, is_initialized);
+        config_key.copy_from_slice(self.config_key.as_ref());
+        owner.copy_from_slice(self.owner.as_ref());
+        referrer.copy_from_slice(self.referrer.as_ref());
+        *reserved = [0u8; USER_REFERRER_RESERVED_LEN];
+    }
+}
+
+impl VersionedAccount for UserReferrerData {
+    const LAYOUTS: &'static [AccountLayout] = &[AccountLayout {
+        version: USER_REFERRER_VERSION,
+        len: USER_REFERRER_LEN,
+    }];
+
+    fn unpack_layout(layout: &AccountLayout, src: &[u8]) -> Result<Self, ProgramError> {
+        match layout.version {
+            USER_REFERRER_VERSION => Self::unpack_from_slice(src),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+}
+
+impl Sealed for UserReferrerData {}
+impl IsInitialized for UserReferrerData {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        state::{FarmUser, SwapInfo},
+        DUMMY_REFERRER_ADDRESS,
+    };
+    use std::str::FromStr;
+
+    #[test]
+    fn test_user_referrer_packing() {
+        let user_referrer = UserReferrerData {
+            version: USER_REFERRER_VERSION,
+            is_initialized: true,
+            config_key: Pubkey::new_unique(),
+            owner: Pubkey::new_unique(),
+            referrer: Pubkey::new_unique(),
+        };
+        let mut packed = [0u8; UserReferrerData::LEN];
+        UserReferrerData::pack(user_referrer.clone(), &mut packed).unwrap();
+        assert_eq!(UserReferrerData::unpack(&packed).unwrap(), user_referrer);
+        assert!(Pubkey::from_str(DUMMY_REFERRER_ADDRESS).is_ok());
+
+        // a farm position of the same owner is not a referral
+        let mut farm_user_data = [0u8; SwapInfo::LEN];
+        FarmUser {
+            is_initialized: true,
+            owner: user_referrer.owner,
+            ..FarmUser::default()
+        }
+        .pack_into_slice(&mut farm_user_data);
+        assert_eq!(
+            UserReferrerData::unpack_from_slice(&farm_user_data).unwrap_err(),
+            SwapError::InvalidAccount.into()
+        );
+        let mut referrer_data = [0u8; SwapInfo::LEN];
+        user_referrer.pack_into_slice(&mut referrer_data);
+        assert_eq!(
+            FarmUser::unpack_from_slice(&referrer_data).unwrap_err(),
+            SwapError::InvalidAccount.into()
+        );
+        assert_eq!(
+            SwapInfo::unpack_from_slice(&referrer_data).unwrap_err(),
+            SwapError::InvalidAccount.into()
+        );
+    }
+}
@@ -5,6 +5,19 @@ use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
 use solana_program::{
     program_error::ProgramError,
     program_pack::{IsInitialized, Pack, Sealed},
+    pubkey::Pubkey,
+};
+
+use crate::{
+    curve::{CurveType, Fees, PoolState, TokenDecimals},
+    error::SwapError,
+    math::{Decimal, Rate},
+};
+
+use super::{
+    pack_account_type, pack_bool, pack_decimal, pack_rate, unpack_account_type, unpack_bool,
+    unpack_decimal, unpack_rate, AccountLayout, AccountType, ConfigInfo, PriceAccumulator,
+    VersionedAccount,
 };
 
 
@@ -12,49 +25,187 @@ use solana_program::{
 #[repr(C)]
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct MockedSwap {
+    /// config of the program the pool belongs to
+    pub config_key: Pubkey,
+    /// token a account owned by the pool authority
+    pub token_a: Pubkey,
+    /// token b account owned by the pool authority
+    pub token_b: Pubkey,
     /// target reserve a
-    pub target_reserve_a: u64,
+    pub target_reserve_a: Decimal,
     /// target reserve b
-    pub target_reserve_b: u64,
+    pub target_reserve_b: Decimal,
     /// current reserve a
-    pub current_reserve_a: u64,
+    pub current_reserve_a: Decimal,
     /// current reserve b
-    pub current_reserve_b: u64,
-    /// market price
-    pub market_price: u64,
+    pub current_reserve_b: Decimal,
+    /// market price of one whole token a in whole tokens b
+    pub market_price: Decimal,
+    /// decimals of the token a mint
+    pub token_a_decimals: u8,
+    /// decimals of the token b mint
+    pub token_b_decimals: u8,
+    /// fees charged by the pool
+    pub fees: Fees,
+    /// cumulative price for the pool twap, updated on every swap
+    pub price_accumulator: PriceAccumulator,
+}
+
+impl MockedSwap {
+    /// Authority owning the token accounts of a mocked swap and its bump seed
+    pub fn find_authority(program_id: &Pubkey, mocked_swap_key: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[mocked_swap_key.as_ref()], program_id)
+    }
+}
+
+/// Current version of the mocked swap layout
+pub const MOCKED_SWAP_VERSION: u8 = 1;
+
+const MOCKED_SWAP_V0_LEN: usize = 40;
+const MOCKED_SWAP_LEN: usize = 276;
+
+/// Unpack the original layout, `u64` fields without version byte, discriminator or decimals
+fn unpack_mocked_swap_v0(input: &[u8; MOCKED_SWAP_V0_LEN]) -> MockedSwap {
+    #[allow(clippy::ptr_offset_with_cast)]
+    let (target_reserve_a, target_reserve_b, current_reserve_a, current_reserve_b, market_price) =
+        array_refs![input, 8, 8, 8, 8, 8];
+
+    MockedSwap {
+        // the program has a single config
+        config_key: ConfigInfo::find_address(&crate::id()).0,
+        // the original pools held no tokens
+        token_a: Pubkey::default(),
+        token_b: Pubkey::default(),
+        target_reserve_a: Decimal::from(u64::from_le_bytes(*target_reserve_a)),
+        target_reserve_b: Decimal::from(u64::from_le_bytes(*target_reserve_b)),
+        current_reserve_a: Decimal::from(u64::from_le_bytes(*current_reserve_a)),
+        current_reserve_b: Decimal::from(u64::from_le_bytes(*current_reserve_b)),
+        market_price: Decimal::from(u64::from_le_bytes(*market_price)),
+        // prices were quoted in raw units, which zero decimals keep
+        token_a_decimals: 0,
+        token_b_decimals: 0,
+        fees: Fees::default(),
+        price_accumulator: PriceAccumulator::default(),
+    }
 }
 
-const MOCKED_SWAP_LEN: usize = 40;
 impl Pack for MockedSwap {
     const LEN: usize = MOCKED_SWAP_LEN;
 
-    /// Unpacks a byte buffer into a UserReferrerData
+    /// Unpacks a byte buffer into a MockedSwap
     fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
         let input = array_ref![src, 0, MOCKED_SWAP_LEN];
         #[allow(clippy::ptr_offset_with_cast)]
-        let (target_reserve_a, target_reserve_b, current_reserve_a, current_reserve_b, market_price) =
-            array_refs![input, 8, 8, 8, 8, 8];
-
+        let (
+            version,
+            account_type,
+            config_key,
+            token_a,
+            token_b,
+            target_reserve_a,
+            target_reserve_b,
+            current_reserve_a,
+            current_reserve_b,
+            market_price,
+            token_a_decimals,
+            token_b_decimals,
+            trade_fee,
+            admin_fee,
+            referrer_fee,
+            price_accumulator,
+        ) = array_refs![
+            input, 1, 1, 32, 32, 32, 16, 16, 16, 16, 16, 1, 1, 16, 16, 16,
+            PriceAccumulator::LEN
+        ];
+        if unpack_account_type(account_type)? != AccountType::MockedSwap {
+            return Err(SwapError::InvalidAccount.into());
+        }
+        if version[0] != MOCKED_SWAP_VERSION {
+            return Err(ProgramError::InvalidAccountData);
+        }
         Ok(Self {
-            target_reserve_a: u64::from_le_bytes(*target_reserve_a),
-            target_reserve_b: u64::from_le_bytes(*target_reserve_b),
-            current_reserve_a: u64::from_le_bytes(*current_reserve_a),
-            current_reserve_b: u64::from_le_bytes(*current_reserve_b),
-            market_price: u64::from_le_bytes(*market_price),
+            config_key: Pubkey::new_from_array(*config_key),
+            token_a: Pubkey::new_from_array(*token_a),
+            token_b: Pubkey::new_from_array(*token_b),
+            target_reserve_a: unpack_decimal(target_reserve_a),
+            target_reserve_b: unpack_decimal(target_reserve_b),
+            current_reserve_a: unpack_decimal(current_reserve_a),
+            current_reserve_b: unpack_decimal(current_reserve_b),
+            market_price: unpack_decimal(market_price),
+            token_a_decimals: token_a_decimals[0],
+            token_b_decimals: token_b_decimals[0],
+            fees: Fees {
+                trade_fee: unpack_rate(trade_fee),
+                admin_fee: unpack_rate(admin_fee),
+                referrer_fee: unpack_rate(referrer_fee),
+            },
+            price_accumulator: PriceAccumulator::unpack_from_slice(price_accumulator)?,
         })
     }
 
     fn pack_into_slice(&self, dst: &mut [u8]) {
         let output = array_mut_ref![dst, 0, MOCKED_SWAP_LEN];
         #[allow(clippy::ptr_offset_with_cast)]
-        let (target_reserve_a, target_reserve_b, current_reserve_a, current_reserve_b, market_price) =
-            mut_array_refs![output, 8, 8, 8, 8, 8];
+        let (
+            version,
+            account_type,
+            config_key,
+            token_a,
+            token_b,
+            target_reserve_a,
+            target_reserve_b,
+            current_reserve_a,
+            current_reserve_b,
+            market_price,
+            token_a_decimals,
+            token_b_decimals,
+            trade_fee,
+            admin_fee,
+            referrer_fee,
+            price_accumulator,
+        ) = mut_array_refs![
+            output, 1, 1, 32, 32, 32, 16, 16, 16, 16, 16, 1, 1, 16, 16, 16,
+            PriceAccumulator::LEN
+        ];
+
+        version[0] = MOCKED_SWAP_VERSION;
+        pack_account_type(AccountType::MockedSwap, account_type);
+        config_key.copy_from_slice(self.config_key.as_ref());
+        token_a.copy_from_slice(self.token_a.as_ref());
+        token_b.copy_from_slice(self.token_b.as_ref());
+        pack_decimal(self.target_reserve_a, target_reserve_a);
+        pack_decimal(self.target_reserve_b, target_reserve_b);
+        pack_decimal(self.current_reserve_a, current_reserve_a);
+        pack_decimal(self.current_reserve_b, current_reserve_b);
+        pack_decimal(self.market_price, market_price);
+        token_a_decimals[0] = self.token_a_decimals;
+        token_b_decimals[0] = self.token_b_decimals;
+        pack_rate(self.fees.trade_fee, trade_fee);
+        pack_rate(self.fees.admin_fee, admin_fee);
+        pack_rate(self.fees.referrer_fee, referrer_fee);
+        self.price_accumulator.pack_into_slice(price_accumulator);
+    }
+}
+
+impl VersionedAccount for MockedSwap {
+    const LAYOUTS: &'static [AccountLayout] = &[
+        // the original layout had no version byte
+        AccountLayout {
+            version: 0,
+            len: MOCKED_SWAP_V0_LEN,
+        },
+        AccountLayout {
+            version: MOCKED_SWAP_VERSION,
+            len: MOCKED_SWAP_LEN,
+        },
+    ];
 
-        *target_reserve_a = self.target_reserve_a.to_le_bytes();
-        *target_reserve_b = self.target_reserve_b.to_le_bytes();
-        *current_reserve_a = self.current_reserve_a.to_le_bytes();
-        *current_reserve_b = self.current_reserve_b.to_le_bytes();
-        *market_price = self.market_price.to_le_bytes();
+    fn unpack_layout(layout: &AccountLayout, src: &[u8]) -> Result<Self, ProgramError> {
+        match layout.version {
+            0 => Ok(unpack_mocked_swap_v0(array_ref![src, 0, MOCKED_SWAP_V0_LEN])),
+            MOCKED_SWAP_VERSION => Self::unpack_from_slice(src),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
     }
 }
 
@@ -66,5 +217,592 @@ impl IsInitialized for MockedSwap {
 }
 
 
+/// Current version of the swap info layout
+pub const SWAP_INFO_VERSION: u8 = 1;
+
+const CURVE_TYPE_ORACLE: u8 = 0;
+const CURVE_TYPE_STABLE: u8 = 1;
+const CURVE_TYPE_WEIGHTED: u8 = 2;
+
+/// Pool account
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SwapInfo {
+    /// layout version
+    pub version: u8,
+    /// whether the pool is initialized
+    pub is_initialized: bool,
+    /// whether swaps and deposits are paused
+    pub is_paused: bool,
+    /// bump seed of the pool authority
+    pub bump_seed: u8,
+    /// decimals of the token a mint
+    pub token_a_decimals: u8,
+    /// decimals of the token b mint
+    pub token_b_decimals: u8,
+    /// token program id
+    pub token_program_id: Pubkey,
+    /// token a account owned by the pool authority
+    pub token_a: Pubkey,
+    /// token b account owned by the pool authority
+    pub token_b: Pubkey,
+    /// mint of token a
+    pub token_a_mint: Pubkey,
+    /// mint of token b
+    pub token_b_mint: Pubkey,
+    /// mint of the pool token
+    pub pool_mint: Pubkey,
+    /// token a account receiving admin fees
+    pub admin_fee_a: Pubkey,
+    /// token b account receiving admin fees
+    pub admin_fee_b: Pubkey,
+    /// oracle price account of token a
+    pub oracle_a: Pubkey,
+    /// oracle price account of token b
+    pub oracle_b: Pubkey,
+    /// curve pricing the pool
+    pub curve_type: CurveType,
+    /// market price of one whole token a in whole tokens b, raw target and current reserves
+    pub pool_state: PoolState,
+    /// fees charged by the pool
+    pub fees: Fees,
+    /// cumulative price for the pool twap
+    pub price_accumulator: PriceAccumulator,
+    /// config of the program the pool belongs to
+    pub config_key: Pubkey,
+}
+
+impl Default for SwapInfo {
+    fn default() -> Self {
+        Self {
+            version: 0,
+            is_initialized: false,
+            is_paused: false,
+            bump_seed: 0,
+            token_a_decimals: 0,
+            token_b_decimals: 0,
+            token_program_id: Pubkey::default(),
+            token_a: Pubkey::default(),
+            token_b: Pubkey::default(),
+            token_a_mint: Pubkey::default(),
+            token_b_mint: Pubkey::default(),
+            pool_mint: Pubkey::default(),
+            admin_fee_a: Pubkey::default(),
+            admin_fee_b: Pubkey::default(),
+            oracle_a: Pubkey::default(),
+            oracle_b: Pubkey::default(),
+            curve_type: CurveType::Oracle,
+            pool_state: PoolState::default(),
+            // zeroed like a freshly allocated account
+            fees: Fees {
+                trade_fee: Rate::zero(),
+                admin_fee: Rate::zero(),
+                referrer_fee: Rate::zero(),
+            },
+            price_accumulator: PriceAccumulator::default(),
+            config_key: Pubkey::default(),
+        }
+    }
+}
+
+/// Pack a curve type into its tag and two decimal parameters
+pub fn pack_curve_type(
+    curve_type: &CurveType,
+    tag: &mut [u8; 1],
+    param_a: &mut [u8; 16],
+    param_b: &mut [u8; 16],
+) {
+    let (curve_tag, decimal_a, decimal_b) = match *curve_type {
+        CurveType::Oracle => (CURVE_TYPE_ORACLE, Decimal::zero(), Decimal::zero()),
+        CurveType::Stable { amp } => (CURVE_TYPE_STABLE, Decimal::from(amp), Decimal::zero()),
+        CurveType::Weighted { weight_a, weight_b } => (CURVE_TYPE_WEIGHTED, weight_a, weight_b),
+    };
+    tag[0] = curve_tag;
+    pack_decimal(decimal_a, param_a);
+    pack_decimal(decimal_b, param_b);
+}
+
+/// Unpack a curve type from its tag and two decimal parameters
+pub fn unpack_curve_type(
+    tag: &[u8; 1],
+    param_a: &[u8; 16],
+    param_b: &[u8; 16],
+) -> Result<CurveType, ProgramError> {
+    let decimal_a = unpack_decimal(param_a);
+    match tag[0] {
+        CURVE_TYPE_ORACLE => Ok(CurveType::Oracle),
+        CURVE_TYPE_STABLE => Ok(CurveType::Stable {
+            amp: decimal_a.try_floor_u64()?,
+        }),
+        CURVE_TYPE_WEIGHTED => Ok(CurveType::Weighted {
+            weight_a: decimal_a,
+            weight_b: unpack_decimal(param_b),
+        }),
+        _ => Err(SwapError::IncorrectSwapType.into()),
+    }
+}
+
+const SWAP_INFO_RESERVED_LEN: usize = 30;
+const SWAP_INFO_LEN: usize = 598;
+
+impl SwapInfo {
+    /// Decimals of the pool mints, normalizing amounts before curve math
+    pub fn token_decimals(&self) -> Result<TokenDecimals, ProgramError> {
+        TokenDecimals::new(self.token_a_decimals, self.token_b_decimals)
+    }
+}
+
+impl Pack for SwapInfo {
+    const LEN: usize = SWAP_INFO_LEN;
+
+    /// Unpacks a byte buffer into a SwapInfo
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![src, 0, SWAP_INFO_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            version,
+            account_type,
+            is_initialized,
+            is_paused,
+            bump_seed,
+            token_a_decimals,
+            token_b_decimals,
+            token_program_id,
+            token_a,
+            token_b,
+            token_a_mint,
+            token_b_mint,
+            pool_mint,
+            admin_fee_a,
+            admin_fee_b,
+            oracle_a,
+            oracle_b,
+            curve_tag,
+            curve_param_a,
+            curve_param_b,
+            market_price,
+            target_reserve_a,
+            target_reserve_b,
+            current_reserve_a,
+            current_reserve_b,
+            trade_fee,
+            admin_fee,
+            referrer_fee,
+            price_accumulator,
+            config_key,
+            _reserved,
+        ) = array_refs![
+            input, 1, 1, 1, 1, 1, 1, 1, 32, 32, 32, 32, 32, 32, 32, 32, 32, 32, 1, 16, 16, 16, 16,
+            16, 16, 16, 16, 16, 16, PriceAccumulator::LEN, 32, SWAP_INFO_RESERVED_LEN
+        ];
+        // check the discriminator before reading fields of what may be another account type
+        let account_type = unpack_account_type(account_type)?;
+        if account_type != AccountType::SwapInfo && account_type != AccountType::Uninitialized {
+            return Err(SwapError::InvalidAccount.into());
+        }
+        let is_initialized = unpack_bool(is_initialized)?;
+        // a zeroed account is uninitialized, anything else must be in the current layout
+        if is_initialized {
+            if account_type != AccountType::SwapInfo {
+                return Err(SwapError::InvalidAccount.into());
+            }
+            if version[0] != SWAP_INFO_VERSION {
+                return Err(ProgramError::InvalidAccountData);
+            }
+        }
+
+        Ok(Self {
+            version: version[0],
+            is_initialized,
+            is_paused: unpack_bool(is_paused)?,
+            bump_seed: bump_seed[0],
+            token_a_decimals: token_a_decimals[0],
+            token_b_decimals: token_b_decimals[0],
+            token_program_id: Pubkey::new_from_array(*token_program_id),
+            token_a: Pubkey::new_from_array(*token_a),
+            token_b: Pubkey::new_from_array(*token_b),
+            token_a_mint: Pubkey::new_from_array(*token_a_mint),
+            token_b_mint: Pubkey::new_from_array(*token_b_mint),
+            pool_mint: Pubkey::new_from_array(*pool_mint),
+            admin_fee_a: Pubkey::new_from_array(*admin_fee_a),
+            admin_fee_b: Pubkey::new_from_array(*admin_fee_b),
+            oracle_a: Pubkey::new_from_array(*oracle_a),
+            oracle_b: Pubkey::new_from_array(*oracle_b),
+            curve_type: unpack_curve_type(curve_tag, curve_param_a, curve_param_b)?,
+            pool_state: PoolState {
+                market_price: unpack_decimal(market_price),
+                target_reserve_a: unpack_decimal(target_reserve_a),
+                target_reserve_b: unpack_decimal(target_reserve_b),
+                current_reserve_a: unpack_decimal(current_reserve_a),
+                current_reserve_b: unpack_decimal(current_reserve_b),
+            },
+            fees: Fees {
+                trade_fee: unpack_rate(trade_fee),
+                admin_fee: unpack_rate(admin_fee),
+                referrer_fee: unpack_rate(referrer_fee),
+            },
+            price_accumulator: PriceAccumulator::unpack_from_slice(price_accumulator)?,
+            config_key: Pubkey::new_from_array(*config_key),
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, SWAP_INFO_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            version,
+            account_type,
+            is_initialized,
+            is_paused,
+            bump_seed,
+            token_a_decimals,
+            token_b_decimals,
+            token_program_id,
+            token_a,
+            token_b,
+            token_a_mint,
+            token_b_mint,
+            pool_mint,
+            admin_fee_a,
+            admin_fee_b,
+            oracle_a,
+            oracle_b,
+            curve_tag,
+            curve_param_a,
+            curve_param_b,
+            market_price,
+            target_reserve_a,
+            target_reserve_b,
+            current_reserve_a,
+            current_reserve_b,
+            trade_fee,
+            admin_fee,
+            referrer_fee,
+            price_accumulator,
+            config_key,
+            reserved,
+        ) = mut_array_refs![
+            output, 1, 1, 1, 1, 1, 1, 1, 32, 32, 32, 32, 32, 32, 32, 32, 32, 32, 1, 16, 16, 16, 16,
+            16, 16, 16, 16, 16, 16, PriceAccumulator::LEN, 32, SWAP_INFO_RESERVED_LEN
+        ];
+
+        version[0] = SWAP_INFO_VERSION;
+        pack_account_type(AccountType::SwapInfo, account_type);
+        pack_bool(self.is_initialized, is_initialized);
+        pack_bool(self.is_paused, is_paused);
+        bump_seed[0] = self.bump_seed;
+        token_a_decimals[0] = self.token_a_decimals;
+        token_b_decimals[0] = self.token_b_decimals;
+        token_program_id.copy_from_slice(self.token_program_id.as_ref());
+        token_a.copy_from_slice(self.token_a.as_ref());
+        token_b.copy_from_slice(self.token_b.as_ref());
+        token_a_mint.copy_from_slice(self.token_a_mint.as_ref());
+        token_b_mint.copy_from_slice(self.token_b_mint.as_ref());
+        pool_mint.copy_from_slice(self.pool_mint.as_ref());
+        admin_fee_a.copy_from_slice(self.admin_fee_a.as_ref());
+        admin_fee_b.copy_from_slice(self.admin_fee_b.as_ref());
+        oracle_a.copy_from_slice(self.oracle_a.as_ref());
+        oracle_b.copy_from_slice(self.oracle_b.as_ref());
+        pack_curve_type(&self.curve_type, curve_tag, curve_param_a, curve_param_b);
+        pack_decimal(self.pool_state.market_price, market_price);
+        pack_decimal(self.pool_state.target_reserve_a, target_reserve_a);
+        pack_decimal(self.pool_state.target_reserve_b, target_reserve_b);
+        pack_decimal(self.pool_state.current_reserve_a, current_reserve_a);
+        pack_decimal(self.pool_state.current_reserve_b, current_reserve_b);
+        pack_rate(self.fees.trade_fee, trade_fee);
+        pack_rate(self.fees.admin_fee, admin_fee);
+        pack_rate(self.fees.referrer_fee, referrer_fee);
+        self.price_accumulator.pack_into_slice(price_accumulator);
+        config_key.copy_from_slice(self.config_key.as_ref());
+        *reserved = [0u8; SWAP_INFO_RESERVED_LEN];
+    }
+}
+
+impl VersionedAccount for SwapInfo {
+    const LAYOUTS: &'static [AccountLayout] = &[AccountLayout {
+        version: SWAP_INFO_VERSION,
+        len: SWAP_INFO_LEN,
+    }];
+
+    fn unpack_layout(layout: &AccountLayout, src: &[u8]) -> Result<Self, ProgramError> {
+        match layout.version {
+            SWAP_INFO_VERSION => Self::unpack_from_slice(src),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+}
+
+impl Sealed for SwapInfo {}
+impl IsInitialized for SwapInfo {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+/// Swap info with every field set, shared by the state and program tests
+#[cfg(any(test, feature = "test-bpf"))]
+pub fn test_swap_info() -> SwapInfo {
+    SwapInfo {
+        version: SWAP_INFO_VERSION,
+        is_initialized: true,
+        is_paused: true,
+        bump_seed: 254,
+        token_a_decimals: 6,
+        token_b_decimals: 9,
+        token_program_id: Pubkey::new_unique(),
+        token_a: Pubkey::new_unique(),
+        token_b: Pubkey::new_unique(),
+        token_a_mint: Pubkey::new_unique(),
+        token_b_mint: Pubkey::new_unique(),
+        pool_mint: Pubkey::new_unique(),
+        admin_fee_a: Pubkey::new_unique(),
+        admin_fee_b: Pubkey::new_unique(),
+        oracle_a: Pubkey::new_unique(),
+        oracle_b: Pubkey::new_unique(),
+        curve_type: CurveType::Weighted {
+            weight_a: Decimal::from_percent(80),
+            weight_b: Decimal::from_percent(20),
+        },
+        pool_state: PoolState {
+            market_price: Decimal::from_scaled_val(3_141_592_653_589),
+            target_reserve_a: Decimal::from(1_000_000u64),
+            target_reserve_b: Decimal::from(3_000_000u64),
+            current_reserve_a: Decimal::from_scaled_val(1_100_000_123_456_789),
+            current_reserve_b: Decimal::from(2_700_000u64),
+        },
+        fees: Fees {
+            trade_fee: Rate::from_scaled_val(2_500_000_000),
+            ..Fees::default()
+        },
+        price_accumulator: PriceAccumulator {
+            price_cumulative: 42,
+            last_price: Decimal::from(3u64),
+            last_update_timestamp: 1_650_000_000,
+            last_update_slot: 130_000_000,
+        },
+        config_key: Pubkey::new_unique(),
+    }
+}
+
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::*;
+    use crate::state::{account_type_of, migrate_data};
+
+    #[test]
+    fn test_swap_info_packing() {
+        let swap_info = test_swap_info();
+        let mut packed = [0u8; SwapInfo::LEN];
+        SwapInfo::pack(swap_info.clone(), &mut packed).unwrap();
+        assert_eq!(SwapInfo::unpack(&packed).unwrap(), swap_info);
+
+        for curve_type in [CurveType::Oracle, CurveType::Stable { amp: 85 }].iter() {
+            let swap_info = SwapInfo {
+                curve_type: *curve_type,
+                ..swap_info.clone()
+            };
+            SwapInfo::pack(swap_info.clone(), &mut packed).unwrap();
+            assert_eq!(SwapInfo::unpack(&packed).unwrap(), swap_info);
+        }
+    }
+
+    #[test]
+    fn test_swap_info_initialized() {
+        let packed = [0u8; SwapInfo::LEN];
+        assert_eq!(
+            SwapInfo::unpack(&packed).unwrap_err(),
+            ProgramError::UninitializedAccount
+        );
+        assert_eq!(
+            SwapInfo::unpack_unchecked(&packed).unwrap(),
+            SwapInfo::default()
+        );
+
+        let mut packed = [0u8; SwapInfo::LEN];
+        SwapInfo::pack(test_swap_info(), &mut packed).unwrap();
+        // curve tag follows the seven header bytes and ten keys
+        packed[7 + 10 * 32] = 7;
+        assert_eq!(
+            SwapInfo::unpack(&packed).unwrap_err(),
+            SwapError::IncorrectSwapType.into()
+        );
+        packed[2] = 2;
+        assert_eq!(
+            SwapInfo::unpack_unchecked(&packed).unwrap_err(),
+            ProgramError::InvalidAccountData
+        );
+
+        // an initialized pool in another version is rejected
+        let mut packed = [0u8; SwapInfo::LEN];
+        SwapInfo::pack(test_swap_info(), &mut packed).unwrap();
+        packed[0] = SWAP_INFO_VERSION + 1;
+        assert_eq!(
+            SwapInfo::unpack(&packed).unwrap_err(),
+            ProgramError::InvalidAccountData
+        );
+        assert_eq!(
+            SwapInfo::unpack_versioned(&packed).unwrap_err(),
+            ProgramError::InvalidAccountData
+        );
+    }
+
+    #[test]
+    fn test_migrate_legacy_mocked_swap() {
+        // legacy pools join the config of the program with its default fees
+        let mocked_swap = MockedSwap {
+            config_key: ConfigInfo::find_address(&crate::id()).0,
+            target_reserve_a: Decimal::from(100_001_423_523u64),
+            target_reserve_b: Decimal::from(2_005_232_345_234u64),
+            current_reserve_a: Decimal::from(2_043u64),
+            current_reserve_b: Decimal::from(996u64),
+            market_price: Decimal::from(3u64),
+            ..MockedSwap::default()
+        };
+        let mut packed = [0u8; MockedSwap::LEN];
+        MockedSwap::pack(mocked_swap.clone(), &mut packed).unwrap();
+        assert_eq!(packed[0], MOCKED_SWAP_VERSION);
+
+        // the legacy layout held the fields as u64s without version byte and discriminator
+        let legacy: Vec<u8> = [100_001_423_523u64, 2_005_232_345_234, 2_043, 996, 3]
+            .iter()
+            .flat_map(|field| field.to_le_bytes().to_vec())
+            .collect();
+        assert_eq!(MockedSwap::layout_of(&legacy).unwrap().version, 0);
+        assert_eq!(MockedSwap::unpack_versioned(&legacy).unwrap(), mocked_swap);
+        assert_eq!(migrate_data::<MockedSwap>(&legacy).unwrap(), packed.to_vec());
+
+        // migrating twice is a no-op
+        assert_eq!(migrate_data::<MockedSwap>(&packed).unwrap(), packed.to_vec());
+
+        // fractional prices and the price accumulator survive the current layout
+        let fractional = MockedSwap {
+            market_price: Decimal::from_scaled_val(370_000_000_000),
+            token_a_decimals: 9,
+            token_b_decimals: 6,
+            price_accumulator: PriceAccumulator {
+                price_cumulative: 42,
+                last_price: Decimal::from_scaled_val(370_000_000_000),
+                last_update_timestamp: 1_650_000_000,
+                last_update_slot: 130_000_000,
+            },
+            ..mocked_swap
+        };
+        MockedSwap::pack(fractional.clone(), &mut packed).unwrap();
+        assert_eq!(MockedSwap::unpack(&packed).unwrap(), fractional);
+
+        assert_eq!(
+            MockedSwap::unpack_versioned(&packed[..20]).unwrap_err(),
+            ProgramError::InvalidAccountData
+        );
+    }
+
+    #[test]
+    fn test_account_type_confusion() {
+        let mocked_swap = MockedSwap {
+            target_reserve_a: Decimal::from(1_000_000u64),
+            target_reserve_b: Decimal::from(3_000_000u64),
+            current_reserve_a: Decimal::from(1_000_000u64),
+            current_reserve_b: Decimal::from(3_000_000u64),
+            market_price: Decimal::from(3u64),
+            ..MockedSwap::default()
+        };
+        // every account packed into a buffer long enough for any of the types
+        let mut mocked_swap_data = [0u8; SwapInfo::LEN];
+        mocked_swap.pack_into_slice(&mut mocked_swap_data);
+        let swap_info = test_swap_info();
+        let mut swap_info_data = [0u8; SwapInfo::LEN];
+        swap_info.pack_into_slice(&mut swap_info_data);
+
+        assert_eq!(
+            MockedSwap::unpack_from_slice(&mocked_swap_data).unwrap(),
+            mocked_swap
+        );
+        assert_eq!(
+            SwapInfo::unpack_from_slice(&swap_info_data).unwrap(),
+            swap_info
+        );
+        assert_eq!(
+            MockedSwap::unpack_from_slice(&swap_info_data).unwrap_err(),
+            SwapError::InvalidAccount.into()
+        );
+        assert_eq!(
+            SwapInfo::unpack_from_slice(&mocked_swap_data).unwrap_err(),
+            SwapError::InvalidAccount.into()
+        );
+
+        // zeroed and unknown discriminators
+        let zeroed = [0u8; SwapInfo::LEN];
+        assert_eq!(
+            MockedSwap::unpack_from_slice(&zeroed).unwrap_err(),
+            SwapError::InvalidAccount.into()
+        );
+        let mut unknown = swap_info_data;
+        unknown[1] = 0xff;
+        assert_eq!(
+            SwapInfo::unpack_from_slice(&unknown).unwrap_err(),
+            SwapError::InvalidAccount.into()
+        );
+        // an initialized pool can not drop its discriminator
+        let mut stripped = swap_info_data;
+        stripped[1] = AccountType::Uninitialized as u8;
+        assert_eq!(
+            SwapInfo::unpack_from_slice(&stripped).unwrap_err(),
+            SwapError::InvalidAccount.into()
+        );
+    }
+
+    #[test]
+    fn test_account_type_of_layouts() {
+        let mut mocked_swap = [0u8; MockedSwap::LEN];
+        MockedSwap::default().pack_into_slice(&mut mocked_swap);
+        assert_eq!(
+            account_type_of(&mocked_swap).unwrap(),
+            AccountType::MockedSwap
+        );
+        // the original layout has no version byte and is told apart by length
+        assert_eq!(
+            account_type_of(&[7u8; MOCKED_SWAP_V0_LEN]).unwrap(),
+            AccountType::MockedSwap
+        );
+        let mut swap_info_data = [0u8; SwapInfo::LEN];
+        test_swap_info().pack_into_slice(&mut swap_info_data);
+        assert_eq!(
+            account_type_of(&swap_info_data).unwrap(),
+            AccountType::SwapInfo
+        );
+
+        // the discriminator selects the type, which must have a layout of that version and
+        // length
+        let mut bad_version = swap_info_data;
+        bad_version[0] = SWAP_INFO_VERSION + 1;
+        assert_eq!(
+            account_type_of(&bad_version).unwrap_err(),
+            ProgramError::InvalidAccountData
+        );
+        assert_eq!(
+            account_type_of(&swap_info_data[..SwapInfo::LEN - 1]).unwrap_err(),
+            ProgramError::InvalidAccountData
+        );
+        let mut mislabeled = swap_info_data;
+        mislabeled[1] = AccountType::FarmUser as u8;
+        assert_eq!(
+            account_type_of(&mislabeled).unwrap_err(),
+            ProgramError::InvalidAccountData
+        );
+
+        assert_eq!(
+            account_type_of(&[0u8; SwapInfo::LEN]).unwrap_err(),
+            ProgramError::InvalidAccountData
+        );
+        assert_eq!(
+            account_type_of(&[MOCKED_SWAP_VERSION]).unwrap_err(),
+            ProgramError::InvalidAccountData
+        );
+        let mut unknown = swap_info_data;
+        unknown[1] = 0xff;
+        assert_eq!(
+            account_type_of(&unknown).unwrap_err(),
+            SwapError::InvalidAccount.into()
+        );
+    }
+}
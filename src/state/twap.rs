@@ -0,0 +1,257 @@
+//! Cumulative price accumulator for time-weighted average prices
+
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+
+use solana_program::{
+    clock::{Clock, Slot, UnixTimestamp},
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+};
+
+use crate::{error::SwapError, math::Decimal};
+
+use super::{pack_decimal, unpack_decimal};
+
+/// Cumulative price at a point in time
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TwapObservation {
+    /// sum of scaled marginal price times seconds, wrapping on overflow
+    pub price_cumulative: u128,
+    /// unix timestamp of the observation
+    pub timestamp: UnixTimestamp,
+    /// slot of the observation
+    pub slot: Slot,
+}
+
+impl TwapObservation {
+    /// Time-weighted average price between `older` and this observation.
+    /// The cumulative difference is taken wrapping, so a single overflow in between is harmless.
+    pub fn twap_since(&self, older: &TwapObservation) -> Result<Decimal, ProgramError> {
+        let elapsed = self
+            .timestamp
+            .checked_sub(older.timestamp)
+            .filter(|elapsed| *elapsed > 0)
+            .ok_or(SwapError::InvalidInput)?;
+        Ok(Decimal::from_scaled_val(
+            self.price_cumulative.wrapping_sub(older.price_cumulative) / elapsed as u128,
+        ))
+    }
+}
+
+/// Price accumulator stored in the pool state.
+/// Call `update` after every change of the pool with the curve marginal price the change left
+/// it at; the previous price is weighted by the seconds it was in effect.
+#[repr(C)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PriceAccumulator {
+    /// sum of scaled marginal price times seconds, wrapping on overflow
+    pub price_cumulative: u128,
+    /// marginal price recorded by the last update
+    pub last_price: Decimal,
+    /// unix timestamp of the last update
+    pub last_update_timestamp: UnixTimestamp,
+    /// slot of the last update
+    pub last_update_slot: Slot,
+}
+
+impl PriceAccumulator {
+    /// Cumulative price extrapolated to `clock` with the last price
+    pub fn observe(&self, clock: &Clock) -> Result<TwapObservation, ProgramError> {
+        // clock timestamps are not strictly monotonic, an earlier one adds no time
+        let elapsed = clock
+            .unix_timestamp
+            .saturating_sub(self.last_update_timestamp)
+            .max(0) as u128;
+        let price_cumulative = self
+            .price_cumulative
+            .wrapping_add(self.last_price.to_scaled_val()?.wrapping_mul(elapsed));
+        Ok(TwapObservation {
+            price_cumulative,
+            timestamp: self.last_update_timestamp.max(clock.unix_timestamp),
+            slot: self.last_update_slot.max(clock.slot),
+        })
+    }
+
+    /// Accumulate the last price up to `clock` and record `marginal_price` as the new one
+    pub fn update(&mut self, marginal_price: Decimal, clock: &Clock) -> Result<(), ProgramError> {
+        let observation = self.observe(clock)?;
+        // reject prices that can not be accumulated before touching the state
+        marginal_price.to_scaled_val()?;
+
+        self.price_cumulative = observation.price_cumulative;
+        self.last_update_timestamp = observation.timestamp;
+        self.last_update_slot = observation.slot;
+        self.last_price = marginal_price;
+        Ok(())
+    }
+
+    /// Time-weighted average price from `older` up to `clock`
+    pub fn twap(&self, older: &TwapObservation, clock: &Clock) -> Result<Decimal, ProgramError> {
+        self.observe(clock)?.twap_since(older)
+    }
+}
+
+const PRICE_ACCUMULATOR_LEN: usize = 48;
+impl Pack for PriceAccumulator {
+    const LEN: usize = PRICE_ACCUMULATOR_LEN;
+
+    /// Unpacks a byte buffer into a PriceAccumulator
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![src, 0, PRICE_ACCUMULATOR_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (price_cumulative, last_price, last_update_timestamp, last_update_slot) =
+            array_refs![input, 16, 16, 8, 8];
+
+        Ok(Self {
+            price_cumulative: u128::from_le_bytes(*price_cumulative),
+            last_price: unpack_decimal(last_price),
+            last_update_timestamp: i64::from_le_bytes(*last_update_timestamp),
+            last_update_slot: u64::from_le_bytes(*last_update_slot),
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, PRICE_ACCUMULATOR_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (price_cumulative, last_price, last_update_timestamp, last_update_slot) =
+            mut_array_refs![output, 16, 16, 8, 8];
+
+        *price_cumulative = self.price_cumulative.to_le_bytes();
+        pack_decimal(self.last_price, last_price);
+        *last_update_timestamp = self.last_update_timestamp.to_le_bytes();
+        *last_update_slot = self.last_update_slot.to_le_bytes();
+    }
+}
+
+impl Sealed for PriceAccumulator {}
+impl IsInitialized for PriceAccumulator {
+    fn is_initialized(&self) -> bool {
+        self.last_update_slot != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clock(slot: Slot, unix_timestamp: UnixTimestamp) -> Clock {
+        Clock {
+            slot,
+            unix_timestamp,
+            ..Clock::default()
+        }
+    }
+
+    #[test]
+    fn test_twap_weights_by_time() {
+        let mut accumulator = PriceAccumulator::default();
+        accumulator
+            .update(Decimal::from(3u64), &clock(10, 1_000))
+            .unwrap();
+        let start = accumulator.observe(&clock(10, 1_000)).unwrap();
+
+        // price 3 for 30 seconds, then 5 for 10 seconds
+        accumulator
+            .update(Decimal::from(5u64), &clock(85, 1_030))
+            .unwrap();
+        assert_eq!(
+            accumulator.twap(&start, &clock(110, 1_040)).unwrap(),
+            Decimal::from_scaled_val(3_500_000_000_000)
+        );
+
+        // a spike and revert within one timestamp carries no weight
+        accumulator
+            .update(Decimal::from(1_000u64), &clock(110, 1_040))
+            .unwrap();
+        accumulator
+            .update(Decimal::from(5u64), &clock(110, 1_040))
+            .unwrap();
+        assert_eq!(
+            accumulator.twap(&start, &clock(110, 1_040)).unwrap(),
+            Decimal::from_scaled_val(3_500_000_000_000)
+        );
+
+        assert_eq!(
+            start.twap_since(&start).unwrap_err(),
+            SwapError::InvalidInput.into()
+        );
+    }
+
+    #[test]
+    fn test_long_idle_gap() {
+        let mut accumulator = PriceAccumulator::default();
+        accumulator
+            .update(Decimal::from(2u64), &clock(0, 0))
+            .unwrap();
+        let start = accumulator.observe(&clock(0, 0)).unwrap();
+
+        // no swap for a year, the last price holds over the whole gap
+        let year = 365 * 24 * 60 * 60;
+        assert_eq!(
+            accumulator.twap(&start, &clock(80_000_000, year)).unwrap(),
+            Decimal::from(2u64)
+        );
+        accumulator
+            .update(Decimal::from(4u64), &clock(80_000_000, year))
+            .unwrap();
+        assert_eq!(
+            accumulator
+                .twap(&start, &clock(160_000_000, 2 * year))
+                .unwrap(),
+            Decimal::from(3u64)
+        );
+        // a timestamp going backwards adds no time
+        accumulator
+            .update(Decimal::from(4u64), &clock(160_000_000, 2 * year))
+            .unwrap();
+        let cumulative = accumulator.price_cumulative;
+        accumulator
+            .update(Decimal::from(4u64), &clock(160_000_001, 2 * year - 5))
+            .unwrap();
+        assert_eq!(accumulator.last_update_timestamp, 2 * year);
+        assert_eq!(accumulator.price_cumulative, cumulative);
+    }
+
+    #[test]
+    fn test_wrap_around() {
+        let mut accumulator = PriceAccumulator {
+            price_cumulative: u128::MAX - 5_000_000_000_000,
+            last_price: Decimal::from(7u64),
+            last_update_timestamp: 100,
+            last_update_slot: 200,
+        };
+        let start = accumulator.observe(&clock(200, 100)).unwrap();
+        accumulator
+            .update(Decimal::from(9u64), &clock(300, 140))
+            .unwrap();
+        assert!(accumulator.price_cumulative < start.price_cumulative);
+        assert_eq!(
+            accumulator.twap(&start, &clock(300, 140)).unwrap(),
+            Decimal::from(7u64)
+        );
+        assert_eq!(
+            accumulator.twap(&start, &clock(400, 180)).unwrap(),
+            Decimal::from(8u64)
+        );
+    }
+
+    #[test]
+    fn test_pack_accumulator() {
+        let accumulator = PriceAccumulator {
+            price_cumulative: u128::MAX - 1,
+            last_price: Decimal::from_scaled_val(3_141_592_653_589),
+            last_update_timestamp: -42,
+            last_update_slot: 123_456,
+        };
+        let mut packed = [0u8; PriceAccumulator::LEN];
+        PriceAccumulator::pack(accumulator.clone(), &mut packed).unwrap();
+        assert_eq!(PriceAccumulator::unpack(&packed).unwrap(), accumulator);
+
+        // an accumulator never updated is not initialized
+        assert!(!PriceAccumulator::default().is_initialized());
+        assert_eq!(
+            PriceAccumulator::unpack(&[0u8; PriceAccumulator::LEN]).unwrap_err(),
+            ProgramError::UninitializedAccount
+        );
+    }
+}
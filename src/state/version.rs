@@ -0,0 +1,86 @@
+//! Versioned account layouts and in-place migration
+
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    program::invoke,
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack},
+    rent::Rent,
+    system_instruction,
+    sysvar::Sysvar,
+};
+
+/// Layout of a state account at one version
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AccountLayout {
+    /// version stored in the first byte, zero for legacy layouts without a version byte
+    pub version: u8,
+    /// packed length
+    pub len: usize,
+}
+
+/// State account with a registry of its layouts
+pub trait VersionedAccount: Pack + IsInitialized {
+    /// Registered layouts in ascending version order, the last one is packed by `Pack`
+    const LAYOUTS: &'static [AccountLayout];
+
+    /// Unpack `src` stored in `layout`
+    fn unpack_layout(layout: &AccountLayout, src: &[u8]) -> Result<Self, ProgramError>;
+
+    /// Layout `Pack` writes
+    fn current_layout() -> AccountLayout {
+        Self::LAYOUTS[Self::LAYOUTS.len() - 1]
+    }
+
+    /// Registered layout of `src`, found by length and version byte
+    fn layout_of(src: &[u8]) -> Result<AccountLayout, ProgramError> {
+        Self::LAYOUTS
+            .iter()
+            .find(|layout| {
+                layout.len == src.len() && (layout.version == 0 || src[0] == layout.version)
+            })
+            .copied()
+            .ok_or(ProgramError::InvalidAccountData)
+    }
+
+    /// Unpack an initialized account stored in any registered layout
+    fn unpack_versioned(src: &[u8]) -> Result<Self, ProgramError> {
+        let value = Self::unpack_layout(&Self::layout_of(src)?, src)?;
+        if !value.is_initialized() {
+            return Err(ProgramError::UninitializedAccount);
+        }
+        Ok(value)
+    }
+}
+
+/// Re-encode an account stored in any registered layout in the current layout
+pub fn migrate_data<T: VersionedAccount>(src: &[u8]) -> Result<Vec<u8>, ProgramError> {
+    let value = T::unpack_versioned(src)?;
+    let mut dst = vec![0u8; T::LEN];
+    T::pack(value, &mut dst)?;
+    Ok(dst)
+}
+
+/// Upgrade `account` to the current layout in place.
+/// The account is resized to the current length, `payer` tops up the rent exemption.
+pub fn migrate_account<'a, T: VersionedAccount>(
+    account: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+) -> ProgramResult {
+    let migrated = migrate_data::<T>(&account.data.borrow())?;
+    if account.data_len() != migrated.len() {
+        let required = Rent::get()?.minimum_balance(migrated.len());
+        let top_up = required.saturating_sub(account.lamports());
+        if top_up > 0 {
+            invoke(
+                &system_instruction::transfer(payer.key, account.key, top_up),
+                &[payer.clone(), account.clone(), system_program.clone()],
+            )?;
+        }
+        account.realloc(migrated.len(), false)?;
+    }
+    account.data.borrow_mut()[..migrated.len()].copy_from_slice(&migrated);
+    Ok(())
+}
@@ -0,0 +1,444 @@
+//! Zero-copy views over large state accounts
+//!
+//! A view reads and writes single fields in place instead of unpacking the whole account
+//! and packing it back. Account data carries no alignment guarantee, so fields are copied
+//! out of byte arrays with `from_le_bytes` and never cast to references. Small accounts keep
+//! using `Pack`.
+
+use arrayref::{array_mut_ref, array_ref};
+
+use solana_program::{
+    clock::UnixTimestamp, program_error::ProgramError, program_pack::Pack, pubkey::Pubkey,
+};
+
+use crate::{
+    curve::{CurveType, Fees, PoolState, TokenDecimals},
+    error::SwapError,
+    math::Decimal,
+};
+
+use super::{
+    pack_bool, pack_decimal, unpack_account_type, unpack_bool, unpack_curve_type, unpack_decimal,
+    unpack_rate, AccountType, FarmInfo, PriceAccumulator, SwapInfo, FARM_VERSION,
+    SWAP_INFO_VERSION,
+};
+
+// offsets of the current swap info layout, see `SwapInfo::pack_into_slice`
+const VERSION_OFFSET: usize = 0;
+const ACCOUNT_TYPE_OFFSET: usize = 1;
+const IS_INITIALIZED_OFFSET: usize = 2;
+const IS_PAUSED_OFFSET: usize = 3;
+const BUMP_SEED_OFFSET: usize = 4;
+const TOKEN_A_DECIMALS_OFFSET: usize = 5;
+const TOKEN_B_DECIMALS_OFFSET: usize = 6;
+const TOKEN_A_OFFSET: usize = 39;
+const TOKEN_B_OFFSET: usize = 71;
+const POOL_MINT_OFFSET: usize = 167;
+const CURVE_TYPE_OFFSET: usize = 327;
+const POOL_STATE_OFFSET: usize = 360;
+const FEES_OFFSET: usize = 440;
+const PRICE_ACCUMULATOR_OFFSET: usize = 488;
+
+// offsets of the current farm info layout, see `FarmInfo::pack_into_slice`
+const FARM_BUMP_SEED_OFFSET: usize = 3;
+const FARM_CONFIG_KEY_OFFSET: usize = 4;
+const FARM_POOL_TOKEN_OFFSET: usize = 68;
+const FARM_REWARD_TOKEN_OFFSET: usize = 100;
+const FARM_ACC_REWARD_PER_SHARE_OFFSET: usize = 148;
+const FARM_LAST_UPDATE_TIMESTAMP_OFFSET: usize = 164;
+const FARM_TOTAL_STAKED_OFFSET: usize = 172;
+
+/// Check `data` holds an initialized account of `account_type` in the layout `version`
+fn check_account(
+    data: &[u8],
+    len: usize,
+    account_type: AccountType,
+    version: u8,
+) -> Result<(), ProgramError> {
+    if data.len() != len {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if unpack_account_type(array_ref![data, ACCOUNT_TYPE_OFFSET, 1])? != account_type {
+        return Err(SwapError::InvalidAccount.into());
+    }
+    if data[VERSION_OFFSET] != version {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if !unpack_bool(array_ref![data, IS_INITIALIZED_OFFSET, 1])? {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    Ok(())
+}
+
+/// Check `data` holds an initialized swap info in the current layout
+fn check_swap_info(data: &[u8]) -> Result<(), ProgramError> {
+    check_account(
+        data,
+        SwapInfo::LEN,
+        AccountType::SwapInfo,
+        SWAP_INFO_VERSION,
+    )
+}
+
+/// Check `data` holds an initialized farm info in the current layout
+fn check_farm_info(data: &[u8]) -> Result<(), ProgramError> {
+    check_account(data, FarmInfo::LEN, AccountType::FarmInfo, FARM_VERSION)
+}
+
+fn read_pubkey(data: &[u8], offset: usize) -> Pubkey {
+    Pubkey::new_from_array(*array_ref![data, offset, 32])
+}
+
+fn read_pool_state(data: &[u8]) -> PoolState {
+    let decimal =
+        |index: usize| unpack_decimal(array_ref![data, POOL_STATE_OFFSET + 16 * index, 16]);
+    PoolState {
+        market_price: decimal(0),
+        target_reserve_a: decimal(1),
+        target_reserve_b: decimal(2),
+        current_reserve_a: decimal(3),
+        current_reserve_b: decimal(4),
+    }
+}
+
+/// Read-only view over a swap info account
+#[derive(Clone, Copy, Debug)]
+pub struct SwapInfoView<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> SwapInfoView<'a> {
+    /// View over an initialized swap info in the current layout
+    pub fn new(data: &'a [u8]) -> Result<Self, ProgramError> {
+        check_swap_info(data)?;
+        Ok(Self { data })
+    }
+
+    /// Bump seed of the pool authority
+    pub fn bump_seed(&self) -> u8 {
+        self.data[BUMP_SEED_OFFSET]
+    }
+
+    /// Token a account owned by the pool authority
+    pub fn token_a(&self) -> Pubkey {
+        read_pubkey(self.data, TOKEN_A_OFFSET)
+    }
+
+    /// Token b account owned by the pool authority
+    pub fn token_b(&self) -> Pubkey {
+        read_pubkey(self.data, TOKEN_B_OFFSET)
+    }
+
+    /// Mint of the pool token
+    pub fn pool_mint(&self) -> Pubkey {
+        read_pubkey(self.data, POOL_MINT_OFFSET)
+    }
+
+    /// Curve pricing the pool
+    pub fn curve_type(&self) -> Result<CurveType, ProgramError> {
+        unpack_curve_type(
+            array_ref![self.data, CURVE_TYPE_OFFSET, 1],
+            array_ref![self.data, CURVE_TYPE_OFFSET + 1, 16],
+            array_ref![self.data, CURVE_TYPE_OFFSET + 17, 16],
+        )
+    }
+
+    /// Market price, target and current reserves
+    pub fn pool_state(&self) -> PoolState {
+        read_pool_state(self.data)
+    }
+
+    /// Fees charged by the pool
+    pub fn fees(&self) -> Fees {
+        Fees {
+            trade_fee: unpack_rate(array_ref![self.data, FEES_OFFSET, 16]),
+            admin_fee: unpack_rate(array_ref![self.data, FEES_OFFSET + 16, 16]),
+            referrer_fee: unpack_rate(array_ref![self.data, FEES_OFFSET + 32, 16]),
+        }
+    }
+
+    /// Cumulative price for the pool twap
+    pub fn price_accumulator(&self) -> Result<PriceAccumulator, ProgramError> {
+        PriceAccumulator::unpack_from_slice(array_ref![
+            self.data,
+            PRICE_ACCUMULATOR_OFFSET,
+            PriceAccumulator::LEN
+        ])
+    }
+
+    /// Whether swaps and deposits are paused
+    pub fn is_paused(&self) -> Result<bool, ProgramError> {
+        unpack_bool(array_ref![self.data, IS_PAUSED_OFFSET, 1])
+    }
+
+    /// Decimals of the pool mints
+    pub fn token_decimals(&self) -> Result<TokenDecimals, ProgramError> {
+        TokenDecimals::new(
+            self.data[TOKEN_A_DECIMALS_OFFSET],
+            self.data[TOKEN_B_DECIMALS_OFFSET],
+        )
+    }
+}
+
+/// Mutable view over a swap info account, writing the fields that change on every trade
+#[derive(Debug)]
+pub struct SwapInfoViewMut<'a> {
+    data: &'a mut [u8],
+}
+
+impl<'a> SwapInfoViewMut<'a> {
+    /// View over an initialized swap info in the current layout
+    pub fn new(data: &'a mut [u8]) -> Result<Self, ProgramError> {
+        check_swap_info(data)?;
+        Ok(Self { data })
+    }
+
+    /// Read-only view of the same account
+    pub fn as_view(&self) -> SwapInfoView<'_> {
+        SwapInfoView { data: self.data }
+    }
+
+    /// Write the market price, target and current reserves
+    pub fn set_pool_state(&mut self, pool_state: &PoolState) {
+        let decimals = [
+            pool_state.market_price,
+            pool_state.target_reserve_a,
+            pool_state.target_reserve_b,
+            pool_state.current_reserve_a,
+            pool_state.current_reserve_b,
+        ];
+        for (index, decimal) in decimals.iter().enumerate() {
+            pack_decimal(
+                *decimal,
+                array_mut_ref![self.data, POOL_STATE_OFFSET + 16 * index, 16],
+            );
+        }
+    }
+
+    /// Write the cumulative price for the pool twap
+    pub fn set_price_accumulator(&mut self, price_accumulator: &PriceAccumulator) {
+        price_accumulator.pack_into_slice(array_mut_ref![
+            self.data,
+            PRICE_ACCUMULATOR_OFFSET,
+            PriceAccumulator::LEN
+        ]);
+    }
+
+    /// Pause or resume swaps and deposits
+    pub fn set_is_paused(&mut self, is_paused: bool) {
+        pack_bool(is_paused, array_mut_ref![self.data, IS_PAUSED_OFFSET, 1]);
+    }
+}
+
+/// Read-only view over a farm info account
+#[derive(Clone, Copy, Debug)]
+pub struct FarmInfoView<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> FarmInfoView<'a> {
+    /// View over an initialized farm info in the current layout
+    pub fn new(data: &'a [u8]) -> Result<Self, ProgramError> {
+        check_farm_info(data)?;
+        Ok(Self { data })
+    }
+
+    /// Bump seed of the farm authority owning the token accounts
+    pub fn bump_seed(&self) -> u8 {
+        self.data[FARM_BUMP_SEED_OFFSET]
+    }
+
+    /// Config whose admin created the farm
+    pub fn config_key(&self) -> Pubkey {
+        read_pubkey(self.data, FARM_CONFIG_KEY_OFFSET)
+    }
+
+    /// Token account holding the staked pool tokens
+    pub fn pool_token(&self) -> Pubkey {
+        read_pubkey(self.data, FARM_POOL_TOKEN_OFFSET)
+    }
+
+    /// Token account holding the rewards
+    pub fn reward_token(&self) -> Pubkey {
+        read_pubkey(self.data, FARM_REWARD_TOKEN_OFFSET)
+    }
+
+    /// Rewards accumulated per staked token
+    pub fn acc_reward_per_share(&self) -> Decimal {
+        unpack_decimal(array_ref![self.data, FARM_ACC_REWARD_PER_SHARE_OFFSET, 16])
+    }
+
+    /// Unix timestamp rewards were accumulated up to
+    pub fn last_update_timestamp(&self) -> UnixTimestamp {
+        i64::from_le_bytes(*array_ref![self.data, FARM_LAST_UPDATE_TIMESTAMP_OFFSET, 8])
+    }
+
+    /// Pool tokens staked in the farm
+    pub fn total_staked(&self) -> u64 {
+        u64::from_le_bytes(*array_ref![self.data, FARM_TOTAL_STAKED_OFFSET, 8])
+    }
+}
+
+/// Mutable view over a farm info account, writing the fields that change with every stake
+#[derive(Debug)]
+pub struct FarmInfoViewMut<'a> {
+    data: &'a mut [u8],
+}
+
+impl<'a> FarmInfoViewMut<'a> {
+    /// View over an initialized farm info in the current layout
+    pub fn new(data: &'a mut [u8]) -> Result<Self, ProgramError> {
+        check_farm_info(data)?;
+        Ok(Self { data })
+    }
+
+    /// Read-only view of the same account
+    pub fn as_view(&self) -> FarmInfoView<'_> {
+        FarmInfoView { data: self.data }
+    }
+
+    /// Write the accumulated rewards and staked amount of `farm`
+    pub fn set_rewards(&mut self, farm: &FarmInfo) {
+        pack_decimal(
+            farm.acc_reward_per_share,
+            array_mut_ref![self.data, FARM_ACC_REWARD_PER_SHARE_OFFSET, 16],
+        );
+        *array_mut_ref![self.data, FARM_LAST_UPDATE_TIMESTAMP_OFFSET, 8] =
+            farm.last_update_timestamp.to_le_bytes();
+        *array_mut_ref![self.data, FARM_TOTAL_STAKED_OFFSET, 8] = farm.total_staked.to_le_bytes();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::test_swap_info;
+
+    #[test]
+    fn test_view_matches_unpack() {
+        let swap_info = test_swap_info();
+        let mut data = [0u8; SwapInfo::LEN];
+        swap_info.pack_into_slice(&mut data);
+
+        let view = SwapInfoView::new(&data).unwrap();
+        assert_eq!(view.bump_seed(), swap_info.bump_seed);
+        assert_eq!(view.token_a(), swap_info.token_a);
+        assert_eq!(view.token_b(), swap_info.token_b);
+        assert_eq!(view.pool_mint(), swap_info.pool_mint);
+        assert_eq!(view.curve_type().unwrap(), swap_info.curve_type);
+        assert_eq!(view.pool_state(), swap_info.pool_state);
+        assert_eq!(view.fees(), swap_info.fees);
+        assert_eq!(
+            view.price_accumulator().unwrap(),
+            swap_info.price_accumulator
+        );
+        assert_eq!(view.is_paused().unwrap(), swap_info.is_paused);
+        assert_eq!(
+            view.token_decimals().unwrap(),
+            swap_info.token_decimals().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_view_writes_in_place() {
+        let swap_info = test_swap_info();
+        // an odd offset into the buffer leaves the fields unaligned
+        let mut buffer = [0u8; SwapInfo::LEN + 1];
+        swap_info.pack_into_slice(&mut buffer[1..]);
+
+        let pool_state = PoolState {
+            current_reserve_a: Decimal::from(1_100_000u64),
+            current_reserve_b: Decimal::from(900_001u64),
+            ..swap_info.pool_state
+        };
+        let price_accumulator = PriceAccumulator {
+            price_cumulative: u128::MAX,
+            ..swap_info.price_accumulator.clone()
+        };
+        let mut view = SwapInfoViewMut::new(&mut buffer[1..]).unwrap();
+        view.set_pool_state(&pool_state);
+        view.set_price_accumulator(&price_accumulator);
+        view.set_is_paused(false);
+        assert_eq!(view.as_view().pool_state(), pool_state);
+
+        assert_eq!(
+            SwapInfo::unpack(&buffer[1..]).unwrap(),
+            SwapInfo {
+                is_paused: false,
+                pool_state,
+                price_accumulator,
+                ..swap_info
+            }
+        );
+    }
+
+    #[test]
+    fn test_view_checks_account() {
+        let mut data = [0u8; SwapInfo::LEN];
+        assert_eq!(
+            SwapInfoView::new(&data).unwrap_err(),
+            SwapError::InvalidAccount.into()
+        );
+        test_swap_info().pack_into_slice(&mut data);
+        assert_eq!(
+            SwapInfoView::new(&data[..SwapInfo::LEN - 1]).unwrap_err(),
+            ProgramError::InvalidAccountData
+        );
+
+        data[IS_INITIALIZED_OFFSET] = 0;
+        assert_eq!(
+            SwapInfoView::new(&data).unwrap_err(),
+            ProgramError::UninitializedAccount
+        );
+        data[VERSION_OFFSET] = SWAP_INFO_VERSION + 1;
+        assert_eq!(
+            SwapInfoViewMut::new(&mut data).unwrap_err(),
+            ProgramError::InvalidAccountData
+        );
+    }
+
+    #[test]
+    fn test_farm_view() {
+        let farm = FarmInfo {
+            version: FARM_VERSION,
+            is_initialized: true,
+            bump_seed: 253,
+            config_key: Pubkey::new_unique(),
+            pool_mint: Pubkey::new_unique(),
+            pool_token: Pubkey::new_unique(),
+            reward_token: Pubkey::new_unique(),
+            reward_per_second: 100,
+            min_claim_period: 60,
+            acc_reward_per_share: Decimal::from_scaled_val(1_234_567_890_123),
+            last_update_timestamp: 1_650_000_000,
+            total_staked: 4_000,
+        };
+        let mut buffer = [0u8; FarmInfo::LEN + 1];
+        farm.pack_into_slice(&mut buffer[1..]);
+
+        let view = FarmInfoView::new(&buffer[1..]).unwrap();
+        assert_eq!(view.bump_seed(), farm.bump_seed);
+        assert_eq!(view.config_key(), farm.config_key);
+        assert_eq!(view.pool_token(), farm.pool_token);
+        assert_eq!(view.reward_token(), farm.reward_token);
+        assert_eq!(view.acc_reward_per_share(), farm.acc_reward_per_share);
+        assert_eq!(view.last_update_timestamp(), farm.last_update_timestamp);
+        assert_eq!(view.total_staked(), farm.total_staked);
+
+        let mut accrued = farm.clone();
+        accrued.accrue(1_650_000_060).unwrap();
+        accrued.total_staked += 1_000;
+        let mut view = FarmInfoViewMut::new(&mut buffer[1..]).unwrap();
+        view.set_rewards(&accrued);
+        assert_eq!(view.as_view().total_staked(), 5_000);
+        assert_eq!(FarmInfo::unpack(&buffer[1..]).unwrap(), accrued);
+
+        // a swap info is not a farm
+        let mut data = [0u8; SwapInfo::LEN];
+        test_swap_info().pack_into_slice(&mut data);
+        assert_eq!(
+            FarmInfoView::new(&data[..FarmInfo::LEN]).unwrap_err(),
+            SwapError::InvalidAccount.into()
+        );
+    }
+}
@@ -0,0 +1,86 @@
+//! SPL token helpers
+
+use arrayref::{array_ref, array_refs};
+
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    program::invoke_signed,
+    program_error::ProgramError,
+    pubkey,
+    pubkey::Pubkey,
+};
+
+use crate::error::SwapError;
+
+/// SPL token program id
+pub const SPL_TOKEN_PROGRAM_ID: Pubkey = pubkey!("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
+
+/// Packed length of an SPL token account
+pub const TOKEN_ACCOUNT_LEN: usize = 165;
+
+/// Instruction tag of the SPL token transfer
+const TOKEN_TRANSFER_TAG: u8 = 3;
+
+/// Fields of an SPL token account read by the program
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TokenAccount {
+    /// mint of the tokens held
+    pub mint: Pubkey,
+    /// owner allowed to transfer the tokens
+    pub owner: Pubkey,
+    /// amount held
+    pub amount: u64,
+}
+
+/// Read the mint, owner and amount of an SPL token account
+pub fn unpack_token_account(account_info: &AccountInfo) -> Result<TokenAccount, ProgramError> {
+    if *account_info.owner != SPL_TOKEN_PROGRAM_ID {
+        return Err(SwapError::IncorrectTokenProgramId.into());
+    }
+    let data = account_info.data.borrow();
+    if data.len() != TOKEN_ACCOUNT_LEN {
+        return Err(SwapError::ExpectedAccount.into());
+    }
+    let input = array_ref![data, 0, 72];
+    #[allow(clippy::ptr_offset_with_cast)]
+    let (mint, owner, amount) = array_refs![input, 32, 32, 8];
+    Ok(TokenAccount {
+        mint: Pubkey::new_from_array(*mint),
+        owner: Pubkey::new_from_array(*owner),
+        amount: u64::from_le_bytes(*amount),
+    })
+}
+
+/// Transfer `amount` tokens from `source` to `destination`, signed by `authority`.
+/// `signer_seeds` signs for a program derived authority, empty when the authority signed.
+pub fn token_transfer<'a>(
+    token_program: AccountInfo<'a>,
+    source: AccountInfo<'a>,
+    destination: AccountInfo<'a>,
+    authority: AccountInfo<'a>,
+    amount: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    if *token_program.key != SPL_TOKEN_PROGRAM_ID {
+        return Err(SwapError::IncorrectTokenProgramId.into());
+    }
+    let mut data = vec![TOKEN_TRANSFER_TAG];
+    data.extend_from_slice(&amount.to_le_bytes());
+    let instruction = Instruction {
+        program_id: *token_program.key,
+        accounts: vec![
+            AccountMeta::new(*source.key, false),
+            AccountMeta::new(*destination.key, false),
+            AccountMeta::new_readonly(*authority.key, true),
+        ],
+        data,
+    };
+    invoke_signed(
+        &instruction,
+        &[source, destination, authority, token_program],
+        signer_seeds,
+    )
+    .map_err(|_| SwapError::TokenTransferFailed.into())
+}
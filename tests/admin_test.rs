@@ -0,0 +1,322 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use calculation_test::{
+    curve::Fees,
+    error::SwapError,
+    instruction::{
+        apply_new_admin, cancel_admin_transfer, commit_new_admin, initialize_config, set_paused,
+        InitializeConfigData,
+    },
+    processor::process,
+    state::{ConfigInfo, ADMIN_TRANSFER_DELAY, CONFIG_VERSION},
+};
+use solana_program_test::*;
+
+use solana_sdk::{
+    clock::Clock, instruction::InstructionError, signature::Keypair, signer::Signer,
+    transaction::TransactionError,
+};
+
+use solana_program::pubkey::Pubkey;
+
+use utils::{add_config, get_packed_account, process_instruction, AddPacked};
+
+fn swap_error(error: SwapError) -> TransactionError {
+    TransactionError::InstructionError(0, InstructionError::Custom(error as u32))
+}
+
+async fn setup() -> (ProgramTestContext, Pubkey, Keypair) {
+    let mut test = ProgramTest::new(
+        "calculation_test",
+        calculation_test::id(),
+        processor!(process),
+    );
+    let admin = Keypair::new();
+    let config_pubkey = add_config(&mut test, admin.pubkey());
+    (test.start_with_context().await, config_pubkey, admin)
+}
+
+async fn advance_clock(context: &mut ProgramTestContext, seconds: i64) {
+    let mut clock = context.banks_client.get_sysvar::<Clock>().await.unwrap();
+    clock.unix_timestamp += seconds;
+    context.set_sysvar(&clock);
+}
+
+#[tokio::test]
+async fn test_admin_transfer() {
+    let (mut context, config_pubkey, admin) = setup().await;
+    let new_admin = Keypair::new();
+    let program_id = calculation_test::id();
+
+    process_instruction(
+        &mut context,
+        commit_new_admin(
+            program_id,
+            config_pubkey,
+            admin.pubkey(),
+            new_admin.pubkey(),
+        )
+        .unwrap(),
+        &[&admin],
+    )
+    .await
+    .unwrap();
+    let config: ConfigInfo = get_packed_account(&mut context, config_pubkey).await;
+    assert_eq!(config.future_admin_key, new_admin.pubkey());
+
+    // a second proposal while the first one is pending
+    assert_eq!(
+        process_instruction(
+            &mut context,
+            commit_new_admin(
+                program_id,
+                config_pubkey,
+                admin.pubkey(),
+                Pubkey::new_unique()
+            )
+            .unwrap(),
+            &[&admin],
+        )
+        .await
+        .unwrap_err(),
+        swap_error(SwapError::ActiveTransfer)
+    );
+
+    // only the proposed admin can accept
+    let stranger = Keypair::new();
+    assert_eq!(
+        process_instruction(
+            &mut context,
+            apply_new_admin(program_id, config_pubkey, stranger.pubkey()).unwrap(),
+            &[&stranger],
+        )
+        .await
+        .unwrap_err(),
+        swap_error(SwapError::InvalidAdmin)
+    );
+
+    process_instruction(
+        &mut context,
+        apply_new_admin(program_id, config_pubkey, new_admin.pubkey()).unwrap(),
+        &[&new_admin],
+    )
+    .await
+    .unwrap();
+    let config: ConfigInfo = get_packed_account(&mut context, config_pubkey).await;
+    assert_eq!(config.admin_key, new_admin.pubkey());
+    assert!(!config.has_active_transfer());
+
+    // the previous admin lost its rights
+    assert_eq!(
+        process_instruction(
+            &mut context,
+            commit_new_admin(program_id, config_pubkey, admin.pubkey(), admin.pubkey()).unwrap(),
+            &[&admin],
+        )
+        .await
+        .unwrap_err(),
+        swap_error(SwapError::InvalidAdmin)
+    );
+}
+
+#[tokio::test]
+async fn test_admin_transfer_deadline() {
+    let (mut context, config_pubkey, admin) = setup().await;
+    let new_admin = Keypair::new();
+    let program_id = calculation_test::id();
+
+    process_instruction(
+        &mut context,
+        commit_new_admin(
+            program_id,
+            config_pubkey,
+            admin.pubkey(),
+            new_admin.pubkey(),
+        )
+        .unwrap(),
+        &[&admin],
+    )
+    .await
+    .unwrap();
+    advance_clock(&mut context, ADMIN_TRANSFER_DELAY + 1).await;
+
+    assert_eq!(
+        process_instruction(
+            &mut context,
+            apply_new_admin(program_id, config_pubkey, new_admin.pubkey()).unwrap(),
+            &[&new_admin],
+        )
+        .await
+        .unwrap_err(),
+        swap_error(SwapError::AdminDeadlineExceeded)
+    );
+
+    // an expired proposal can be replaced
+    process_instruction(
+        &mut context,
+        commit_new_admin(
+            program_id,
+            config_pubkey,
+            admin.pubkey(),
+            new_admin.pubkey(),
+        )
+        .unwrap(),
+        &[&admin],
+    )
+    .await
+    .unwrap();
+    process_instruction(
+        &mut context,
+        apply_new_admin(program_id, config_pubkey, new_admin.pubkey()).unwrap(),
+        &[&new_admin],
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn test_cancel_admin_transfer() {
+    let (mut context, config_pubkey, admin) = setup().await;
+    let new_admin = Keypair::new();
+    let program_id = calculation_test::id();
+
+    assert_eq!(
+        process_instruction(
+            &mut context,
+            cancel_admin_transfer(program_id, config_pubkey, admin.pubkey()).unwrap(),
+            &[&admin],
+        )
+        .await
+        .unwrap_err(),
+        swap_error(SwapError::NoActiveTransfer)
+    );
+
+    process_instruction(
+        &mut context,
+        commit_new_admin(
+            program_id,
+            config_pubkey,
+            admin.pubkey(),
+            new_admin.pubkey(),
+        )
+        .unwrap(),
+        &[&admin],
+    )
+    .await
+    .unwrap();
+    process_instruction(
+        &mut context,
+        cancel_admin_transfer(program_id, config_pubkey, admin.pubkey()).unwrap(),
+        &[&admin],
+    )
+    .await
+    .unwrap();
+    let config: ConfigInfo = get_packed_account(&mut context, config_pubkey).await;
+    assert!(!config.has_active_transfer());
+
+    assert_eq!(
+        process_instruction(
+            &mut context,
+            apply_new_admin(program_id, config_pubkey, new_admin.pubkey()).unwrap(),
+            &[&new_admin],
+        )
+        .await
+        .unwrap_err(),
+        swap_error(SwapError::NoActiveTransfer)
+    );
+}
+
+#[tokio::test]
+async fn test_initialize_config() {
+    let test = ProgramTest::new(
+        "calculation_test",
+        calculation_test::id(),
+        processor!(process),
+    );
+    let mut context = test.start_with_context().await;
+    let program_id = calculation_test::id();
+    let admin_key = context.payer.pubkey();
+    let config_data = || InitializeConfigData {
+        fees: Fees::default(),
+        pyth_program_id: Pubkey::new_unique(),
+        serum_program_id: Pubkey::new_unique(),
+    };
+
+    // the config only lives at the program address
+    let mut instruction = initialize_config(program_id, admin_key, config_data()).unwrap();
+    instruction.accounts[0].pubkey = Pubkey::new_unique();
+    assert_eq!(
+        process_instruction(&mut context, instruction, &[])
+            .await
+            .unwrap_err(),
+        swap_error(SwapError::InvalidProgramAddress)
+    );
+
+    process_instruction(
+        &mut context,
+        initialize_config(program_id, admin_key, config_data()).unwrap(),
+        &[],
+    )
+    .await
+    .unwrap();
+    let (config_pubkey, bump_seed) = ConfigInfo::find_address(&program_id);
+    let config: ConfigInfo = get_packed_account(&mut context, config_pubkey).await;
+    assert_eq!(config.admin_key, admin_key);
+    assert_eq!(config.bump_seed, bump_seed);
+
+    assert_eq!(
+        process_instruction(
+            &mut context,
+            initialize_config(program_id, admin_key, config_data()).unwrap(),
+            &[],
+        )
+        .await
+        .unwrap_err(),
+        swap_error(SwapError::AlreadyInUse)
+    );
+}
+
+#[tokio::test]
+async fn test_config_outside_program_address() {
+    let mut test = ProgramTest::new(
+        "calculation_test",
+        calculation_test::id(),
+        processor!(process),
+    );
+    let admin = Keypair::new();
+    add_config(&mut test, Pubkey::new_unique());
+    // a config written by anyone else is rejected even when owned by the program
+    let forged_config_pubkey = Pubkey::new_unique();
+    test.add_packable_account(
+        forged_config_pubkey,
+        u32::MAX as u64,
+        &ConfigInfo {
+            version: CONFIG_VERSION,
+            is_initialized: true,
+            bump_seed: ConfigInfo::find_address(&calculation_test::id()).1,
+            admin_key: admin.pubkey(),
+            ..ConfigInfo::default()
+        },
+        &calculation_test::id(),
+    );
+    let mut context = test.start_with_context().await;
+
+    assert_eq!(
+        process_instruction(
+            &mut context,
+            set_paused(
+                calculation_test::id(),
+                forged_config_pubkey,
+                admin.pubkey(),
+                true
+            )
+            .unwrap(),
+            &[&admin],
+        )
+        .await
+        .unwrap_err(),
+        swap_error(SwapError::InvalidProgramAddress)
+    );
+}
@@ -4,6 +4,8 @@ mod utils;
 
 use calculation_test::{
     // curve::calc::simple_powf, 
+    curve::{CurveType, PoolState},
+    math::Decimal,
     processor::process,
     state::MockedSwap,
     instruction::mock_swap,
@@ -12,72 +14,59 @@ use solana_program_test::*;
 // use solana_program::pubkey::Pubkey;
 
 use solana_sdk::{
-    account::Account,
     signature::Keypair,
     signer::Signer,
     transaction::Transaction,
 };
 
 use solana_program::{program_pack::Pack, pubkey::Pubkey};
+use std::convert::TryFrom;
 
-trait AddPacked {
-    fn add_packable_account<T: Pack>(
-        &mut self,
-        pubkey: Pubkey,
-        amount: u64,
-        data: &T,
-        owner: &Pubkey,
-    );
-}
-
-impl AddPacked for ProgramTest {
-    fn add_packable_account<T: Pack>(
-        &mut self,
-        pubkey: Pubkey,
-        amount: u64,
-        data: &T,
-        owner: &Pubkey,
-    ) {
-        let mut account = Account::new(amount, T::get_packed_len(), owner);
-        data.pack_into_slice(&mut account.data);
-        self.add_account(pubkey, account);
-    }
-}
+use utils::{add_config, add_mocked_swap, add_token_account};
 
 #[tokio::test]
 async fn test_powf() {
     let mut test = ProgramTest::new("calculation_test", calculation_test::id(), processor!(process));
 
-    let swap_config_keypair = Keypair::new();
-    let swap_config_pubkey = swap_config_keypair.pubkey();
-    test.add_packable_account(
-        swap_config_pubkey,
-        u32::MAX as u64,
-        &MockedSwap {
-            target_reserve_a: 100_001_423_523u64,
-            target_reserve_b: 2_005_232_345_234u64,
-            current_reserve_a: 2043u64,
-            current_reserve_b: 996u64,
-            market_price: 3u64,
-        },
-        &calculation_test::id()
-    );
+    let config_pubkey = add_config(&mut test, Pubkey::new_unique());
+    let mint_a = Pubkey::new_unique();
+    let mint_b = Pubkey::new_unique();
+    let initial_swap = MockedSwap {
+        config_key: config_pubkey,
+        target_reserve_a: Decimal::from(100_001_423_523u64),
+        target_reserve_b: Decimal::from(2_005_232_345_234u64),
+        current_reserve_a: Decimal::from(2043u64),
+        current_reserve_b: Decimal::from(996u64),
+        market_price: Decimal::from(3u64),
+        ..MockedSwap::default()
+    };
+    let (swap_config_pubkey, pool_token_a, pool_token_b) =
+        add_mocked_swap(&mut test, initial_swap.clone(), mint_a, mint_b);
 
     // limit to track compute unit increase
     test.set_bpf_compute_max_units(30_000);
+    let user = Keypair::new();
+    let source = add_token_account(&mut test, mint_a, user.pubkey(), 13);
+    let destination = add_token_account(&mut test, mint_b, user.pubkey(), 0);
     let (mut banks_client, payer, recent_blockhash) = test.start().await;
 
     let mut transaction = Transaction::new_with_payer(
         &[mock_swap(
             calculation_test::id(),
             swap_config_pubkey,
-            payer.pubkey()
+            user.pubkey(),
+            config_pubkey,
+            None,
+            source,
+            destination,
+            pool_token_a,
+            pool_token_b,
         )
         .unwrap()],
         Some(&payer.pubkey()),
     );
     
-    transaction.sign(&[&payer], recent_blockhash);
+    transaction.sign(&[&payer, &user], recent_blockhash);
 
     banks_client
         .process_transaction(transaction)
@@ -85,6 +74,27 @@ async fn test_powf() {
         .map_err(|e| e.unwrap())
         .unwrap();
 
+    // the swap records the marginal price it left the pool at
+    let account = banks_client
+        .get_account(swap_config_pubkey)
+        .await
+        .unwrap()
+        .unwrap();
+    let mocked_swap = MockedSwap::unpack(&account.data).unwrap();
+    assert_eq!(mocked_swap.current_reserve_a, Decimal::from(2056u64));
+    assert_ne!(
+        mocked_swap.price_accumulator.last_price,
+        CurveType::Oracle
+            .marginal_price(&PoolState::try_from(&initial_swap).unwrap())
+            .unwrap()
+    );
+    let pool = PoolState::try_from(&mocked_swap).unwrap();
+    assert_eq!(
+        mocked_swap.price_accumulator.last_price,
+        CurveType::Oracle.marginal_price(&pool).unwrap()
+    );
+    assert!(mocked_swap.price_accumulator.last_update_slot > 0);
+
     // let result = simple_powf(2u64, 1001u64, 523u64, 2043u64, 996u64, 13u64);
     // println!("{}", result);
     // msg!("result {}", result);
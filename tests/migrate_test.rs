@@ -0,0 +1,168 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use calculation_test::{
+    error::SwapError,
+    instruction::migrate_account,
+    math::Decimal,
+    processor::process,
+    state::{ConfigInfo, MockedSwap, CONFIG_VERSION, MOCKED_SWAP_VERSION},
+};
+use solana_program_test::*;
+
+use solana_sdk::{
+    account::Account, instruction::InstructionError, signature::Keypair, signer::Signer,
+    transaction::TransactionError,
+};
+
+use solana_program::pubkey::Pubkey;
+
+use utils::{add_config, get_packed_account, process_instruction, AddPacked};
+
+#[tokio::test]
+async fn test_migrate_legacy_mocked_swap() {
+    let mut test = ProgramTest::new(
+        "calculation_test",
+        calculation_test::id(),
+        processor!(process),
+    );
+    let admin = Keypair::new();
+    let config_pubkey = add_config(&mut test, admin.pubkey());
+    // a config made by a stranger outside the config address
+    let stranger = Keypair::new();
+    let stranger_config_pubkey = Pubkey::new_unique();
+    test.add_packable_account(
+        stranger_config_pubkey,
+        u32::MAX as u64,
+        &ConfigInfo {
+            version: CONFIG_VERSION,
+            is_initialized: true,
+            bump_seed: ConfigInfo::find_address(&calculation_test::id()).1,
+            admin_key: stranger.pubkey(),
+            ..ConfigInfo::default()
+        },
+        &calculation_test::id(),
+    );
+
+    // the legacy layout held the fields as u64s without version byte and discriminator
+    let legacy: Vec<u8> = [1_000_000u64, 3_000_000, 1_050_000, 2_900_000, 3]
+        .iter()
+        .flat_map(|field| field.to_le_bytes().to_vec())
+        .collect();
+    let mocked_swap_pubkey = Pubkey::new_unique();
+    test.add_account(
+        mocked_swap_pubkey,
+        Account {
+            lamports: u32::MAX as u64,
+            data: legacy,
+            owner: calculation_test::id(),
+            ..Account::default()
+        },
+    );
+    // zeroed accounts have no registered layout
+    let zeroed_pubkey = Pubkey::new_unique();
+    test.add_account(
+        zeroed_pubkey,
+        Account::new(u32::MAX as u64, 64, &calculation_test::id()),
+    );
+
+    let mut context = test.start_with_context().await;
+    let program_id = calculation_test::id();
+    let payer = context.payer.pubkey();
+
+    // only the admin of the program config can migrate
+    assert_eq!(
+        process_instruction(
+            &mut context,
+            migrate_account(
+                program_id,
+                mocked_swap_pubkey,
+                payer,
+                config_pubkey,
+                stranger.pubkey()
+            )
+            .unwrap(),
+            &[&stranger],
+        )
+        .await
+        .unwrap_err(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(SwapError::InvalidAdmin as u32)
+        )
+    );
+    assert_eq!(
+        process_instruction(
+            &mut context,
+            migrate_account(
+                program_id,
+                mocked_swap_pubkey,
+                payer,
+                stranger_config_pubkey,
+                stranger.pubkey()
+            )
+            .unwrap(),
+            &[&stranger],
+        )
+        .await
+        .unwrap_err(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(SwapError::InvalidProgramAddress as u32)
+        )
+    );
+
+    process_instruction(
+        &mut context,
+        migrate_account(
+            program_id,
+            mocked_swap_pubkey,
+            payer,
+            config_pubkey,
+            admin.pubkey(),
+        )
+        .unwrap(),
+        &[&admin],
+    )
+    .await
+    .unwrap();
+    let mocked_swap: MockedSwap = get_packed_account(&mut context, mocked_swap_pubkey).await;
+    assert_eq!(
+        mocked_swap,
+        MockedSwap {
+            config_key: config_pubkey,
+            target_reserve_a: Decimal::from(1_000_000u64),
+            target_reserve_b: Decimal::from(3_000_000u64),
+            current_reserve_a: Decimal::from(1_050_000u64),
+            current_reserve_b: Decimal::from(2_900_000u64),
+            market_price: Decimal::from(3u64),
+            ..MockedSwap::default()
+        }
+    );
+    let account = context
+        .banks_client
+        .get_account(mocked_swap_pubkey)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(account.data[0], MOCKED_SWAP_VERSION);
+
+    assert_eq!(
+        process_instruction(
+            &mut context,
+            migrate_account(
+                program_id,
+                zeroed_pubkey,
+                payer,
+                config_pubkey,
+                admin.pubkey()
+            )
+            .unwrap(),
+            &[&admin],
+        )
+        .await
+        .unwrap_err(),
+        TransactionError::InstructionError(0, InstructionError::InvalidAccountData)
+    );
+}
@@ -0,0 +1,337 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use calculation_test::{
+    curve::Fees,
+    error::SwapError,
+    instruction::{mock_swap, set_referrer},
+    math::{Decimal, Rate},
+    processor::process,
+    state::{ConfigInfo, MockedSwap, UserReferrerData, CONFIG_VERSION},
+};
+use solana_program_test::*;
+
+use solana_sdk::{
+    account::Account,
+    instruction::{Instruction, InstructionError},
+    signature::Keypair,
+    signer::Signer,
+    transaction::TransactionError,
+};
+
+use solana_program::{pubkey::Pubkey, system_program};
+
+use utils::{
+    add_config, add_mocked_swap, add_token_account, get_packed_account, get_token_balance,
+    process_instruction, AddPacked,
+};
+
+fn swap_error(error: SwapError) -> TransactionError {
+    TransactionError::InstructionError(0, InstructionError::Custom(error as u32))
+}
+
+/// Mocked swap with its token accounts
+struct TestPool {
+    mocked_swap: Pubkey,
+    token_a: Pubkey,
+    token_b: Pubkey,
+}
+
+/// Signer selling token a of `source` for token b paid to `destination`
+struct Trader {
+    keypair: Keypair,
+    source: Pubkey,
+    destination: Pubkey,
+}
+
+/// 1_000 whole token a against 3_000 token b of 6 decimals, the swap sells 13 token a
+fn add_pool(
+    test: &mut ProgramTest,
+    config_pubkey: Pubkey,
+    mint_a: Pubkey,
+    mint_b: Pubkey,
+) -> TestPool {
+    let (mocked_swap, token_a, token_b) = add_mocked_swap(
+        test,
+        MockedSwap {
+            config_key: config_pubkey,
+            target_reserve_a: Decimal::from(1_000u64),
+            target_reserve_b: Decimal::from(3_000_000_000u64),
+            current_reserve_a: Decimal::from(1_000u64),
+            current_reserve_b: Decimal::from(3_000_000_000u64),
+            market_price: Decimal::from(3u64),
+            token_a_decimals: 0,
+            token_b_decimals: 6,
+            ..MockedSwap::default()
+        },
+        mint_a,
+        mint_b,
+    );
+    TestPool {
+        mocked_swap,
+        token_a,
+        token_b,
+    }
+}
+
+fn add_trader(test: &mut ProgramTest, mint_a: Pubkey, mint_b: Pubkey) -> Trader {
+    let keypair = Keypair::new();
+    // traders pay the rent of their registration
+    test.add_account(
+        keypair.pubkey(),
+        Account::new(1_000_000_000, 0, &system_program::id()),
+    );
+    Trader {
+        source: add_token_account(test, mint_a, keypair.pubkey(), 100),
+        destination: add_token_account(test, mint_b, keypair.pubkey(), 0),
+        keypair,
+    }
+}
+
+fn swap(
+    pool: &TestPool,
+    trader: &Trader,
+    config_pubkey: Pubkey,
+    referrer: Option<Pubkey>,
+) -> Instruction {
+    mock_swap(
+        calculation_test::id(),
+        pool.mocked_swap,
+        trader.keypair.pubkey(),
+        config_pubkey,
+        referrer,
+        trader.source,
+        trader.destination,
+        pool.token_a,
+        pool.token_b,
+    )
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_referrer_fee_routing() {
+    let mut test = ProgramTest::new(
+        "calculation_test",
+        calculation_test::id(),
+        processor!(process),
+    );
+    let program_id = calculation_test::id();
+    let config_pubkey = add_config(&mut test, Pubkey::new_unique());
+    let mint_a = Pubkey::new_unique();
+    let mint_b = Pubkey::new_unique();
+    let pool = add_pool(&mut test, config_pubkey, mint_a, mint_b);
+    let user = add_trader(&mut test, mint_a, mint_b);
+    let stranger = add_trader(&mut test, mint_a, mint_b);
+    let referrer = add_token_account(&mut test, mint_b, Pubkey::new_unique(), 0);
+    let other_mint_referrer =
+        add_token_account(&mut test, Pubkey::new_unique(), Pubkey::new_unique(), 0);
+    let mut context = test.start_with_context().await;
+
+    // users without a registration swap with the dummy referrer only
+    process_instruction(
+        &mut context,
+        swap(&pool, &user, config_pubkey, None),
+        &[&user.keypair],
+    )
+    .await
+    .unwrap();
+    assert_eq!(get_token_balance(&mut context, user.source).await, 87);
+    assert_eq!(get_token_balance(&mut context, pool.token_a).await, 1_013);
+    let amount_out = get_token_balance(&mut context, user.destination).await;
+    assert!(amount_out > 0);
+    assert_eq!(
+        get_token_balance(&mut context, pool.token_b).await,
+        3_000_000_000 - amount_out
+    );
+    assert_eq!(
+        process_instruction(
+            &mut context,
+            swap(&pool, &user, config_pubkey, Some(referrer)),
+            &[&user.keypair],
+        )
+        .await
+        .unwrap_err(),
+        swap_error(SwapError::InvalidReferrer)
+    );
+
+    process_instruction(
+        &mut context,
+        set_referrer(program_id, config_pubkey, user.keypair.pubkey(), referrer).unwrap(),
+        &[&user.keypair],
+    )
+    .await
+    .unwrap();
+    let (user_referrer_pubkey, _) =
+        UserReferrerData::find_address(&program_id, &config_pubkey, &user.keypair.pubkey());
+    let user_referrer: UserReferrerData =
+        get_packed_account(&mut context, user_referrer_pubkey).await;
+    assert_eq!(user_referrer.referrer, referrer);
+    assert_eq!(
+        process_instruction(
+            &mut context,
+            set_referrer(program_id, config_pubkey, user.keypair.pubkey(), referrer).unwrap(),
+            &[&user.keypair],
+        )
+        .await
+        .unwrap_err(),
+        swap_error(SwapError::AlreadyInUse)
+    );
+
+    // a registered user can not leave the referrer out
+    assert_eq!(
+        process_instruction(
+            &mut context,
+            swap(&pool, &user, config_pubkey, None),
+            &[&user.keypair],
+        )
+        .await
+        .unwrap_err(),
+        swap_error(SwapError::InvalidReferrer)
+    );
+
+    let pool_token_b = get_token_balance(&mut context, pool.token_b).await;
+    process_instruction(
+        &mut context,
+        swap(&pool, &user, config_pubkey, Some(referrer)),
+        &[&user.keypair],
+    )
+    .await
+    .unwrap();
+    let referrer_fee = get_token_balance(&mut context, referrer).await;
+    assert!(referrer_fee > 0);
+    let amount_out = get_token_balance(&mut context, user.destination).await - amount_out;
+    assert_eq!(
+        get_token_balance(&mut context, pool.token_b).await,
+        pool_token_b - amount_out - referrer_fee
+    );
+    let mocked_swap: MockedSwap = get_packed_account(&mut context, pool.mocked_swap).await;
+    assert_eq!(mocked_swap.current_reserve_a, Decimal::from(1_026u64));
+
+    // the referrer is paid in the output token
+    process_instruction(
+        &mut context,
+        set_referrer(
+            program_id,
+            config_pubkey,
+            stranger.keypair.pubkey(),
+            other_mint_referrer,
+        )
+        .unwrap(),
+        &[&stranger.keypair],
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        process_instruction(
+            &mut context,
+            swap(&pool, &stranger, config_pubkey, Some(other_mint_referrer)),
+            &[&stranger.keypair],
+        )
+        .await
+        .unwrap_err(),
+        swap_error(SwapError::IncorrectMint)
+    );
+}
+
+#[tokio::test]
+async fn test_caller_accounts_cannot_move_pool_funds() {
+    let mut test = ProgramTest::new(
+        "calculation_test",
+        calculation_test::id(),
+        processor!(process),
+    );
+    let program_id = calculation_test::id();
+    let config_pubkey = add_config(&mut test, Pubkey::new_unique());
+    let mint_a = Pubkey::new_unique();
+    let mint_b = Pubkey::new_unique();
+    let pool = add_pool(&mut test, config_pubkey, mint_a, mint_b);
+    let other_pool = add_pool(&mut test, config_pubkey, mint_a, mint_b);
+    let attacker = add_trader(&mut test, mint_a, mint_b);
+    let referrer = add_token_account(&mut test, mint_b, Pubkey::new_unique(), 0);
+
+    // a config of the attacker handing the whole output to the referrer
+    let forged_config_pubkey = Pubkey::new_unique();
+    test.add_packable_account(
+        forged_config_pubkey,
+        u32::MAX as u64,
+        &ConfigInfo {
+            version: CONFIG_VERSION,
+            is_initialized: true,
+            bump_seed: ConfigInfo::find_address(&program_id).1,
+            admin_key: attacker.keypair.pubkey(),
+            fees: Fees {
+                trade_fee: Rate::one(),
+                admin_fee: Rate::zero(),
+                referrer_fee: Rate::one(),
+            },
+            ..ConfigInfo::default()
+        },
+        &program_id,
+    );
+    let mut context = test.start_with_context().await;
+
+    assert_eq!(
+        process_instruction(
+            &mut context,
+            set_referrer(
+                program_id,
+                forged_config_pubkey,
+                attacker.keypair.pubkey(),
+                referrer
+            )
+            .unwrap(),
+            &[&attacker.keypair],
+        )
+        .await
+        .unwrap_err(),
+        swap_error(SwapError::InvalidProgramAddress)
+    );
+    assert_eq!(
+        process_instruction(
+            &mut context,
+            swap(&pool, &attacker, forged_config_pubkey, None),
+            &[&attacker.keypair],
+        )
+        .await
+        .unwrap_err(),
+        swap_error(SwapError::InvalidProgramAddress)
+    );
+
+    // the token accounts of another pool share the mints but not the authority
+    for token_accounts in [
+        (other_pool.token_a, pool.token_b),
+        (pool.token_a, other_pool.token_b),
+    ]
+    .iter()
+    {
+        let substituted = TestPool {
+            mocked_swap: pool.mocked_swap,
+            token_a: token_accounts.0,
+            token_b: token_accounts.1,
+        };
+        assert_eq!(
+            process_instruction(
+                &mut context,
+                swap(&substituted, &attacker, config_pubkey, None),
+                &[&attacker.keypair],
+            )
+            .await
+            .unwrap_err(),
+            swap_error(SwapError::IncorrectSwapAccount)
+        );
+    }
+
+    for pool in [&pool, &other_pool].iter() {
+        assert_eq!(get_token_balance(&mut context, pool.token_a).await, 1_000);
+        assert_eq!(
+            get_token_balance(&mut context, pool.token_b).await,
+            3_000_000_000
+        );
+    }
+    assert_eq!(
+        get_token_balance(&mut context, attacker.destination).await,
+        0
+    );
+    assert_eq!(get_token_balance(&mut context, referrer).await, 0);
+}
@@ -0,0 +1,172 @@
+#![allow(dead_code)]
+
+use arrayref::array_ref;
+use calculation_test::{
+    curve::Fees,
+    state::{ConfigInfo, MockedSwap, CONFIG_VERSION},
+    utils::{SPL_TOKEN_PROGRAM_ID, TOKEN_ACCOUNT_LEN},
+};
+use solana_program_test::*;
+
+use solana_sdk::{
+    account::Account,
+    instruction::Instruction,
+    signature::Keypair,
+    signer::Signer,
+    transaction::{Transaction, TransactionError},
+};
+
+use solana_program::{
+    program_pack::{IsInitialized, Pack},
+    pubkey::Pubkey,
+};
+
+pub trait AddPacked {
+    fn add_packable_account<T: Pack>(
+        &mut self,
+        pubkey: Pubkey,
+        amount: u64,
+        data: &T,
+        owner: &Pubkey,
+    );
+}
+
+impl AddPacked for ProgramTest {
+    fn add_packable_account<T: Pack>(
+        &mut self,
+        pubkey: Pubkey,
+        amount: u64,
+        data: &T,
+        owner: &Pubkey,
+    ) {
+        let mut account = Account::new(amount, T::get_packed_len(), owner);
+        data.pack_into_slice(&mut account.data);
+        self.add_account(pubkey, account);
+    }
+}
+
+/// Add the initialized config of the program administrated by `admin_key`
+pub fn add_config(test: &mut ProgramTest, admin_key: Pubkey) -> Pubkey {
+    let (config_pubkey, bump_seed) = ConfigInfo::find_address(&calculation_test::id());
+    test.add_packable_account(
+        config_pubkey,
+        u32::MAX as u64,
+        &ConfigInfo {
+            version: CONFIG_VERSION,
+            is_initialized: true,
+            bump_seed,
+            admin_key,
+            fees: Fees::default(),
+            ..ConfigInfo::default()
+        },
+        &calculation_test::id(),
+    );
+    config_pubkey
+}
+
+/// Add an SPL token account holding `amount` of `mint` owned by `owner`
+pub fn add_token_account(
+    test: &mut ProgramTest,
+    mint: Pubkey,
+    owner: Pubkey,
+    amount: u64,
+) -> Pubkey {
+    let token_account_pubkey = Pubkey::new_unique();
+    let mut data = vec![0u8; TOKEN_ACCOUNT_LEN];
+    data[..32].copy_from_slice(mint.as_ref());
+    data[32..64].copy_from_slice(owner.as_ref());
+    data[64..72].copy_from_slice(&amount.to_le_bytes());
+    // account state initialized
+    data[108] = 1;
+    test.add_account(
+        token_account_pubkey,
+        Account {
+            lamports: u32::MAX as u64,
+            data,
+            owner: SPL_TOKEN_PROGRAM_ID,
+            ..Account::default()
+        },
+    );
+    token_account_pubkey
+}
+
+/// Add `mocked_swap` with token accounts of `mint_a` and `mint_b` holding its current
+/// reserves, returns the mocked swap and its token a and token b accounts
+pub fn add_mocked_swap(
+    test: &mut ProgramTest,
+    mocked_swap: MockedSwap,
+    mint_a: Pubkey,
+    mint_b: Pubkey,
+) -> (Pubkey, Pubkey, Pubkey) {
+    let mocked_swap_pubkey = Pubkey::new_unique();
+    let (authority, _) = MockedSwap::find_authority(&calculation_test::id(), &mocked_swap_pubkey);
+    let token_a = add_token_account(
+        test,
+        mint_a,
+        authority,
+        mocked_swap.current_reserve_a.try_floor_u64().unwrap(),
+    );
+    let token_b = add_token_account(
+        test,
+        mint_b,
+        authority,
+        mocked_swap.current_reserve_b.try_floor_u64().unwrap(),
+    );
+    test.add_packable_account(
+        mocked_swap_pubkey,
+        u32::MAX as u64,
+        &MockedSwap {
+            token_a,
+            token_b,
+            ..mocked_swap
+        },
+        &calculation_test::id(),
+    );
+    (mocked_swap_pubkey, token_a, token_b)
+}
+
+/// Amount held by an SPL token account
+pub async fn get_token_balance(context: &mut ProgramTestContext, pubkey: Pubkey) -> u64 {
+    let account = context
+        .banks_client
+        .get_account(pubkey)
+        .await
+        .unwrap()
+        .unwrap();
+    u64::from_le_bytes(*array_ref![account.data, 64, 8])
+}
+
+/// Send `instruction` in a transaction of its own, paid by the context payer
+pub async fn process_instruction(
+    context: &mut ProgramTestContext,
+    instruction: Instruction,
+    signers: &[&Keypair],
+) -> Result<(), TransactionError> {
+    // a fresh blockhash keeps repeated instructions from being deduplicated
+    let recent_blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let mut transaction =
+        Transaction::new_with_payer(&[instruction], Some(&context.payer.pubkey()));
+    let mut all_signers = vec![&context.payer];
+    all_signers.extend_from_slice(signers);
+    transaction.sign(&all_signers, recent_blockhash);
+
+    context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .map_err(|e| e.unwrap())
+}
+
+/// Read and unpack a packed account
+pub async fn get_packed_account<T: Pack + IsInitialized>(
+    context: &mut ProgramTestContext,
+    pubkey: Pubkey,
+) -> T {
+    let account = context
+        .banks_client
+        .get_account(pubkey)
+        .await
+        .unwrap()
+        .unwrap();
+    T::unpack(&account.data).unwrap()
+}
@@ -0,0 +1,66 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use calculation_test::{
+    instruction::update_twap,
+    math::Decimal,
+    processor::process,
+    state::{test_swap_info, SwapInfo, SwapInfoViewMut},
+};
+use solana_program_test::*;
+
+use solana_program::{
+    program_pack::{IsInitialized, Pack},
+    pubkey::Pubkey,
+};
+
+use utils::{get_packed_account, AddPacked};
+
+#[test]
+fn test_zero_copy_matches_pack() {
+    // both leave the same account behind
+    let mut packed_data = [0u8; SwapInfo::LEN];
+    test_swap_info().pack_into_slice(&mut packed_data);
+    let mut zero_copy_data = packed_data;
+    let mut swap_info = SwapInfo::unpack(&packed_data).unwrap();
+    swap_info.price_accumulator.last_price = Decimal::from(7u64);
+    SwapInfo::pack(swap_info.clone(), &mut packed_data).unwrap();
+    let mut view = SwapInfoViewMut::new(&mut zero_copy_data).unwrap();
+    view.set_price_accumulator(&swap_info.price_accumulator);
+    assert_eq!(packed_data, zero_copy_data);
+}
+
+#[tokio::test]
+async fn test_update_twap_zero_copy() {
+    let mut test = ProgramTest::new(
+        "calculation_test",
+        calculation_test::id(),
+        processor!(process),
+    );
+    let swap_info_pubkey = Pubkey::new_unique();
+    test.add_packable_account(
+        swap_info_pubkey,
+        u32::MAX as u64,
+        &test_swap_info(),
+        &calculation_test::id(),
+    );
+    let mut context = test.start_with_context().await;
+
+    utils::process_instruction(
+        &mut context,
+        update_twap(calculation_test::id(), swap_info_pubkey).unwrap(),
+        &[],
+    )
+    .await
+    .unwrap();
+    let swap_info: SwapInfo = get_packed_account(&mut context, swap_info_pubkey).await;
+    let pool = swap_info
+        .token_decimals()
+        .unwrap()
+        .normalize_reserves(&swap_info.pool_state)
+        .unwrap();
+    let expected_price = swap_info.curve_type.marginal_price(&pool).unwrap();
+    assert_eq!(swap_info.price_accumulator.last_price, expected_price);
+    assert!(swap_info.price_accumulator.is_initialized());
+}